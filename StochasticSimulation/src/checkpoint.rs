@@ -0,0 +1,607 @@
+//! Checkpoints of running simulations, to resume runs that were stopped or killed.
+//!
+//! A checkpoint holds the species quantities, the simulated time and step count, the state
+//! the visitor keeps between steps, such as the firing times scheduled by the next reaction
+//! method, and optionally what a monitor has recorded. Resuming from it continues the run
+//! exactly as it would have gone on.
+//!
+//! The generator is stored by its seed, stream and position in the stream, so taking a
+//! checkpoint does not change the run: it takes the same course whether it is checkpointed,
+//! resumed or neither.
+//!
+//! Files are a small binary format, with floats stored bit for bit. What the monitor of
+//! `ChemicalSystem::simulate_with_checkpoints` recorded goes to a journal next to the
+//! checkpoint file, named like it with `.monitor` added, to which every checkpoint adds only
+//! what was recorded since the one before.
+
+use std::fmt;
+use std::fs::{self, File, OpenOptions};
+use std::io::{self, Seek, SeekFrom, Write};
+use std::path::{Path, PathBuf};
+use rand::SeedableRng;
+use rand_chacha::ChaCha12Rng;
+use crate::model::Model;
+use crate::monitor::FilterableMonitor;
+use crate::system::{Algorithm, ChemicalSystem};
+
+const MAGIC: &[u8; 8] = b"SSIMCKPT";
+const VERSION: u32 = 2;
+
+#[derive(Debug)]
+pub enum CheckpointError {
+    Io(io::Error),
+    NotACheckpoint,
+    UnsupportedVersion(u32),
+    /// The data ends before everything was read.
+    Truncated,
+    Corrupt(String),
+    /// The checkpoint was taken from a run of a different reaction network.
+    WrongModel,
+    /// The recordings of the monitor are in a journal that was not read with the checkpoint.
+    MissingJournal
+}
+
+impl fmt::Display for CheckpointError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            CheckpointError::Io(error) => write!(f, "could not access checkpoint: {}", error),
+            CheckpointError::NotACheckpoint => write!(f, "not a checkpoint file"),
+            CheckpointError::UnsupportedVersion(version) => write!(f, "unsupported checkpoint version {}", version),
+            CheckpointError::Truncated => write!(f, "checkpoint ends unexpectedly"),
+            CheckpointError::Corrupt(reason) => write!(f, "corrupt checkpoint: {}", reason),
+            CheckpointError::WrongModel => write!(f, "checkpoint is of a different reaction network"),
+            CheckpointError::MissingJournal => write!(f, "monitor journal of the checkpoint was not read")
+        }
+    }
+}
+
+impl std::error::Error for CheckpointError {}
+
+impl From<io::Error> for CheckpointError {
+    fn from(error: io::Error) -> Self {
+        CheckpointError::Io(error)
+    }
+}
+
+/// The state of a run between two steps. Made by `Simulation::checkpoint`, and taken up
+/// again by `Simulation::resume` or `ChemicalSystem::resume`.
+#[derive(Clone, Debug)]
+pub struct Checkpoint {
+    pub(crate) algorithm: Algorithm,
+    pub(crate) species: Vec<String>,
+    pub(crate) formulas: Vec<String>,
+    pub(crate) lambdas: Vec<f64>,
+    pub(crate) quantities: Vec<i32>,
+    pub(crate) time: f64,
+    pub(crate) steps: u64,
+    pub(crate) rng: ChaCha12Rng,
+    pub(crate) visitor: Vec<u8>,
+    pub(crate) monitor: Option<Vec<u8>>,
+    // Length of the monitor journal up to this checkpoint, and its parts once they are read
+    pub(crate) journal_length: Option<u64>,
+    pub(crate) journal: Option<Vec<Vec<u8>>>,
+    // File the checkpoint was read from
+    pub(crate) source: Option<PathBuf>
+}
+
+impl Checkpoint {
+    /// Reads a checkpoint written by `write`, and the monitor journal next to it if it has one.
+    pub fn read(path: impl AsRef<Path>) -> Result<Self, CheckpointError> {
+        let path = path.as_ref();
+        let mut checkpoint = Self::from_bytes(&fs::read(path)?)?;
+
+        if let Some(length) = checkpoint.journal_length {
+            let bytes = fs::read(journal_path(path))?;
+            let length = usize::try_from(length).map_err(|_| CheckpointError::Truncated)?;
+
+            // Parts added after this checkpoint was written are not part of it
+            let mut reader = StateReader::new(bytes.get(..length).ok_or(CheckpointError::Truncated)?);
+            let mut parts = Vec::new();
+
+            while !reader.is_empty() {
+                parts.push(reader.read_bytes()?.to_vec());
+            }
+
+            checkpoint.journal = Some(parts);
+        }
+
+        checkpoint.source = Some(path.to_path_buf());
+
+        Ok(checkpoint)
+    }
+
+    /// Writes the checkpoint to `path`, along with its monitor journal if it was read with
+    /// one. Files are written to a temporary file next to them first and then renamed, so a
+    /// run killed while writing leaves the previous checkpoint intact.
+    pub fn write(&self, path: impl AsRef<Path>) -> Result<(), CheckpointError> {
+        let path = path.as_ref();
+
+        if let Some(parts) = &self.journal {
+            let mut journal = StateWriter::new();
+            parts.iter().for_each(|part| journal.write_bytes(part));
+
+            write_replacing(&journal_path(path), &journal.bytes)?;
+        }
+
+        write_replacing(path, &self.to_bytes())
+    }
+
+    pub fn from_bytes(bytes: &[u8]) -> Result<Self, CheckpointError> {
+        if !bytes.starts_with(MAGIC) {
+            return Err(CheckpointError::NotACheckpoint);
+        }
+
+        let mut reader = StateReader::new(&bytes[MAGIC.len()..]);
+
+        let version = reader.read_u32()?;
+        if version != VERSION {
+            return Err(CheckpointError::UnsupportedVersion(version));
+        }
+
+        let algorithm = match reader.read_u32()? {
+            0 => Algorithm::FirstReaction,
+            1 => Algorithm::Direct,
+            2 => Algorithm::NextReaction,
+            3 => Algorithm::TauLeaping,
+            other => return Err(CheckpointError::Corrupt(format!("unknown algorithm {}", other)))
+        };
+
+        let species = reader.read_strings()?;
+        let formulas = reader.read_strings()?;
+        let lambdas = reader.read_f64s()?;
+        let quantities = reader.read_i32s()?;
+        let time = reader.read_f64()?;
+        let steps = reader.read_u64()?;
+        let mut rng = ChaCha12Rng::from_seed(reader.take(32)?.try_into().unwrap());
+        rng.set_stream(reader.read_u64()?);
+        rng.set_word_pos(u128::from_le_bytes(reader.take(16)?.try_into().unwrap()));
+        let visitor = reader.read_bytes()?.to_vec();
+        let monitor = if reader.read_bool()? { Some(reader.read_bytes()?.to_vec()) } else { None };
+        let journal_length = if reader.read_bool()? { Some(reader.read_u64()?) } else { None };
+
+        reader.finish()?;
+
+        if quantities.len() != species.len() || lambdas.len() != formulas.len() {
+            return Err(CheckpointError::Corrupt("species or reactions do not line up".to_string()));
+        }
+
+        Ok(Checkpoint {
+            algorithm,
+            species,
+            formulas,
+            lambdas,
+            quantities,
+            time,
+            steps,
+            rng,
+            visitor,
+            monitor,
+            journal_length,
+            journal: None,
+            source: None
+        })
+    }
+
+    pub fn to_bytes(&self) -> Vec<u8> {
+        let mut writer = StateWriter::new();
+
+        writer.bytes.extend_from_slice(MAGIC);
+        writer.write_u32(VERSION);
+        writer.write_u32(match self.algorithm {
+            Algorithm::FirstReaction => 0,
+            Algorithm::Direct => 1,
+            Algorithm::NextReaction => 2,
+            Algorithm::TauLeaping => 3
+        });
+        writer.write_strings(&self.species);
+        writer.write_strings(&self.formulas);
+        writer.write_f64s(&self.lambdas);
+        writer.write_i32s(&self.quantities);
+        writer.write_f64(self.time);
+        writer.write_u64(self.steps);
+        writer.bytes.extend_from_slice(&self.rng.get_seed());
+        writer.write_u64(self.rng.get_stream());
+        writer.bytes.extend_from_slice(&self.rng.get_word_pos().to_le_bytes());
+        writer.write_bytes(&self.visitor);
+        writer.write_bool(self.monitor.is_some());
+
+        if let Some(monitor) = &self.monitor {
+            writer.write_bytes(monitor);
+        }
+
+        writer.write_bool(self.journal_length.is_some());
+
+        if let Some(length) = self.journal_length {
+            writer.write_u64(length);
+        }
+
+        writer.bytes
+    }
+
+    /// The algorithm of the checkpointed system, which `ChemicalSystem::resume` continues with.
+    pub fn algorithm(&self) -> Algorithm {
+        self.algorithm
+    }
+
+    pub fn time(&self) -> f64 {
+        self.time
+    }
+
+    pub fn steps(&self) -> u64 {
+        self.steps
+    }
+
+    /// Quantity of every species, indexed like `Model::species`.
+    pub fn quantities(&self) -> &[i32] {
+        &self.quantities
+    }
+
+    /// The generator the run continues with, where it was in its stream.
+    pub fn rng(&self) -> ChaCha12Rng {
+        self.rng.clone()
+    }
+
+    /// Stores what `monitor` has recorded so far, replacing any monitor stored before.
+    pub fn save_monitor(&mut self, monitor: &(impl ResumableMonitor + ?Sized)) {
+        let mut writer = StateWriter::new();
+
+        monitor.save_state(&mut writer);
+        self.monitor = Some(writer.bytes);
+    }
+
+    /// Restores the recordings stored by `save_monitor`, or in the monitor journal, into
+    /// `monitor`, which should be of the same type and made with the same options. Does
+    /// nothing if none were stored.
+    pub fn restore_monitor(&self, monitor: &mut (impl ResumableMonitor + ?Sized), system: &ChemicalSystem) -> Result<(), CheckpointError> {
+        let journal = match (self.journal_length, &self.journal) {
+            (Some(_), None) => return Err(CheckpointError::MissingJournal),
+            (_, journal) => journal.iter().flatten()
+        };
+
+        for bytes in self.monitor.iter().chain(journal) {
+            let mut reader = StateReader::new(bytes);
+
+            monitor.restore_state(&mut reader, system)?;
+            reader.finish()?;
+        }
+
+        Ok(())
+    }
+
+    pub(crate) fn is_of(&self, model: &Model) -> bool {
+        self.species == model.species
+            && self.formulas == model.formulas
+            && self.lambdas.iter().map(|lambda| lambda.to_bits()).eq(model.lambdas.iter().map(|lambda| lambda.to_bits()))
+    }
+}
+
+/// When `ChemicalSystem::simulate_with_checkpoints` writes a checkpoint. Every checkpoint
+/// overwrites the previous one at `path`.
+#[derive(Clone, Debug)]
+pub struct CheckpointOptions {
+    pub path: PathBuf,
+    /// Writes a checkpoint after every this many steps.
+    pub every_steps: Option<u64>,
+    /// Writes a checkpoint after the first step past every multiple of this simulated time.
+    pub every_time: Option<f64>
+}
+
+impl CheckpointOptions {
+    pub fn new(path: impl Into<PathBuf>) -> Self {
+        CheckpointOptions {
+            path: path.into(),
+            every_steps: Some(1_000_000),
+            every_time: None
+        }
+    }
+
+    // Whether a step from `previous_time` to the current state is followed by a checkpoint
+    pub(crate) fn is_due(&self, previous_time: f64, time: f64, steps: u64) -> bool {
+        let by_steps = self.every_steps.is_some_and(|every| every > 0 && steps.is_multiple_of(every));
+        let by_time = self.every_time.is_some_and(|every| (time / every).floor() > (previous_time / every).floor());
+
+        by_steps || by_time
+    }
+}
+
+/// A monitor whose recordings can be stored in a checkpoint, so that a resumed run records
+/// the same as an uninterrupted one.
+pub trait ResumableMonitor: FilterableMonitor<ChemicalSystem> {
+    fn save_state(&self, state: &mut StateWriter);
+
+    /// Writes what was recorded since the last call, or everything if `from_start`, for the
+    /// monitor journal of a checkpointed run. By default everything is written every time,
+    /// which suits monitors that keep little.
+    fn save_changes(&mut self, state: &mut StateWriter, _from_start: bool) {
+        self.save_state(state);
+    }
+
+    /// Replaces the recordings with those `save_state` wrote during a run of `system`, or adds
+    /// those that `save_changes` wrote since.
+    fn restore_state(&mut self, state: &mut StateReader, system: &ChemicalSystem) -> Result<(), CheckpointError>;
+}
+
+impl<A: ResumableMonitor, B: ResumableMonitor> ResumableMonitor for (A, B) {
+    fn save_state(&self, state: &mut StateWriter) {
+        self.0.save_state(state);
+        self.1.save_state(state);
+    }
+
+    fn save_changes(&mut self, state: &mut StateWriter, from_start: bool) {
+        self.0.save_changes(state, from_start);
+        self.1.save_changes(state, from_start);
+    }
+
+    fn restore_state(&mut self, state: &mut StateReader, system: &ChemicalSystem) -> Result<(), CheckpointError> {
+        self.0.restore_state(state, system)?;
+        self.1.restore_state(state, system)
+    }
+}
+
+// The monitor journal of a checkpointed run, a part per checkpoint holding what was recorded
+// since the checkpoint before. It is opened at the first checkpoint of the run.
+pub(crate) struct MonitorJournal {
+    path: PathBuf,
+    file: Option<File>,
+    length: u64
+}
+
+impl MonitorJournal {
+    // A journal for checkpoints at `checkpoints.path`, going on from `resumed` if it was read
+    // from there, and started again otherwise
+    pub(crate) fn new(checkpoints: &CheckpointOptions, resumed: Option<&Checkpoint>) -> Self {
+        let length = resumed
+            .filter(|checkpoint| checkpoint.source.as_deref() == Some(checkpoints.path.as_path()))
+            .and_then(|checkpoint| checkpoint.journal_length)
+            .unwrap_or(0);
+
+        MonitorJournal {
+            path: journal_path(&checkpoints.path),
+            file: None,
+            length
+        }
+    }
+
+    // Adds what `monitor` recorded since the last part, returning the length of the journal
+    pub(crate) fn append(&mut self, monitor: &mut (impl ResumableMonitor + ?Sized)) -> Result<u64, CheckpointError> {
+        let from_start = self.length == 0;
+        let file = match &mut self.file {
+            Some(file) => file,
+            None => {
+                let mut file = OpenOptions::new().write(true).create(from_start).truncate(false).open(&self.path)?;
+
+                if file.metadata()?.len() < self.length {
+                    return Err(CheckpointError::Truncated);
+                }
+
+                // Parts written after the checkpoint that is gone on from are dropped
+                file.set_len(self.length)?;
+                file.seek(SeekFrom::End(0))?;
+
+                self.file.insert(file)
+            }
+        };
+
+        let mut part = StateWriter::new();
+        monitor.save_changes(&mut part, from_start);
+
+        let mut framed = StateWriter::new();
+        framed.write_bytes(&part.bytes);
+
+        file.write_all(&framed.bytes)?;
+        self.length += framed.bytes.len() as u64;
+
+        Ok(self.length)
+    }
+}
+
+fn journal_path(path: &Path) -> PathBuf {
+    let mut journal = path.as_os_str().to_owned();
+    journal.push(".monitor");
+
+    PathBuf::from(journal)
+}
+
+// Writes to a temporary file next to `path` first, then renames it to `path`
+fn write_replacing(path: &Path, bytes: &[u8]) -> Result<(), CheckpointError> {
+    let mut temporary = path.as_os_str().to_owned();
+    temporary.push(".tmp");
+
+    fs::write(&temporary, bytes)?;
+    fs::rename(&temporary, path)?;
+
+    Ok(())
+}
+
+/// Collects the state of a visitor or monitor for a checkpoint, in little endian.
+pub struct StateWriter {
+    bytes: Vec<u8>
+}
+
+impl StateWriter {
+    pub(crate) fn new() -> Self {
+        StateWriter { bytes: Vec::new() }
+    }
+
+    pub(crate) fn into_bytes(self) -> Vec<u8> {
+        self.bytes
+    }
+
+    pub fn write_bool(&mut self, value: bool) {
+        self.bytes.push(value as u8);
+    }
+
+    pub fn write_u32(&mut self, value: u32) {
+        self.bytes.extend_from_slice(&value.to_le_bytes());
+    }
+
+    pub fn write_u64(&mut self, value: u64) {
+        self.bytes.extend_from_slice(&value.to_le_bytes());
+    }
+
+    pub fn write_usize(&mut self, value: usize) {
+        self.write_u64(value as u64);
+    }
+
+    pub fn write_i32(&mut self, value: i32) {
+        self.bytes.extend_from_slice(&value.to_le_bytes());
+    }
+
+    pub fn write_f64(&mut self, value: f64) {
+        self.bytes.extend_from_slice(&value.to_le_bytes());
+    }
+
+    pub fn write_str(&mut self, value: &str) {
+        self.write_bytes(value.as_bytes());
+    }
+
+    pub fn write_bytes(&mut self, value: &[u8]) {
+        self.write_usize(value.len());
+        self.bytes.extend_from_slice(value);
+    }
+
+    pub fn write_i32s(&mut self, values: &[i32]) {
+        self.write_usize(values.len());
+        values.iter().for_each(|&value| self.write_i32(value));
+    }
+
+    pub fn write_u64s(&mut self, values: &[u64]) {
+        self.write_usize(values.len());
+        values.iter().for_each(|&value| self.write_u64(value));
+    }
+
+    pub fn write_f64s(&mut self, values: &[f64]) {
+        self.write_usize(values.len());
+        values.iter().for_each(|&value| self.write_f64(value));
+    }
+
+    pub fn write_strings(&mut self, values: &[String]) {
+        self.write_usize(values.len());
+        values.iter().for_each(|value| self.write_str(value));
+    }
+}
+
+/// Reads back what a `StateWriter` wrote, in the same order.
+pub struct StateReader<'a> {
+    bytes: &'a [u8]
+}
+
+impl<'a> StateReader<'a> {
+    pub(crate) fn new(bytes: &'a [u8]) -> Self {
+        StateReader { bytes }
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.bytes.is_empty()
+    }
+
+    // Fails if anything is left unread, which means it was written by something else
+    pub(crate) fn finish(&self) -> Result<(), CheckpointError> {
+        if self.bytes.is_empty() {
+            Ok(())
+        } else {
+            Err(CheckpointError::Corrupt(format!("{} bytes left unread", self.bytes.len())))
+        }
+    }
+
+    fn take(&mut self, count: usize) -> Result<&'a [u8], CheckpointError> {
+        if self.bytes.len() < count {
+            return Err(CheckpointError::Truncated);
+        }
+
+        let (taken, rest) = self.bytes.split_at(count);
+        self.bytes = rest;
+
+        Ok(taken)
+    }
+
+    pub fn read_bool(&mut self) -> Result<bool, CheckpointError> {
+        match self.take(1)?[0] {
+            0 => Ok(false),
+            1 => Ok(true),
+            other => Err(CheckpointError::Corrupt(format!("invalid flag {}", other)))
+        }
+    }
+
+    pub fn read_u32(&mut self) -> Result<u32, CheckpointError> {
+        Ok(u32::from_le_bytes(self.take(4)?.try_into().unwrap()))
+    }
+
+    pub fn read_u64(&mut self) -> Result<u64, CheckpointError> {
+        Ok(u64::from_le_bytes(self.take(8)?.try_into().unwrap()))
+    }
+
+    pub fn read_usize(&mut self) -> Result<usize, CheckpointError> {
+        let value = self.read_u64()?;
+
+        usize::try_from(value).map_err(|_| CheckpointError::Corrupt(format!("length {} too large", value)))
+    }
+
+    pub fn read_i32(&mut self) -> Result<i32, CheckpointError> {
+        Ok(i32::from_le_bytes(self.take(4)?.try_into().unwrap()))
+    }
+
+    pub fn read_f64(&mut self) -> Result<f64, CheckpointError> {
+        Ok(f64::from_le_bytes(self.take(8)?.try_into().unwrap()))
+    }
+
+    pub fn read_str(&mut self) -> Result<String, CheckpointError> {
+        let bytes = self.read_bytes()?;
+
+        String::from_utf8(bytes.to_vec()).map_err(|_| CheckpointError::Corrupt("invalid UTF-8 in string".to_string()))
+    }
+
+    pub fn read_bytes(&mut self) -> Result<&'a [u8], CheckpointError> {
+        let length = self.read_usize()?;
+
+        self.take(length)
+    }
+
+    pub fn read_i32s(&mut self) -> Result<Vec<i32>, CheckpointError> {
+        let length = self.read_length(4)?;
+
+        (0..length).map(|_| self.read_i32()).collect()
+    }
+
+    pub fn read_u64s(&mut self) -> Result<Vec<u64>, CheckpointError> {
+        let length = self.read_length(8)?;
+
+        (0..length).map(|_| self.read_u64()).collect()
+    }
+
+    pub fn read_f64s(&mut self) -> Result<Vec<f64>, CheckpointError> {
+        let length = self.read_length(8)?;
+
+        (0..length).map(|_| self.read_f64()).collect()
+    }
+
+    pub fn read_strings(&mut self) -> Result<Vec<String>, CheckpointError> {
+        let length = self.read_length(8)?;
+
+        (0..length).map(|_| self.read_str()).collect()
+    }
+
+    /// Reads a tag written with `write_str`, failing if it is not `expected`. Visitors and
+    /// monitors start their state with one, so state is not restored into the wrong kind.
+    pub fn expect_tag(&mut self, expected: &str) -> Result<(), CheckpointError> {
+        let tag = self.read_str()?;
+
+        if tag == expected {
+            Ok(())
+        } else {
+            Err(CheckpointError::Corrupt(format!("expected {} state, found {}", expected, tag)))
+        }
+    }
+
+    // Length of a sequence of items at least `item_size` bytes long, checked against the
+    // bytes left so a corrupt length cannot allocate without bound
+    fn read_length(&mut self, item_size: usize) -> Result<usize, CheckpointError> {
+        let length = self.read_usize()?;
+
+        if length.saturating_mul(item_size) > self.bytes.len() {
+            return Err(CheckpointError::Truncated);
+        }
+
+        Ok(length)
+    }
+}
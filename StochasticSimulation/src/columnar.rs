@@ -0,0 +1,304 @@
+//! Arrow IPC and Parquet export of trajectories, behind the `arrow` feature.
+//!
+//! Files have a `time` column of f64, a `replicate` column of u32 and an i32 column per
+//! species, one row per recorded state like the wide CSV layout. Rows are buffered and
+//! written as a record batch, and in Parquet as a row group of its own, every
+//! `ColumnarOptions::row_group_size` rows, so a file grows during a run instead of being
+//! built in memory.
+
+use std::fmt;
+use std::fs::File;
+use std::io::{self, BufWriter, Write};
+use std::path::Path;
+use std::sync::Arc;
+use arrow_array::{ArrayRef, Float64Array, Int32Array, RecordBatch, UInt32Array};
+use arrow_ipc::writer::FileWriter;
+use arrow_schema::{ArrowError, DataType, Field, Schema, SchemaRef};
+use parquet::arrow::ArrowWriter;
+use parquet::basic::Compression;
+use parquet::errors::ParquetError;
+use parquet::file::properties::WriterProperties;
+use crate::model::Model;
+use crate::monitor::{FilterableMonitor, Monitor};
+use crate::reaction::SpeciesRole;
+use crate::sampling::SamplingMonitor;
+use crate::system::ChemicalSystem;
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum ColumnarFormat {
+    ArrowIpc,
+    Parquet
+}
+
+#[derive(Clone, Debug)]
+pub struct ColumnarOptions {
+    pub format: ColumnarFormat,
+    /// Rows buffered before they are written out.
+    pub row_group_size: usize,
+    /// Codec of Parquet column chunks. Snappy is the only one built in besides
+    /// `UNCOMPRESSED`. Arrow IPC files are not compressed.
+    pub compression: Compression
+}
+
+impl Default for ColumnarOptions {
+    fn default() -> Self {
+        ColumnarOptions {
+            format: ColumnarFormat::Parquet,
+            row_group_size: 65536,
+            compression: Compression::SNAPPY
+        }
+    }
+}
+
+#[derive(Debug)]
+pub enum ColumnarError {
+    Io(io::Error),
+    Arrow(ArrowError),
+    Parquet(ParquetError),
+    /// A species the file has a column for that the recorded system or monitor lacks.
+    MissingSpecies(String),
+    WrongQuantityCount { expected: usize, found: usize }
+}
+
+impl fmt::Display for ColumnarError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            ColumnarError::Io(error) => write!(f, "could not write file: {}", error),
+            ColumnarError::Arrow(error) => write!(f, "could not write Arrow data: {}", error),
+            ColumnarError::Parquet(error) => write!(f, "could not write Parquet data: {}", error),
+            ColumnarError::MissingSpecies(species) => write!(f, "no quantity of species `{}` to write", species),
+            ColumnarError::WrongQuantityCount { expected, found } =>
+                write!(f, "got {} quantities for {} species columns", found, expected)
+        }
+    }
+}
+
+impl std::error::Error for ColumnarError {}
+
+impl From<io::Error> for ColumnarError {
+    fn from(error: io::Error) -> Self {
+        ColumnarError::Io(error)
+    }
+}
+
+impl From<ArrowError> for ColumnarError {
+    fn from(error: ArrowError) -> Self {
+        ColumnarError::Arrow(error)
+    }
+}
+
+impl From<ParquetError> for ColumnarError {
+    fn from(error: ParquetError) -> Self {
+        ColumnarError::Parquet(error)
+    }
+}
+
+enum Sink<W: Write + Send> {
+    Ipc(FileWriter<W>),
+    Parquet(ArrowWriter<W>)
+}
+
+/// Writes trajectories with one column per species, fixed when the writer is made.
+///
+/// Like `csv::CsvWriter` it is a monitor as well, recording into the replicate set with
+/// `set_replicate`, and keeps the first error for `finish`. Every species column is
+/// recorded whatever filter the simulation passes.
+pub struct ColumnarWriter<W: Write + Send> {
+    sink: Sink<W>,
+    schema: SchemaRef,
+    species: Vec<String>,
+    row_group_size: usize,
+    times: Vec<f64>,
+    replicates: Vec<u32>,
+    // Indexed by species, then buffered row
+    quantities: Vec<Vec<i32>>,
+    // Index in the recorded model of every species column, worked out once per model
+    model_columns: Option<(Arc<Model>, Vec<usize>)>,
+    replicate: u32,
+    error: Option<ColumnarError>
+}
+
+impl ColumnarWriter<BufWriter<File>> {
+    pub fn create(path: impl AsRef<Path>, species: &[String], options: &ColumnarOptions) -> Result<Self, ColumnarError> {
+        Self::new(BufWriter::new(File::create(path)?), species, options)
+    }
+}
+
+impl<W: Write + Send> ColumnarWriter<W> {
+    pub fn new(writer: W, species: &[String], options: &ColumnarOptions) -> Result<Self, ColumnarError> {
+        let fields: Vec<Field> = [
+            Field::new("time", DataType::Float64, false),
+            Field::new("replicate", DataType::UInt32, false)
+        ].into_iter()
+            .chain(species.iter().map(|name| Field::new(name, DataType::Int32, false)))
+            .collect();
+        let schema = Arc::new(Schema::new(fields));
+        let row_group_size = options.row_group_size.max(1);
+
+        let sink = match options.format {
+            ColumnarFormat::ArrowIpc => Sink::Ipc(FileWriter::try_new(writer, &schema)?),
+            ColumnarFormat::Parquet => {
+                let properties = WriterProperties::builder()
+                    .set_max_row_group_size(row_group_size)
+                    .set_compression(options.compression)
+                    .build();
+
+                Sink::Parquet(ArrowWriter::try_new(writer, Arc::clone(&schema), Some(properties))?)
+            }
+        };
+
+        Ok(ColumnarWriter {
+            sink,
+            schema,
+            species: species.to_vec(),
+            row_group_size,
+            times: Vec::with_capacity(row_group_size),
+            replicates: Vec::with_capacity(row_group_size),
+            quantities: vec![Vec::with_capacity(row_group_size); species.len()],
+            model_columns: None,
+            replicate: 0,
+            error: None
+        })
+    }
+
+    pub fn species(&self) -> &[String] {
+        &self.species
+    }
+
+    /// The replicate that recorded states are written for.
+    pub fn set_replicate(&mut self, replicate: u32) {
+        self.replicate = replicate;
+    }
+
+    /// Adds a row with the quantities of every species column, in order.
+    pub fn write_row(&mut self, time: f64, replicate: u32, quantities: &[i32]) -> Result<(), ColumnarError> {
+        if quantities.len() != self.species.len() {
+            return Err(ColumnarError::WrongQuantityCount { expected: self.species.len(), found: quantities.len() });
+        }
+
+        self.times.push(time);
+        self.replicates.push(replicate);
+
+        for (column, &quantity) in self.quantities.iter_mut().zip(quantities) {
+            column.push(quantity);
+        }
+
+        if self.times.len() >= self.row_group_size {
+            self.flush()?;
+        }
+
+        Ok(())
+    }
+
+    /// Writes the grid samples of one replicate, which has to have sampled every species column.
+    pub fn write_samples(&mut self, replicate: u32, monitor: &SamplingMonitor) -> Result<(), ColumnarError> {
+        let columns = self.columns(monitor.species())?;
+        let mut row = vec![0; columns.len()];
+
+        for (&time, samples) in monitor.times().iter().zip(monitor.samples()) {
+            for (value, &column) in row.iter_mut().zip(&columns) {
+                *value = samples[column];
+            }
+
+            self.write_row(time, replicate, &row)?;
+        }
+
+        Ok(())
+    }
+
+    /// Writes the buffered rows out, as a row group of their own in Parquet.
+    pub fn flush(&mut self) -> Result<(), ColumnarError> {
+        if self.times.is_empty() {
+            return Ok(());
+        }
+
+        let columns: Vec<ArrayRef> = [
+            Arc::new(Float64Array::from(std::mem::take(&mut self.times))) as ArrayRef,
+            Arc::new(UInt32Array::from(std::mem::take(&mut self.replicates)))
+        ].into_iter()
+            .chain(self.quantities.iter_mut().map(|column| Arc::new(Int32Array::from(std::mem::take(column))) as ArrayRef))
+            .collect();
+        let batch = RecordBatch::try_new(Arc::clone(&self.schema), columns)?;
+
+        match &mut self.sink {
+            Sink::Ipc(writer) => writer.write(&batch)?,
+            Sink::Parquet(writer) => {
+                writer.write(&batch)?;
+                writer.flush()?;
+            }
+        }
+
+        Ok(())
+    }
+
+    /// Writes the remaining rows and the file footer, and returns the output or the first
+    /// error met while recording.
+    pub fn finish(mut self) -> Result<W, ColumnarError> {
+        if let Some(error) = self.error.take() {
+            return Err(error);
+        }
+
+        self.flush()?;
+
+        let mut writer = match self.sink {
+            Sink::Ipc(writer) => writer.into_inner()?,
+            // Writes the footer too
+            Sink::Parquet(writer) => writer.into_inner()?
+        };
+
+        writer.flush()?;
+
+        Ok(writer)
+    }
+
+    // Position of every species column among `species`
+    fn columns(&self, species: &[String]) -> Result<Vec<usize>, ColumnarError> {
+        self.species.iter()
+            .map(|name| species.iter().position(|species| species == name).ok_or_else(|| ColumnarError::MissingSpecies(name.clone())))
+            .collect()
+    }
+
+    fn record(&mut self, time: f64, system: &ChemicalSystem) {
+        if self.error.is_some() {
+            return;
+        }
+
+        if !self.model_columns.as_ref().is_some_and(|(model, _)| Arc::ptr_eq(model, &system.model)) {
+            match self.columns(&system.model.species) {
+                Ok(columns) => self.model_columns = Some((Arc::clone(&system.model), columns)),
+                Err(error) => {
+                    self.error = Some(error);
+                    return;
+                }
+            }
+        }
+
+        let (_, columns) = self.model_columns.as_ref().unwrap();
+
+        self.times.push(time);
+        self.replicates.push(self.replicate);
+
+        for (column, &index) in self.quantities.iter_mut().zip(columns) {
+            column.push(system.quantities[index]);
+        }
+
+        if self.times.len() >= self.row_group_size {
+            if let Err(error) = self.flush() {
+                self.error = Some(error);
+            }
+        }
+    }
+}
+
+impl<W: Write + Send> Monitor<ChemicalSystem> for ColumnarWriter<W> {
+    fn record_state(&mut self, time: f64, system: &ChemicalSystem) {
+        self.record(time, system);
+    }
+}
+
+impl<W: Write + Send> FilterableMonitor<ChemicalSystem> for ColumnarWriter<W> {
+    /// The columns were chosen when the writer was made, so the filter is not used.
+    fn record_state_with_filter(&mut self, time: f64, system: &ChemicalSystem, _species_to_record: &[(&str, SpeciesRole)]) {
+        self.record(time, system);
+    }
+}
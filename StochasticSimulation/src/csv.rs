@@ -0,0 +1,494 @@
+//! CSV export and import of trajectories.
+//!
+//! Two layouts are written. The wide layout has a `time, replicate` column followed by one
+//! column per species, so every row is the state of a replicate at one time. The long layout
+//! has the columns `time, replicate, species, count`, with one row per species and time,
+//! which is what pandas' `melt` and R's `pivot_longer` produce.
+//!
+//! Reading tells the layouts apart by their header, so a wide file cannot have species
+//! named exactly `species` and `count`.
+
+use std::collections::HashMap;
+use std::fmt;
+use std::fs::File;
+use std::io::{self, BufWriter, Write};
+use std::path::Path;
+use crate::monitor::{FilterableMonitor, Monitor, SnapshotData, SpeciesEvents, SystemStateSnapshot};
+use crate::reaction::SpeciesRole;
+use crate::sampling::SamplingMonitor;
+use crate::system::ChemicalSystem;
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum CsvLayout {
+    /// `time, replicate, species...`
+    Wide,
+    /// `time, replicate, species, count`
+    Long
+}
+
+/// Writes trajectories to CSV as they are produced.
+///
+/// It is a monitor as well, writing every recorded state as rows of the replicate set with
+/// `set_replicate`. The monitor traits cannot return errors, so the first one is kept and
+/// returned by `finish`, and nothing more is written after it.
+///
+/// In the wide layout the species columns are fixed by the first row, and every later row
+/// has to be for the same species.
+pub struct CsvWriter<W: Write> {
+    writer: W,
+    layout: CsvLayout,
+    columns: Option<Vec<String>>,
+    replicate: usize,
+    error: Option<io::Error>
+}
+
+impl CsvWriter<BufWriter<File>> {
+    pub fn create(path: impl AsRef<Path>, layout: CsvLayout) -> io::Result<Self> {
+        Ok(Self::new(BufWriter::new(File::create(path)?), layout))
+    }
+}
+
+impl<W: Write> CsvWriter<W> {
+    pub fn new(writer: W, layout: CsvLayout) -> Self {
+        CsvWriter {
+            writer,
+            layout,
+            columns: None,
+            replicate: 0,
+            error: None
+        }
+    }
+
+    pub fn layout(&self) -> CsvLayout {
+        self.layout
+    }
+
+    /// The replicate that recorded states are written for.
+    pub fn set_replicate(&mut self, replicate: usize) {
+        self.replicate = replicate;
+    }
+
+    /// Writes the quantities of `species` in one replicate at `time`.
+    pub fn write_row(&mut self, time: f64, replicate: usize, species: &[&str], quantities: &[i32]) -> io::Result<()> {
+        assert_eq!(species.len(), quantities.len(), "every species needs a quantity");
+
+        let quantities: Vec<Option<i32>> = quantities.iter().copied().map(Some).collect();
+
+        self.row(time, replicate, species, &quantities)
+    }
+
+    /// Writes the history of a `DefaultMonitor` for one replicate, limited to the species in
+    /// `species_to_write` whatever their role. Every snapshot becomes the full state of those
+    /// species, carrying forward the quantities it has no events for. A species that has not
+    /// been seen yet is left empty in the wide layout and left out in the long one.
+    pub fn write_snapshots(&mut self,
+                           replicate: usize,
+                           snapshots: &[SystemStateSnapshot],
+                           species_to_write: &[(&str, SpeciesRole)]) -> io::Result<()> {
+        let mut species: Vec<&str> = Vec::new();
+
+        for (name, _role) in species_to_write {
+            if !species.contains(name) {
+                species.push(name);
+            }
+        }
+
+        let mut quantities = vec![None; species.len()];
+
+        for snapshot in snapshots {
+            match &snapshot.data {
+                SnapshotData::Quantities(model, amounts) => {
+                    for (name, &amount) in model.species.iter().zip(amounts) {
+                        if let Some(column) = species.iter().position(|species| species == name) {
+                            quantities[column] = Some(amount);
+                        }
+                    }
+                }
+                SnapshotData::SpeciesEvents(events) => {
+                    for event in events {
+                        if let Some(column) = species.iter().position(|species| *species == event.species_name) {
+                            quantities[column] = Some(event.new_quantity);
+                        }
+                    }
+                }
+            }
+
+            self.row(snapshot.time, replicate, &species, &quantities)?;
+        }
+
+        Ok(())
+    }
+
+    /// Writes the grid samples of one replicate.
+    pub fn write_samples(&mut self, replicate: usize, monitor: &SamplingMonitor) -> io::Result<()> {
+        let species: Vec<&str> = monitor.species().iter().map(String::as_str).collect();
+
+        for (&time, row) in monitor.times().iter().zip(monitor.samples()) {
+            self.write_row(time, replicate, &species, row)?;
+        }
+
+        Ok(())
+    }
+
+    /// Flushes the output and returns it, or the first error met while recording.
+    pub fn finish(mut self) -> io::Result<W> {
+        if let Some(error) = self.error.take() {
+            return Err(error);
+        }
+
+        self.writer.flush()?;
+
+        Ok(self.writer)
+    }
+
+    fn row(&mut self, time: f64, replicate: usize, species: &[&str], quantities: &[Option<i32>]) -> io::Result<()> {
+        match self.layout {
+            CsvLayout::Wide => {
+                match &self.columns {
+                    Some(columns) if columns.iter().map(String::as_str).eq(species.iter().copied()) => {}
+                    Some(_) => {
+                        return Err(io::Error::new(io::ErrorKind::InvalidInput,
+                                                  "the species differ from the columns of the first row"));
+                    }
+                    None => {
+                        let header: Vec<String> = ["time", "replicate"].iter().chain(species)
+                            .map(|name| escape(name))
+                            .collect();
+
+                        writeln!(self.writer, "{}", header.join(","))?;
+                        self.columns = Some(species.iter().map(|name| name.to_string()).collect());
+                    }
+                }
+
+                write!(self.writer, "{},{}", time, replicate)?;
+
+                for quantity in quantities {
+                    match quantity {
+                        Some(quantity) => write!(self.writer, ",{}", quantity)?,
+                        None => write!(self.writer, ",")?
+                    }
+                }
+
+                writeln!(self.writer)
+            }
+            CsvLayout::Long => {
+                if self.columns.is_none() {
+                    writeln!(self.writer, "time,replicate,species,count")?;
+                    self.columns = Some(Vec::new());
+                }
+
+                for (name, quantity) in species.iter().zip(quantities) {
+                    if let Some(quantity) = quantity {
+                        writeln!(self.writer, "{},{},{},{}", time, replicate, escape(name), quantity)?;
+                    }
+                }
+
+                Ok(())
+            }
+        }
+    }
+
+    fn record(&mut self, time: f64, system: &ChemicalSystem, species_to_record: Option<&[(&str, SpeciesRole)]>) {
+        if self.error.is_some() {
+            return;
+        }
+
+        let (species, quantities): (Vec<&str>, Vec<i32>) = system.model.species.iter()
+            .zip(&system.quantities)
+            .filter(|(name, _)| species_to_record.is_none_or(|selected| {
+                selected.iter().any(|(species, _role)| species == name)
+            }))
+            .map(|(name, &quantity)| (name.as_str(), quantity))
+            .unzip();
+
+        if let Err(error) = self.write_row(time, self.replicate, &species, &quantities) {
+            self.error = Some(error);
+        }
+    }
+}
+
+impl<W: Write> Monitor<ChemicalSystem> for CsvWriter<W> {
+    fn record_state(&mut self, time: f64, system: &ChemicalSystem) {
+        self.record(time, system, None);
+    }
+}
+
+impl<W: Write> FilterableMonitor<ChemicalSystem> for CsvWriter<W> {
+    /// Only the species named in `species_to_record` are written, whatever their role.
+    fn record_state_with_filter(&mut self, time: f64, system: &ChemicalSystem, species_to_record: &[(&str, SpeciesRole)]) {
+        self.record(time, system, Some(species_to_record));
+    }
+}
+
+// Quotes fields that would otherwise be split or misread
+fn escape(field: &str) -> String {
+    if field.contains([',', '"', '\n', '\r']) {
+        format!("\"{}\"", field.replace('"', "\"\""))
+    } else {
+        field.to_string()
+    }
+}
+
+#[derive(Debug)]
+pub enum CsvError {
+    Io(io::Error),
+    MissingHeader,
+    InvalidHeader(String),
+    WrongFieldCount { line: usize, expected: usize, found: usize },
+    InvalidNumber { line: usize, value: String },
+    /// A species with no quantity at the first time of a replicate.
+    MissingValue { replicate: usize, species: String }
+}
+
+impl fmt::Display for CsvError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            CsvError::Io(error) => write!(f, "could not read CSV file: {}", error),
+            CsvError::MissingHeader => write!(f, "the file has no header"),
+            CsvError::InvalidHeader(header) =>
+                write!(f, "header `{}` does not start with `time,replicate`", header),
+            CsvError::WrongFieldCount { line, expected, found } =>
+                write!(f, "line {} has {} fields, expected {}", line, found, expected),
+            CsvError::InvalidNumber { line, value } =>
+                write!(f, "line {}: `{}` is not a number", line, value),
+            CsvError::MissingValue { replicate, species } =>
+                write!(f, "replicate {} has no quantity of `{}` at its first time", replicate, species)
+        }
+    }
+}
+
+impl std::error::Error for CsvError {}
+
+impl From<io::Error> for CsvError {
+    fn from(error: io::Error) -> Self {
+        CsvError::Io(error)
+    }
+}
+
+/// The states of one replicate read back from CSV, one row per time.
+#[derive(Clone, Debug)]
+pub struct Trajectory {
+    pub replicate: usize,
+    pub species: Vec<String>,
+    pub times: Vec<f64>,
+    pub quantities: Vec<Vec<i32>>
+}
+
+impl Trajectory {
+    pub fn series(&self, species: &str) -> Option<Vec<i32>> {
+        let column = self.species.iter().position(|name| name == species)?;
+
+        Some(self.quantities.iter().map(|row| row[column]).collect())
+    }
+
+    /// The trajectory as monitor history, for `plotter::plot`.
+    pub fn snapshots(&self) -> Vec<SystemStateSnapshot> {
+        self.times.iter().zip(&self.quantities)
+            .map(|(&time, row)| SystemStateSnapshot {
+                time,
+                data: SnapshotData::SpeciesEvents(self.species.iter().zip(row)
+                    .map(|(name, &quantity)| SpeciesEvents {
+                        species_name: name.clone(),
+                        new_quantity: quantity
+                    })
+                    .collect())
+            })
+            .collect()
+    }
+
+    /// The trajectory as grid samples, for `statistics`. The times are taken to be the grid.
+    pub fn into_sampling_monitor(self) -> SamplingMonitor {
+        SamplingMonitor::from_samples(self.times, self.species, self.quantities)
+    }
+}
+
+pub fn read_csv(path: impl AsRef<Path>) -> Result<Vec<Trajectory>, CsvError> {
+    parse_csv(&std::fs::read_to_string(path)?)
+}
+
+/// Reads trajectories in either layout, in the order their replicates first appear. Rows
+/// of a replicate have to be in time order. Empty quantities, and species missing from a
+/// time in the long layout, carry forward the previous quantity of the replicate.
+pub fn parse_csv(text: &str) -> Result<Vec<Trajectory>, CsvError> {
+    let mut records = Records::new(text);
+
+    let (_, header) = records.next().ok_or(CsvError::MissingHeader)?;
+    let columns: Vec<String> = header.iter().map(|column| column.trim().to_string()).collect();
+
+    if columns.len() < 2 || columns[0] != "time" || columns[1] != "replicate" {
+        return Err(CsvError::InvalidHeader(columns.join(",")));
+    }
+
+    let long = columns.len() == 4 && columns[2] == "species" && columns[3] == "count";
+    let mut builder = Builder::new(if long { Vec::new() } else { columns[2..].to_vec() });
+
+    for (line, fields) in records {
+        if fields.len() != columns.len() {
+            return Err(CsvError::WrongFieldCount { line, expected: columns.len(), found: fields.len() });
+        }
+
+        let time: f64 = number(line, &fields[0])?;
+        let replicate: usize = number(line, &fields[1])?;
+
+        if long {
+            let column = builder.column(&fields[2]);
+            let count = number(line, &fields[3])?;
+
+            builder.set(replicate, time, column, count);
+        } else {
+            builder.row(replicate, time);
+
+            for (column, field) in fields[2..].iter().enumerate() {
+                if !field.is_empty() {
+                    builder.set(replicate, time, column, number(line, field)?);
+                }
+            }
+        }
+    }
+
+    builder.finish()
+}
+
+fn number<T: std::str::FromStr>(line: usize, field: &str) -> Result<T, CsvError> {
+    field.trim().parse().map_err(|_| CsvError::InvalidNumber { line, value: field.to_string() })
+}
+
+// The fields of every record with the line it starts on, undoing `escape`. Commas and line
+// breaks inside quotes belong to the field, so a record can span several lines. Blank lines
+// are skipped.
+struct Records<'a> {
+    chars: std::iter::Peekable<std::str::Chars<'a>>,
+    line: usize
+}
+
+impl<'a> Records<'a> {
+    fn new(text: &'a str) -> Self {
+        Records { chars: text.chars().peekable(), line: 1 }
+    }
+}
+
+impl Iterator for Records<'_> {
+    type Item = (usize, Vec<String>);
+
+    fn next(&mut self) -> Option<Self::Item> {
+        loop {
+            self.chars.peek()?;
+
+            let start = self.line;
+            let mut fields = Vec::new();
+            let mut field = String::new();
+            let mut quoted = false;
+
+            while let Some(c) = self.chars.next() {
+                match c {
+                    '"' if quoted && self.chars.peek() == Some(&'"') => {
+                        field.push('"');
+                        self.chars.next();
+                    }
+                    '"' => quoted = !quoted,
+                    ',' if !quoted => fields.push(std::mem::take(&mut field)),
+                    '\r' if !quoted && self.chars.peek() == Some(&'\n') => {}
+                    '\n' => {
+                        self.line += 1;
+
+                        if !quoted {
+                            break;
+                        }
+
+                        field.push(c);
+                    }
+                    c => field.push(c)
+                }
+            }
+
+            fields.push(field);
+
+            if fields.len() > 1 || !fields[0].trim().is_empty() {
+                return Some((start, fields));
+            }
+        }
+    }
+}
+
+// The rows of a replicate read so far, with quantities that may still be unknown
+struct Partial {
+    replicate: usize,
+    times: Vec<f64>,
+    rows: Vec<Vec<Option<i32>>>
+}
+
+struct Builder {
+    species: Vec<String>,
+    replicates: Vec<Partial>,
+    positions: HashMap<usize, usize>
+}
+
+impl Builder {
+    fn new(species: Vec<String>) -> Self {
+        Builder {
+            species,
+            replicates: Vec::new(),
+            positions: HashMap::new()
+        }
+    }
+
+    fn column(&mut self, species: &str) -> usize {
+        match self.species.iter().position(|name| name == species) {
+            Some(column) => column,
+            None => {
+                self.species.push(species.to_string());
+                self.species.len() - 1
+            }
+        }
+    }
+
+    // Starts a row at `time` for the replicate, unless its last row is already at that time
+    fn row(&mut self, replicate: usize, time: f64) -> &mut Vec<Option<i32>> {
+        let position = *self.positions.entry(replicate).or_insert_with(|| {
+            self.replicates.push(Partial { replicate, times: Vec::new(), rows: Vec::new() });
+            self.replicates.len() - 1
+        });
+        let Partial { times, rows, .. } = &mut self.replicates[position];
+
+        if times.last() != Some(&time) {
+            let previous = rows.last().cloned().unwrap_or_default();
+
+            times.push(time);
+            rows.push(previous);
+        }
+
+        rows.last_mut().unwrap()
+    }
+
+    fn set(&mut self, replicate: usize, time: f64, column: usize, quantity: i32) {
+        let row = self.row(replicate, time);
+
+        if row.len() <= column {
+            row.resize(column + 1, None);
+        }
+
+        row[column] = Some(quantity);
+    }
+
+    fn finish(self) -> Result<Vec<Trajectory>, CsvError> {
+        let species = self.species;
+
+        self.replicates.into_iter()
+            .map(|Partial { replicate, times, rows }| {
+                let quantities = rows.into_iter()
+                    .map(|mut row| {
+                        row.resize(species.len(), None);
+                        row.iter().zip(&species)
+                            .map(|(quantity, name)| quantity.ok_or_else(|| CsvError::MissingValue {
+                                replicate,
+                                species: name.clone()
+                            }))
+                            .collect()
+                    })
+                    .collect::<Result<_, _>>()?;
+
+                Ok(Trajectory { replicate, species: species.clone(), times, quantities })
+            })
+            .collect()
+    }
+}
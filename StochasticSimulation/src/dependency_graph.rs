@@ -0,0 +1,40 @@
+/// For every reaction, the reactions whose propensity may change when it fires.
+///
+/// Reaction `j` points to reaction `i` when a species whose quantity is changed by `j`
+/// is one of the reactants of `i`. Every reaction depends on itself. Species consumed
+/// and produced in equal amounts, like a catalyst, do not count as changed.
+#[derive(Clone, Debug)]
+pub struct DependencyGraph {
+    dependents: Vec<Vec<usize>>
+}
+
+impl DependencyGraph {
+    pub fn new(species_count: usize, reactants: &[Vec<(usize, u32)>], changes: &[Vec<(usize, i32)>]) -> Self {
+        let mut reactions_by_reactant: Vec<Vec<usize>> = vec![Vec::new(); species_count];
+
+        for (index, consumed) in reactants.iter().enumerate() {
+            for &(species, _) in consumed {
+                reactions_by_reactant[species].push(index);
+            }
+        }
+
+        let dependents = changes.iter().enumerate().map(|(index, changed)| {
+            let mut dependent: Vec<usize> = changed.iter()
+                .filter(|(_, change)| *change != 0)
+                .flat_map(|&(species, _)| reactions_by_reactant[species].iter())
+                .copied()
+                .chain(std::iter::once(index))
+                .collect();
+
+            dependent.sort_unstable();
+            dependent.dedup();
+            dependent
+        }).collect();
+
+        DependencyGraph { dependents }
+    }
+
+    pub fn dependents(&self, index: usize) -> &[usize] {
+        &self.dependents[index]
+    }
+}
@@ -0,0 +1,182 @@
+use std::fmt;
+use rand::{Rng, SeedableRng};
+use rand_chacha::ChaCha12Rng;
+use rayon::prelude::*;
+use rayon::{ThreadPool, ThreadPoolBuildError, ThreadPoolBuilder};
+use crate::monitor::FilterableMonitor;
+use crate::reaction::SpeciesRole;
+use crate::stopping::{SimulationSummary, StopConditions};
+use crate::system::ChemicalSystem;
+
+#[derive(Clone, Debug)]
+pub struct EnsembleOptions {
+    pub replicates: usize,
+    /// Master seed that every replicate's random number generator is derived from.
+    pub seed: u64,
+    /// Size of the thread pool the replicates run on. `None` uses rayon's global pool.
+    pub threads: Option<usize>
+}
+
+impl Default for EnsembleOptions {
+    fn default() -> Self {
+        EnsembleOptions {
+            replicates: 20,
+            seed: 0,
+            threads: None
+        }
+    }
+}
+
+#[derive(Debug)]
+pub enum EnsembleError {
+    /// The thread pool of `EnsembleOptions::threads` could not be started.
+    ThreadPool(ThreadPoolBuildError)
+}
+
+impl fmt::Display for EnsembleError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            EnsembleError::ThreadPool(error) => write!(f, "could not start the thread pool: {}", error)
+        }
+    }
+}
+
+impl std::error::Error for EnsembleError {}
+
+impl From<ThreadPoolBuildError> for EnsembleError {
+    fn from(error: ThreadPoolBuildError) -> Self {
+        EnsembleError::ThreadPool(error)
+    }
+}
+
+/// The outcome of one replicate: the system in its final state, what was recorded and why
+/// it stopped.
+#[derive(Clone)]
+pub struct Replicate<M> {
+    pub index: usize,
+    pub system: ChemicalSystem,
+    pub monitor: M,
+    pub summary: SimulationSummary
+}
+
+/// Independent replicates of one `ChemicalSystem`, run in parallel.
+///
+/// Replicate `i` always gets the same random number stream for a given master seed, so
+/// results are reproducible and come back in replicate order whatever the thread count.
+pub struct Ensemble {
+    system: ChemicalSystem,
+    options: EnsembleOptions,
+    // Started once for all runs, when `EnsembleOptions::threads` is set
+    thread_pool: Option<ThreadPool>
+}
+
+impl Ensemble {
+    /// Fails if the thread pool of `EnsembleOptions::threads` cannot be started.
+    pub fn new(system: ChemicalSystem, options: EnsembleOptions) -> Result<Self, EnsembleError> {
+        let thread_pool = options.threads
+            .map(|threads| ThreadPoolBuilder::new().num_threads(threads).build())
+            .transpose()?;
+
+        Ok(Ensemble { system, options, thread_pool })
+    }
+
+    pub fn system(&self) -> &ChemicalSystem {
+        &self.system
+    }
+
+    pub fn options(&self) -> &EnsembleOptions {
+        &self.options
+    }
+
+    /// Seeds of the replicates' generators. They are drawn in order from a generator seeded
+    /// with the master seed, so adding replicates leaves the earlier ones unchanged.
+    pub fn seeds(&self) -> Vec<[u8; 32]> {
+        let mut master = ChaCha12Rng::seed_from_u64(self.options.seed);
+
+        (0..self.options.replicates).map(|_| master.gen()).collect()
+    }
+
+    /// Simulates every replicate up to `end_time` with the system's algorithm, recording
+    /// into a monitor made for it by `new_monitor`.
+    pub fn run<M, F>(&self,
+                     end_time: f64,
+                     new_monitor: F,
+                     species_to_record: &[(&str, SpeciesRole)]) -> Vec<Replicate<M>>
+    where
+        M: FilterableMonitor<ChemicalSystem> + Send,
+        F: Fn(usize) -> M + Sync
+    {
+        self.run_until(&StopConditions::at(end_time), new_monitor, species_to_record)
+    }
+
+    /// Like `run`, but every replicate stops when one of `conditions` is met.
+    pub fn run_until<M, F>(&self,
+                           conditions: &StopConditions,
+                           new_monitor: F,
+                           species_to_record: &[(&str, SpeciesRole)]) -> Vec<Replicate<M>>
+    where
+        M: FilterableMonitor<ChemicalSystem> + Send,
+        F: Fn(usize) -> M + Sync
+    {
+        self.run_with(|index, mut system, mut rng| {
+            let mut monitor = new_monitor(index);
+
+            let summary = system.simulate_until(conditions, &mut rng, &mut monitor, species_to_record);
+
+            Replicate { index, system, monitor, summary }
+        })
+    }
+
+    /// Calls `replicate` with the index, a fresh copy of the system and the generator of
+    /// every replicate, for runs that need more than `simulate`.
+    pub fn run_with<R, F>(&self, replicate: F) -> Vec<R>
+    where
+        R: Send,
+        F: Fn(usize, ChemicalSystem, ChaCha12Rng) -> R + Sync
+    {
+        let seeds = self.seeds();
+
+        // Indexed parallel iterators collect in order, however the work was split
+        let run = || {
+            seeds.into_par_iter()
+                .enumerate()
+                .map(|(index, seed)| replicate(index, self.system.clone(), ChaCha12Rng::from_seed(seed)))
+                .collect()
+        };
+
+        match &self.thread_pool {
+            Some(pool) => pool.install(run),
+            None => run()
+        }
+    }
+
+    /// Like `run_with`, but hands the results to `consume` in replicate order one batch at a
+    /// time, so no more than `batch_size` of them are held at once.
+    pub fn run_batched<R, F, C>(&self, batch_size: usize, replicate: F, mut consume: C)
+    where
+        R: Send,
+        F: Fn(usize, ChemicalSystem, ChaCha12Rng) -> R + Sync,
+        C: FnMut(R)
+    {
+        let seeds = self.seeds();
+        let batch_size = batch_size.max(1);
+
+        for (batch, batch_seeds) in seeds.chunks(batch_size).enumerate() {
+            let run = || {
+                batch_seeds.par_iter()
+                    .enumerate()
+                    .map(|(offset, seed)| {
+                        replicate(batch * batch_size + offset, self.system.clone(), ChaCha12Rng::from_seed(*seed))
+                    })
+                    .collect::<Vec<R>>()
+            };
+
+            let results = match &self.thread_pool {
+                Some(pool) => pool.install(run),
+                None => run()
+            };
+
+            results.into_iter().for_each(&mut consume);
+        }
+    }
+}
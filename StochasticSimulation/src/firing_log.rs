@@ -0,0 +1,241 @@
+use std::sync::Arc;
+use uuid::Uuid;
+use crate::checkpoint::{CheckpointError, ResumableMonitor, StateReader, StateWriter};
+use crate::model::Model;
+use crate::monitor::{FilterableMonitor, Monitor};
+use crate::reaction::SpeciesRole;
+use crate::system::ChemicalSystem;
+
+#[derive(Clone, Debug)]
+pub struct FiringLogOptions {
+    /// Keep every firing rather than only the counts, which grows with the number of events.
+    pub keep_firings: bool,
+    /// Width of the time windows firings are counted in for `FiringLog::fluxes`.
+    pub window: Option<f64>
+}
+
+impl Default for FiringLogOptions {
+    fn default() -> Self {
+        FiringLogOptions {
+            keep_firings: true,
+            window: None
+        }
+    }
+}
+
+/// One entry of the log. A leap fires a reaction many times at once, and is logged at its end.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct Firing {
+    pub time: f64,
+    pub reaction: usize,
+    pub count: u32
+}
+
+/// Records which reactions fire, to tell which channels drive the dynamics. It ignores the
+/// species states, so pair it with another monitor, e.g. `(DefaultMonitor::new(), log)`, to
+/// record both.
+///
+/// Reactions are indices into the model of the simulated system, which `reaction_id` and
+/// `formula` look up. Every run recorded into one log must simulate the same model.
+#[derive(Clone)]
+pub struct FiringLog {
+    options: FiringLogOptions,
+    model: Option<Arc<Model>>,
+    firings: Vec<Firing>,
+    counts: Vec<u64>,
+    // Indexed by window, then reaction
+    windows: Vec<Vec<u64>>,
+    // Firings already in the monitor journal of a checkpointed run
+    saved: usize
+}
+
+/// Firings of every reaction per time window, starting from time zero.
+#[derive(Clone, Debug)]
+pub struct Fluxes {
+    pub window: f64,
+    // Indexed by window, then reaction
+    pub counts: Vec<Vec<u64>>
+}
+
+impl Fluxes {
+    pub fn window_start(&self, window: usize) -> f64 {
+        window as f64 * self.window
+    }
+
+    /// Firings per unit of time of every reaction in every window.
+    pub fn rates(&self) -> Vec<Vec<f64>> {
+        self.counts.iter()
+            .map(|counts| counts.iter().map(|&count| count as f64 / self.window).collect())
+            .collect()
+    }
+}
+
+impl FiringLog {
+    pub fn new(options: FiringLogOptions) -> Self {
+        if let Some(window) = options.window {
+            assert!(window > 0.0, "flux window must be positive");
+        }
+
+        FiringLog {
+            options,
+            model: None,
+            firings: Vec::new(),
+            counts: Vec::new(),
+            windows: Vec::new(),
+            saved: 0
+        }
+    }
+
+    /// Every firing in order, if `keep_firings` is set.
+    pub fn firings(&self) -> &[Firing] {
+        &self.firings
+    }
+
+    pub fn reaction_id(&self, reaction: usize) -> Option<Uuid> {
+        self.model.as_ref().map(|model| model.reaction_id(reaction))
+    }
+
+    pub fn formula(&self, reaction: usize) -> Option<&str> {
+        self.model.as_ref().map(|model| model.formula(reaction))
+    }
+
+    /// Times every reaction fired, indexed by reaction.
+    pub fn counts(&self) -> &[u64] {
+        &self.counts
+    }
+
+    pub fn total(&self) -> u64 {
+        self.counts.iter().sum()
+    }
+
+    /// Reactions that fired, from the most to the least frequent.
+    pub fn ranked(&self) -> Vec<(usize, u64)> {
+        let mut ranked: Vec<(usize, u64)> = self.counts.iter().copied().enumerate()
+            .filter(|&(_, count)| count > 0)
+            .collect();
+
+        ranked.sort_by(|a, b| b.1.cmp(&a.1).then(a.0.cmp(&b.0)));
+        ranked
+    }
+
+    /// Counts per window, if the log was made with a window.
+    pub fn fluxes(&self) -> Option<Fluxes> {
+        self.options.window.map(|window| Fluxes {
+            window,
+            counts: self.windows.clone()
+        })
+    }
+
+    /// Forgets everything recorded, keeping the options.
+    pub fn clear(&mut self) {
+        self.model = None;
+        self.firings.clear();
+        self.counts.clear();
+        self.windows.clear();
+        self.saved = 0;
+    }
+}
+
+impl Monitor<ChemicalSystem> for FiringLog {
+    fn record_state(&mut self, _time: f64, _system: &ChemicalSystem) {}
+
+    fn record_firing(&mut self, time: f64, system: &ChemicalSystem, reaction: usize, count: u32) {
+        if self.model.is_none() {
+            self.model = Some(Arc::clone(&system.model));
+            self.counts = vec![0; system.model.reaction_count()];
+        }
+
+        self.counts[reaction] += count as u64;
+
+        if self.options.keep_firings {
+            self.firings.push(Firing { time, reaction, count });
+        }
+
+        if let Some(window) = self.options.window {
+            let index = (time / window).floor() as usize;
+
+            if self.windows.len() <= index {
+                self.windows.resize(index + 1, vec![0; self.counts.len()]);
+            }
+
+            self.windows[index][reaction] += count as u64;
+        }
+    }
+}
+
+impl FilterableMonitor<ChemicalSystem> for FiringLog {
+    fn record_state_with_filter(&mut self, _time: f64, _system: &ChemicalSystem, _species_to_record: &[(&str, SpeciesRole)]) {}
+}
+
+impl ResumableMonitor for FiringLog {
+    fn save_state(&self, state: &mut StateWriter) {
+        self.write_firings(state, 0);
+    }
+
+    fn save_changes(&mut self, state: &mut StateWriter, from_start: bool) {
+        let from = if from_start || self.saved > self.firings.len() { 0 } else { self.saved };
+
+        self.write_firings(state, from);
+        self.saved = self.firings.len();
+    }
+
+    fn restore_state(&mut self, state: &mut StateReader, system: &ChemicalSystem) -> Result<(), CheckpointError> {
+        state.expect_tag("firing log")?;
+
+        let replaces = state.read_bool()?;
+        let recorded = state.read_bool()?;
+        let counts = state.read_u64s()?;
+        let firing_count = state.read_usize()?;
+        let firings = (0..firing_count)
+            .map(|_| Ok(Firing {
+                time: state.read_f64()?,
+                reaction: state.read_usize()?,
+                count: state.read_u32()?
+            }))
+            .collect::<Result<Vec<_>, CheckpointError>>()?;
+        let window_count = state.read_usize()?;
+        let windows = (0..window_count).map(|_| state.read_u64s()).collect::<Result<Vec<_>, _>>()?;
+
+        let reaction_count = system.model.reaction_count();
+
+        if (recorded && counts.len() != reaction_count)
+            || firings.iter().any(|firing| firing.reaction >= reaction_count)
+            || windows.iter().any(|window| window.len() != counts.len()) {
+            return Err(CheckpointError::Corrupt("firings do not match the reactions".to_string()));
+        }
+
+        if replaces {
+            self.firings = firings;
+        } else {
+            self.firings.extend(firings);
+        }
+
+        self.model = recorded.then(|| Arc::clone(&system.model));
+        self.counts = counts;
+        self.windows = windows;
+        self.saved = self.firings.len();
+
+        Ok(())
+    }
+}
+
+impl FiringLog {
+    // The firings from `from` on, which replace the restored firings if they start at the
+    // beginning and are added to them otherwise. Counts and fluxes are written in full.
+    fn write_firings(&self, state: &mut StateWriter, from: usize) {
+        state.write_str("firing log");
+        state.write_bool(from == 0);
+        state.write_bool(self.model.is_some());
+        state.write_u64s(&self.counts);
+        state.write_usize(self.firings.len() - from);
+
+        for firing in &self.firings[from..] {
+            state.write_f64(firing.time);
+            state.write_usize(firing.reaction);
+            state.write_u32(firing.count);
+        }
+
+        state.write_usize(self.windows.len());
+        self.windows.iter().for_each(|counts| state.write_u64s(counts));
+    }
+}
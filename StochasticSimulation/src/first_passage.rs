@@ -0,0 +1,257 @@
+use crate::ensemble::Ensemble;
+use crate::monitor::{FilterableMonitor, Monitor};
+use crate::reaction::SpeciesRole;
+use crate::stopping::{StopConditions, StopReason};
+use crate::system::ChemicalSystem;
+
+/// The quantities a species has to reach.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Threshold {
+    AtLeast(i32),
+    AtMost(i32)
+}
+
+impl Threshold {
+    pub fn is_reached(&self, quantity: i32) -> bool {
+        match *self {
+            Threshold::AtLeast(threshold) => quantity >= threshold,
+            Threshold::AtMost(threshold) => quantity <= threshold
+        }
+    }
+}
+
+/// How one replicate ended. A censored replicate stopped without reaching the threshold,
+/// at the end time or earlier if it was absorbed or cut short, so its passage time is only
+/// known to be later than `time`.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct Passage {
+    pub replicate: usize,
+    pub time: f64,
+    pub reached: bool
+}
+
+/// First passage times of a species through a threshold across the replicates of an
+/// ensemble, such as extinction times.
+#[derive(Clone, Debug)]
+pub struct FirstPassageTimes {
+    pub species: String,
+    pub threshold: Threshold,
+    pub end_time: f64,
+    /// In replicate order.
+    pub passages: Vec<Passage>
+}
+
+/// Estimates from `FirstPassageTimes::summary`, with intervals at the requested confidence.
+/// The mean and variance are of the replicates that reached the threshold, so they are
+/// conditional on reaching it before the end time.
+#[derive(Clone, Debug)]
+pub struct PassageSummary {
+    pub replicates: usize,
+    pub reached: usize,
+    pub censored: usize,
+    pub confidence: f64,
+    pub fraction_reached: f64,
+    /// Wilson score interval.
+    pub fraction_interval: (f64, f64),
+    pub mean: Option<f64>,
+    /// Normal approximation, which needs a few tens of passages to be trusted.
+    pub mean_interval: Option<(f64, f64)>,
+    pub variance: Option<f64>,
+    /// Chi-squared interval, which assumes roughly normal passage times.
+    pub variance_interval: Option<(f64, f64)>
+}
+
+impl FirstPassageTimes {
+    /// Runs every replicate of `ensemble` until `species` reaches `threshold` or `end_time`.
+    /// Returns `None` if the system has no such species.
+    pub fn simulate(ensemble: &Ensemble, species: &str, threshold: Threshold, end_time: f64) -> Option<Self> {
+        let index = ensemble.system().model().species_index(species)?;
+        let conditions = StopConditions::at(end_time)
+            .stop_when(move |system| threshold.is_reached(system.quantities()[index]));
+
+        let passages = ensemble.run_with(|replicate, mut system, mut rng| {
+            let summary = system.simulate_until(&conditions, &mut rng, &mut Unrecorded, &[]);
+
+            Passage {
+                replicate,
+                time: summary.time,
+                reached: summary.reason == StopReason::Condition(0)
+            }
+        });
+
+        Some(FirstPassageTimes {
+            species: species.to_string(),
+            threshold,
+            end_time,
+            passages
+        })
+    }
+
+    /// Times until no molecule of `species` is left.
+    pub fn extinction(ensemble: &Ensemble, species: &str, end_time: f64) -> Option<Self> {
+        Self::simulate(ensemble, species, Threshold::AtMost(0), end_time)
+    }
+
+    pub fn reached(&self) -> usize {
+        self.passages.iter().filter(|passage| passage.reached).count()
+    }
+
+    pub fn censored(&self) -> usize {
+        self.passages.len() - self.reached()
+    }
+
+    /// Passage times of the replicates that reached the threshold, in increasing order.
+    pub fn times(&self) -> Vec<f64> {
+        let mut times: Vec<f64> = self.passages.iter()
+            .filter(|passage| passage.reached)
+            .map(|passage| passage.time)
+            .collect();
+
+        times.sort_by(f64::total_cmp);
+        times
+    }
+
+    /// Probability of having reached the threshold by each passage time, as the points of a
+    /// step function. This is the Kaplan-Meier estimate, which takes replicates censored
+    /// before the end time into account and is the plain empirical CDF when there are none.
+    pub fn cdf(&self) -> Vec<(f64, f64)> {
+        let mut passages = self.passages.clone();
+
+        // At equal times passages come first, as censored replicates were still at risk then
+        passages.sort_by(|a, b| a.time.total_cmp(&b.time).then(b.reached.cmp(&a.reached)));
+
+        let mut at_risk = passages.len();
+        let mut survival = 1.0;
+        let mut cdf: Vec<(f64, f64)> = Vec::new();
+        let mut position = 0;
+
+        while position < passages.len() {
+            let time = passages[position].time;
+            let tied = passages[position..].iter().take_while(|passage| passage.time == time).count();
+            let reached = passages[position..position + tied].iter().filter(|passage| passage.reached).count();
+
+            if reached > 0 {
+                survival *= 1.0 - reached as f64 / at_risk as f64;
+                cdf.push((time, 1.0 - survival));
+            }
+
+            at_risk -= tied;
+            position += tied;
+        }
+
+        cdf
+    }
+
+    /// Probability of having reached the threshold by `time`.
+    pub fn cdf_at(&self, time: f64) -> f64 {
+        self.cdf().iter()
+            .take_while(|&&(passage, _)| passage <= time)
+            .last()
+            .map_or(0.0, |&(_, probability)| probability)
+    }
+
+    /// Estimates with intervals at `confidence`, e.g. 0.95.
+    pub fn summary(&self, confidence: f64) -> PassageSummary {
+        let times = self.times();
+        let replicates = self.passages.len();
+        let reached = times.len();
+        let z = normal_quantile(0.5 + confidence / 2.0);
+
+        let fraction_reached = if replicates > 0 { reached as f64 / replicates as f64 } else { 0.0 };
+        let fraction_interval = wilson_interval(reached, replicates, z);
+
+        let mean = (reached > 0).then(|| times.iter().sum::<f64>() / reached as f64);
+        let variance = mean.filter(|_| reached > 1).map(|mean| {
+            times.iter().map(|time| (time - mean).powi(2)).sum::<f64>() / (reached - 1) as f64
+        });
+
+        let mean_interval = mean.zip(variance).map(|(mean, variance)| {
+            let half_width = z * (variance / reached as f64).sqrt();
+
+            (mean - half_width, mean + half_width)
+        });
+        let variance_interval = variance.map(|variance| {
+            let freedom = (reached - 1) as f64;
+
+            (freedom * variance / chi_squared_quantile(0.5 + confidence / 2.0, freedom),
+             freedom * variance / chi_squared_quantile(0.5 - confidence / 2.0, freedom))
+        });
+
+        PassageSummary {
+            replicates,
+            reached,
+            censored: replicates - reached,
+            confidence,
+            fraction_reached,
+            fraction_interval,
+            mean,
+            mean_interval,
+            variance,
+            variance_interval
+        }
+    }
+}
+
+// The runs only need their summaries
+struct Unrecorded;
+
+impl Monitor<ChemicalSystem> for Unrecorded {
+    fn record_state(&mut self, _time: f64, _system: &ChemicalSystem) {}
+}
+
+impl FilterableMonitor<ChemicalSystem> for Unrecorded {
+    fn record_state_with_filter(&mut self, _time: f64, _system: &ChemicalSystem, _species_to_record: &[(&str, SpeciesRole)]) {}
+}
+
+fn wilson_interval(successes: usize, trials: usize, z: f64) -> (f64, f64) {
+    if trials == 0 {
+        return (0.0, 1.0);
+    }
+
+    let n = trials as f64;
+    let p = successes as f64 / n;
+    let denominator = 1.0 + z * z / n;
+    let centre = (p + z * z / (2.0 * n)) / denominator;
+    let half_width = z * (p * (1.0 - p) / n + z * z / (4.0 * n * n)).sqrt() / denominator;
+
+    ((centre - half_width).max(0.0), (centre + half_width).min(1.0))
+}
+
+// Wilson-Hilferty approximation, good to a few parts in a thousand from a handful of degrees of freedom
+fn chi_squared_quantile(probability: f64, freedom: f64) -> f64 {
+    let scale = 2.0 / (9.0 * freedom);
+
+    freedom * (1.0 - scale + normal_quantile(probability) * scale.sqrt()).powi(3).max(f64::MIN_POSITIVE)
+}
+
+// Acklam's rational approximation of the inverse standard normal CDF, relative error below 1.2e-9
+fn normal_quantile(probability: f64) -> f64 {
+    const A: [f64; 6] = [-3.969683028665376e1, 2.209460984245205e2, -2.759285104469687e2,
+                         1.38357751867269e2, -3.066479806614716e1, 2.506628277459239];
+    const B: [f64; 5] = [-5.447609879822406e1, 1.615858368580409e2, -1.556989798598866e2,
+                         6.680131188771972e1, -1.328068155288572e1];
+    const C: [f64; 6] = [-7.784894002430293e-3, -3.223964580411365e-1, -2.400758277161838,
+                         -2.549732539343734, 4.374664141464968, 2.938163982698783];
+    const D: [f64; 4] = [7.784695709041462e-3, 3.224671290700398e-1, 2.445134137142996,
+                         3.754408661907416];
+    const LOW: f64 = 0.02425;
+
+    let p = probability.clamp(f64::MIN_POSITIVE, 1.0 - f64::EPSILON);
+
+    let tail = |q: f64| {
+        (((((C[0] * q + C[1]) * q + C[2]) * q + C[3]) * q + C[4]) * q + C[5])
+            / ((((D[0] * q + D[1]) * q + D[2]) * q + D[3]) * q + 1.0)
+    };
+
+    if p < LOW {
+        tail((-2.0 * p.ln()).sqrt())
+    } else if p > 1.0 - LOW {
+        -tail((-2.0 * (1.0 - p).ln()).sqrt())
+    } else {
+        let q = p - 0.5;
+        let r = q * q;
+
+        (((((A[0] * r + A[1]) * r + A[2]) * r + A[3]) * r + A[4]) * r + A[5]) * q
+            / (((((B[0] * r + B[1]) * r + B[2]) * r + B[3]) * r + B[4]) * r + 1.0)
+    }
+}
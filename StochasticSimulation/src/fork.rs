@@ -0,0 +1,169 @@
+use std::fmt;
+use rand::SeedableRng;
+use rand_chacha::ChaCha12Rng;
+use crate::checkpoint::{Checkpoint, CheckpointError};
+use crate::ensemble::{Ensemble, EnsembleError, EnsembleOptions, Replicate};
+use crate::monitor::FilterableMonitor;
+use crate::reaction::SpeciesRole;
+use crate::simulation::Simulation;
+use crate::stopping::StopConditions;
+use crate::system::ChemicalSystem;
+
+#[derive(Debug)]
+pub enum ForkError {
+    /// The forked state cannot be continued, as its visitor or network does not match.
+    State(CheckpointError),
+    Ensemble(EnsembleError)
+}
+
+impl fmt::Display for ForkError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            ForkError::State(error) => write!(f, "could not fork: {}", error),
+            ForkError::Ensemble(error) => write!(f, "could not fork: {}", error)
+        }
+    }
+}
+
+impl std::error::Error for ForkError {}
+
+impl From<CheckpointError> for ForkError {
+    fn from(error: CheckpointError) -> Self {
+        ForkError::State(error)
+    }
+}
+
+impl From<EnsembleError> for ForkError {
+    fn from(error: EnsembleError) -> Self {
+        ForkError::Ensemble(error)
+    }
+}
+
+/// Independent continuations of one trajectory from the state it had reached, for studies of
+/// what happens after that point. They are a sub-ensemble: every branch starts from the same
+/// time and quantities with a random number stream of its own, drawn from the master seed
+/// like the replicates of an `Ensemble`, and `EnsembleOptions::replicates` branches are run.
+///
+/// Waiting times the forked run had drawn already, such as the firing times scheduled by the
+/// next reaction method, are drawn again in every branch, as sharing them would make the
+/// branches start out alike. Waiting times are memoryless, so this is still exact.
+pub struct Fork {
+    ensemble: Ensemble,
+    state: Checkpoint
+}
+
+impl Fork {
+    /// Branches the run `simulation` is at, which is left as it was. Fails if its visitor is
+    /// not for the algorithm of its system, or the thread pool of `options` cannot be started.
+    pub fn new(simulation: &Simulation<'_>, options: EnsembleOptions) -> Result<Self, ForkError> {
+        Self::branch(simulation.system().clone(), simulation.checkpoint(), options)
+    }
+
+    /// Branches the run `checkpoint` was taken from, which has to be of the reaction network
+    /// of `system`.
+    pub fn from_checkpoint(system: &ChemicalSystem, checkpoint: &Checkpoint, options: EnsembleOptions) -> Result<Self, ForkError> {
+        if !checkpoint.is_of(&system.model) {
+            return Err(CheckpointError::WrongModel.into());
+        }
+
+        let mut system = system.clone();
+        let mut state = checkpoint.clone();
+
+        system.quantities.clone_from(&checkpoint.quantities);
+        system.algorithm = checkpoint.algorithm;
+        // Branches record into monitors of their own
+        state.monitor = None;
+        state.journal_length = None;
+        state.journal = None;
+
+        Self::branch(system, state, options)
+    }
+
+    fn branch(system: ChemicalSystem, state: Checkpoint, options: EnsembleOptions) -> Result<Self, ForkError> {
+        // Restored once here so that the branches cannot fail to
+        let mut visitor = state.algorithm.visitor();
+        let mut rng = ChaCha12Rng::from_seed([0; 32]);
+
+        Simulation::restore(&mut system.clone(), visitor.as_mut(), &mut rng, &state)?;
+
+        Ok(Fork {
+            ensemble: Ensemble::new(system, options)?,
+            state
+        })
+    }
+
+    /// Simulated time the branches start at.
+    pub fn time(&self) -> f64 {
+        self.state.time
+    }
+
+    /// The system in the state the branches start from.
+    pub fn system(&self) -> &ChemicalSystem {
+        self.ensemble.system()
+    }
+
+    pub fn options(&self) -> &EnsembleOptions {
+        self.ensemble.options()
+    }
+
+    /// Seeds of the branches' generators, drawn like those of `Ensemble::seeds`.
+    pub fn seeds(&self) -> Vec<[u8; 32]> {
+        self.ensemble.seeds()
+    }
+
+    /// Continues every branch up to `end_time`, counted from the start of the forked run,
+    /// recording into a monitor made for it by `new_monitor` from the fork time onwards.
+    pub fn run<M, F>(&self,
+                     end_time: f64,
+                     new_monitor: F,
+                     species_to_record: &[(&str, SpeciesRole)]) -> Vec<Replicate<M>>
+    where
+        M: FilterableMonitor<ChemicalSystem> + Send,
+        F: Fn(usize) -> M + Sync
+    {
+        self.run_until(&StopConditions::at(end_time), new_monitor, species_to_record)
+    }
+
+    /// Like `run`, but every branch stops when one of `conditions` is met.
+    pub fn run_until<M, F>(&self,
+                           conditions: &StopConditions,
+                           new_monitor: F,
+                           species_to_record: &[(&str, SpeciesRole)]) -> Vec<Replicate<M>>
+    where
+        M: FilterableMonitor<ChemicalSystem> + Send,
+        F: Fn(usize) -> M + Sync
+    {
+        self.run_with(|index, simulation| {
+            let mut monitor = new_monitor(index);
+
+            monitor.record_state_with_filter(simulation.time(), simulation.system(), species_to_record);
+
+            let summary = simulation.run_until(conditions, &mut monitor, species_to_record);
+
+            Replicate {
+                index,
+                system: simulation.system().clone(),
+                monitor,
+                summary
+            }
+        })
+    }
+
+    /// Calls `branch` with the index and a `Simulation` at the fork state of every branch,
+    /// for continuations that need more than `run`. Results come back in branch order.
+    pub fn run_with<R, F>(&self, branch: F) -> Vec<R>
+    where
+        R: Send,
+        F: Fn(usize, &mut Simulation<'_>) -> R + Sync
+    {
+        self.ensemble.run_with(|index, mut system, mut rng| {
+            let mut visitor = self.state.algorithm.visitor();
+            let mut simulation = Simulation::restore(&mut system, visitor.as_mut(), &mut rng, &self.state)
+                .expect("fork state was restored when the fork was made");
+
+            simulation.visitor.reset();
+
+            branch(index, &mut simulation)
+        })
+    }
+}
@@ -0,0 +1,113 @@
+use std::sync::Arc;
+use rand::Rng;
+use rand_chacha::ChaCha12Rng;
+use rand_distr::StandardNormal;
+use crate::monitor::FilterableMonitor;
+use crate::ode::ContinuousTrajectory;
+use crate::reaction::SpeciesRole;
+use crate::system::ChemicalSystem;
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum LangevinMethod {
+    EulerMaruyama,
+    /// Milstein's method with the correction of each reaction channel on its own,
+    /// leaving out the cross terms between channels.
+    Milstein
+}
+
+/// What to do with a species amount that a step has taken below zero.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum NegativeAmounts {
+    /// Mirror the amount around zero.
+    Reflect,
+    /// Set the amount to zero.
+    Truncate
+}
+
+#[derive(Clone, Debug)]
+pub struct LangevinOptions {
+    pub method: LangevinMethod,
+    pub step: f64,
+    pub negative_amounts: NegativeAmounts
+}
+
+impl Default for LangevinOptions {
+    fn default() -> Self {
+        LangevinOptions {
+            method: LangevinMethod::EulerMaruyama,
+            step: 0.01,
+            negative_amounts: NegativeAmounts::Truncate
+        }
+    }
+}
+
+impl ChemicalSystem {
+    /// Integrates the chemical Langevin equation with a fixed step from the current species
+    /// quantities up to `end_time`, leaving the system as it is. Like `solve_ode`, the returned
+    /// trajectory holds the exact amounts and `monitor` gets every step rounded to molecule
+    /// counts.
+    pub fn solve_cle(&self,
+                     end_time: f64,
+                     options: &LangevinOptions,
+                     rng: &mut ChaCha12Rng,
+                     monitor: &mut dyn FilterableMonitor<ChemicalSystem>,
+                     species_to_record: &[(&str, SpeciesRole)]) -> ContinuousTrajectory {
+        let model = Arc::clone(&self.model);
+
+        let mut amounts: Vec<f64> = self.quantities.iter().map(|&quantity| quantity as f64).collect();
+        let mut increments = vec![0.0; amounts.len()];
+        let mut time = 0.0;
+
+        let mut trajectory = ContinuousTrajectory::new(self);
+        let mut rounded = self.clone();
+
+        trajectory.record(time, &amounts, &mut rounded, monitor, species_to_record);
+
+        while time < end_time {
+            let step = options.step.min(end_time - time);
+
+            increments.iter_mut().for_each(|increment| *increment = 0.0);
+
+            for (reaction, changes) in model.changes.iter().enumerate() {
+                let propensity = model.propensity(reaction, &amounts).max(0.0);
+
+                if propensity == 0.0 {
+                    continue;
+                }
+
+                let wiener: f64 = step.sqrt() * rng.sample::<f64, _>(StandardNormal);
+                let mut firings = propensity * step + propensity.sqrt() * wiener;
+
+                if options.method == LangevinMethod::Milstein {
+                    // The derivative of the noise coefficient along its own channel
+                    let directional_derivative: f64 = changes.iter()
+                        .map(|&(species, change)| change as f64 * model.propensity_derivative(reaction, species, &amounts))
+                        .sum();
+
+                    firings += 0.25 * directional_derivative * (wiener * wiener - step);
+                }
+
+                for &(species, change) in changes {
+                    increments[species] += change as f64 * firings;
+                }
+            }
+
+            for (amount, increment) in amounts.iter_mut().zip(&increments) {
+                *amount += increment;
+
+                if *amount < 0.0 {
+                    *amount = match options.negative_amounts {
+                        NegativeAmounts::Reflect => -*amount,
+                        NegativeAmounts::Truncate => 0.0
+                    };
+                }
+            }
+
+            time += step;
+
+            trajectory.record(time, &amounts, &mut rounded, monitor, species_to_record);
+        }
+
+        trajectory
+    }
+}
@@ -0,0 +1,28 @@
+pub mod system;
+pub mod reaction;
+pub mod species;
+pub mod visitor;
+pub mod symbol_table;
+pub mod monitor_trait;
+pub mod plotter;
+pub mod monitor;
+pub mod dependency_graph;
+pub mod priority_queue;
+pub mod model;
+pub mod tau_leaping;
+pub mod ode;
+pub mod langevin;
+pub mod parser;
+pub mod sbml;
+pub mod ensemble;
+pub mod sampling;
+pub mod statistics;
+pub mod csv;
+#[cfg(feature = "arrow")]
+pub mod columnar;
+pub mod firing_log;
+pub mod stopping;
+pub mod first_passage;
+pub mod simulation;
+pub mod checkpoint;
+pub mod fork;
@@ -0,0 +1,197 @@
+use std::collections::HashMap;
+use std::sync::{Arc, Mutex};
+use uuid::Uuid;
+use crate::dependency_graph::DependencyGraph;
+use crate::reaction::{group_coefficients, Reaction};
+use crate::species::Species;
+
+/// A reaction network compiled into index based form. Species and reactions are numbered
+/// in the order they first appear, and the quantities of a trajectory are kept outside
+/// the model in a plain `Vec<i32>`, so one model can be shared by any number of them.
+#[derive(Clone, Debug)]
+pub struct Model {
+    pub(crate) species: Vec<String>,
+    pub(crate) reaction_ids: Vec<Uuid>,
+    pub(crate) formulas: Vec<String>,
+    pub(crate) lambdas: Vec<f64>,
+    // Species index and how many of it each reaction consumes
+    pub(crate) reactants: Vec<Vec<(usize, u32)>>,
+    // Species index and how many of it each reaction produces
+    pub(crate) products: Vec<Vec<(usize, u32)>>,
+    // Species index and the net change in its quantity when each reaction fires
+    pub(crate) changes: Vec<Vec<(usize, i32)>>,
+    pub(crate) dependency_graph: DependencyGraph
+}
+
+impl Model {
+    /// Compiles a list of reactions, returning the model and the current quantities of its species.
+    pub fn compile<'a>(reactions: impl IntoIterator<Item = &'a Arc<Mutex<Reaction>>>) -> (Model, Vec<i32>) {
+        let mut species = Vec::new();
+        let mut quantities = Vec::new();
+        let mut species_indices: HashMap<*const Mutex<Species>, usize> = HashMap::new();
+
+        let mut index_of = |species_arc: &Arc<Mutex<Species>>| {
+            *species_indices.entry(Arc::as_ptr(species_arc)).or_insert_with(|| {
+                let species_guard = species_arc.lock().unwrap();
+
+                species.push(species_guard.name.clone());
+                quantities.push(species_guard.quantity);
+                species.len() - 1
+            })
+        };
+
+        let mut reaction_ids = Vec::new();
+        let mut formulas = Vec::new();
+        let mut lambdas = Vec::new();
+        let mut reactants = Vec::new();
+        let mut products = Vec::new();
+        let mut changes = Vec::new();
+
+        for reaction in reactions {
+            let reaction_guard = reaction.lock().unwrap();
+
+            let consumed = group_coefficients(reaction_guard.reactants.iter().map(&mut index_of), usize::eq);
+            let produced = group_coefficients(reaction_guard.products.iter().map(&mut index_of), usize::eq);
+
+            let mut change: Vec<(usize, i32)> = consumed.iter()
+                .map(|&(species, count)| (species, -(count as i32)))
+                .collect();
+
+            for &(species, count) in &produced {
+                match change.iter_mut().find(|(species_index, _)| *species_index == species) {
+                    Some((_, total)) => *total += count as i32,
+                    None => change.push((species, count as i32))
+                }
+            }
+
+            change.retain(|(_, amount)| *amount != 0);
+
+            reaction_ids.push(reaction_guard.uuid);
+            formulas.push(reaction_guard.formula.clone());
+            lambdas.push(reaction_guard.lambda);
+            reactants.push(consumed);
+            products.push(produced);
+            changes.push(change);
+        }
+
+        let dependency_graph = DependencyGraph::new(species.len(), &reactants, &changes);
+
+        let model = Model { species, reaction_ids, formulas, lambdas, reactants, products, changes, dependency_graph };
+
+        (model, quantities)
+    }
+
+    pub fn species(&self) -> &[String] {
+        &self.species
+    }
+
+    pub fn species_index(&self, name: &str) -> Option<usize> {
+        self.species.iter().position(|species| species == name)
+    }
+
+    pub fn reaction_count(&self) -> usize {
+        self.reaction_ids.len()
+    }
+
+    pub fn reaction_id(&self, reaction: usize) -> Uuid {
+        self.reaction_ids[reaction]
+    }
+
+    pub fn formula(&self, reaction: usize) -> &str {
+        &self.formulas[reaction]
+    }
+
+    /// Reactions whose propensity may change when `reaction` fires, including itself.
+    pub fn dependents(&self, reaction: usize) -> &[usize] {
+        self.dependency_graph.dependents(reaction)
+    }
+
+    pub fn can_fire(&self, reaction: usize, quantities: &[i32]) -> bool {
+        self.reactants[reaction].iter()
+            .all(|&(species, count)| quantities[species] >= count as i32)
+    }
+
+    pub fn fire(&self, reaction: usize, quantities: &mut [i32]) {
+        for &(species, change) in &self.changes[reaction] {
+            quantities[species] += change;
+        }
+    }
+
+    /// Mass action propensity of a reaction, counting the distinct combinations of reactant
+    /// molecules, so `2A` contributes `A * (A - 1) / 2` rather than `A * A`.
+    pub fn propensity<T: Copy + Into<f64>>(&self, reaction: usize, amounts: &[T]) -> f64 {
+        self.evaluate(reaction, amounts, combinations)
+    }
+
+    /// Partial derivative of a reaction's propensity with respect to the amount of one species.
+    pub fn propensity_derivative(&self, reaction: usize, species: usize, amounts: &[f64]) -> f64 {
+        self.differentiate(reaction, species, amounts, combinations, combinations_derivative)
+    }
+
+    /// Rate of a reaction in the reaction rate equations, the large population limit of its
+    /// propensity, where `2A` contributes `A^2 / 2` instead of `A * (A - 1) / 2`.
+    pub fn rate(&self, reaction: usize, amounts: &[f64]) -> f64 {
+        self.evaluate(reaction, amounts, power)
+    }
+
+    pub fn rate_derivative(&self, reaction: usize, species: usize, amounts: &[f64]) -> f64 {
+        self.differentiate(reaction, species, amounts, power, power_derivative)
+    }
+
+    fn evaluate<T: Copy + Into<f64>>(&self, reaction: usize, amounts: &[T], term: fn(f64, u32) -> f64) -> f64 {
+        self.reactants[reaction].iter()
+            .fold(self.lambdas[reaction], |value, &(species, count)| value * term(amounts[species].into(), count))
+    }
+
+    fn differentiate(&self,
+                     reaction: usize,
+                     species: usize,
+                     amounts: &[f64],
+                     term: fn(f64, u32) -> f64,
+                     derivative: fn(f64, u32) -> f64) -> f64 {
+        let reactants = &self.reactants[reaction];
+
+        if !reactants.iter().any(|&(reactant, _)| reactant == species) {
+            return 0.0;
+        }
+
+        reactants.iter().fold(self.lambdas[reaction], |value, &(reactant, count)| {
+            if reactant == species {
+                value * derivative(amounts[reactant], count)
+            } else {
+                value * term(amounts[reactant], count)
+            }
+        })
+    }
+}
+
+/// Number of ways to pick `count` molecules out of `quantity`, zero when there are too few.
+pub(crate) fn combinations(quantity: f64, count: u32) -> f64 {
+    (0..count).fold(1.0, |combinations, k| {
+        combinations * (quantity - k as f64).max(0.0) / (k + 1) as f64
+    })
+}
+
+fn combinations_derivative(amount: f64, count: u32) -> f64 {
+    // Product rule over the falling factorial amount * (amount - 1) * ... / count!
+    let factorial: f64 = (1..=count).map(|k| k as f64).product();
+
+    (0..count).map(|skipped| {
+        (0..count)
+            .filter(|&k| k != skipped)
+            .map(|k| amount - k as f64)
+            .product::<f64>()
+    }).sum::<f64>() / factorial
+}
+
+fn power(amount: f64, count: u32) -> f64 {
+    let factorial: f64 = (1..=count).map(|k| k as f64).product();
+
+    amount.powi(count as i32) / factorial
+}
+
+fn power_derivative(amount: f64, count: u32) -> f64 {
+    let factorial: f64 = (1..count).map(|k| k as f64).product();
+
+    amount.powi(count as i32 - 1) / factorial
+}
@@ -1,99 +1,114 @@
 use std::collections::HashMap;
-use std::sync::{Arc, Mutex};
-use crate::plotter::plot;
-use crate::reaction::{Reaction, SpeciesRole};
-use crate::species::Species;
+use std::sync::Arc;
+use crate::checkpoint::{CheckpointError, ResumableMonitor, StateReader, StateWriter};
+use crate::model::Model;
+use crate::plotter::{plot, PlotError, PlotOptions};
+use crate::reaction::SpeciesRole;
+use crate::system::ChemicalSystem;
 
 pub trait Monitor<T> {
     fn record_state(&mut self, time: f64, state: &T);
+
+    /// Called for every reaction that fired in the step ending at `time`, before the state
+    /// after the step is recorded. `reaction` indexes the model, and `count` is above one
+    /// only for leaps. Monitors that only look at states can ignore it.
+    fn record_firing(&mut self, _time: f64, _state: &T, _reaction: usize, _count: u32) {}
 }
 
 pub trait FilterableMonitor<T>: Monitor<T> {
     fn record_state_with_filter(&mut self, time: f64, state: &T, species_to_record: &[(&str, SpeciesRole)]);
 }
 
+// A pair of monitors records into both, e.g. species with one and firings with the other
+impl<T, A: Monitor<T>, B: Monitor<T>> Monitor<T> for (A, B) {
+    fn record_state(&mut self, time: f64, state: &T) {
+        self.0.record_state(time, state);
+        self.1.record_state(time, state);
+    }
+
+    fn record_firing(&mut self, time: f64, state: &T, reaction: usize, count: u32) {
+        self.0.record_firing(time, state, reaction, count);
+        self.1.record_firing(time, state, reaction, count);
+    }
+}
+
+impl<T, A: FilterableMonitor<T>, B: FilterableMonitor<T>> FilterableMonitor<T> for (A, B) {
+    fn record_state_with_filter(&mut self, time: f64, state: &T, species_to_record: &[(&str, SpeciesRole)]) {
+        self.0.record_state_with_filter(time, state, species_to_record);
+        self.1.record_state_with_filter(time, state, species_to_record);
+    }
+}
+
+#[derive(Clone)]
 pub enum SnapshotData{
-    Reactions(Vec<Arc<Mutex<Reaction>>>),
+    /// Quantity of every species in the model, indexed like `Model::species`.
+    Quantities(Arc<Model>, Vec<i32>),
     SpeciesEvents(Vec<SpeciesEvents>),
 }
+#[derive(Clone)]
 pub struct SpeciesEvents {
     pub species_name: String,
     pub new_quantity: i32
 }
 
 #[derive(Clone)]
-pub struct SystemStateSnapshot<T> {
+pub struct SystemStateSnapshot {
     pub time: f64,
     pub data: SnapshotData
 }
 
 #[derive(Clone)]
 pub struct DefaultMonitor {
-    pub history: Vec<SystemStateSnapshot<f64>>
+    pub history: Vec<SystemStateSnapshot>,
+    recent_quantities: HashMap<String, i32>,
+    // Snapshots already in the monitor journal of a checkpointed run
+    saved: usize
 }
 
 impl DefaultMonitor {
     pub fn new() -> Self {
         DefaultMonitor {
-            history: Vec::new()
+            history: Vec::new(),
+            recent_quantities: HashMap::new(),
+            saved: 0
         }
     }
 
-    pub fn extract_plot_data(&self, species_to_plot: &[(&str, SpeciesRole)]) -> Option<Vec<SystemStateSnapshot<Reaction>>> {
-        // Iterate over each snapshot in the history to create a new vector of snapshots,
-        // but only including the reactions that involve the specified species in the specified role(s).
-        let filtered_snapshots: Vec<_> = self.history.iter().map(|snapshot| {
-
-            // For each snapshot, iterate over its reactions and retain only those that
-            // involve one of the specified species in the specified role(s).
-            let selected_reactions: Vec<_> = match &snapshot.data {
-                SnapshotData::Reactions(reactions) => {
-                    reactions.iter()
-                        .filter(|reaction| {
-                            let reaction_guard = reaction.lock().unwrap();
-
-                            species_to_plot.iter().any(|(name, role)| {
-                                // Check if the species is in the reactants of the reaction, but only if
-                                // the role we're checking for is Reactant or Both.
-                                let in_reactants = if let SpeciesRole::Reactant | SpeciesRole::Both = role {
-                                    reaction_guard.reactants.iter().any(|species| {
-                                        let species_guard = species.lock().unwrap();
-                                        &species_guard.name == name
-                                    })
-                                } else {
-                                    false
-                                };
-
-                                // Check if the species is in the products of the reaction, but only if
-                                // the role we're checking for is Product or Both.
-                                let in_products = if let SpeciesRole::Product | SpeciesRole::Both = role {
-                                    reaction_guard.products.iter().any(|species| {
-                                        let species_guard = species.lock().unwrap();
-                                        &species_guard.name == name
-                                    })
-                                } else {
-                                    false
-                                };
-
-                                // If the species-role pair matched either a reactant or a product in the reaction,
-                                // we will include this reaction in our output.
-                                in_reactants || in_products
-                            })
-                        }).cloned().collect()
-                },
-                _ => vec![]
+    pub fn extract_plot_data(&self, species_to_plot: &[(&str, SpeciesRole)]) -> Option<Vec<SystemStateSnapshot>> {
+        let is_plotted = |name: &str| species_to_plot.iter().any(|(species, _role)| *species == name);
+
+        // Reduce every snapshot to events for the requested species, whatever the role
+        let filtered_snapshots: Vec<_> = self.history.iter().filter_map(|snapshot| {
+            let events: Vec<_> = match &snapshot.data {
+                SnapshotData::Quantities(model, quantities) => {
+                    model.species.iter().zip(quantities)
+                        .filter(|(name, _)| is_plotted(name))
+                        .map(|(name, &quantity)| SpeciesEvents {
+                            species_name: name.clone(),
+                            new_quantity: quantity
+                        })
+                        .collect()
+                }
+                SnapshotData::SpeciesEvents(events) => {
+                    events.iter()
+                        .filter(|event| is_plotted(&event.species_name))
+                        .cloned()
+                        .collect()
+                }
             };
 
-            // Create a new snapshot using the filtered list of reactions and the time from the original snapshot.
-            SystemStateSnapshot {
-                time: snapshot.time,
-                data: SnapshotData::Reactions(selected_reactions)
+            if events.is_empty() {
+                None
+            } else {
+                Some(SystemStateSnapshot {
+                    time: snapshot.time,
+                    data: SnapshotData::SpeciesEvents(events)
+                })
             }
         })
             .collect();
 
-        // If we did not find any reactions that match the specified species-role pairs in any snapshot,
-        // return None. Otherwise, return the new list of filtered snapshots.
+        // If none of the specified species appear in any snapshot, return None.
         if filtered_snapshots.is_empty() {
             None
         } else {
@@ -101,59 +116,34 @@ impl DefaultMonitor {
         }
     }
 
-    pub fn visualize_data(&self, species_to_plot: &[(&str, SpeciesRole)]) {
-        if let Some(data_to_plot) = self.extract_plot_data(species_to_plot) {
-            plot(&data_to_plot, &species_to_plot);
-        } else {
-            println!("No data to log");
-        }
+    /// Plots the history with the default `PlotOptions`.
+    pub fn visualize_data(&self, species_to_plot: &[(&str, SpeciesRole)]) -> Result<(), PlotError> {
+        self.visualize_data_with_options(species_to_plot, &PlotOptions::default())
+    }
+
+    pub fn visualize_data_with_options(&self, species_to_plot: &[(&str, SpeciesRole)], options: &PlotOptions) -> Result<(), PlotError> {
+        let data_to_plot = self.extract_plot_data(species_to_plot).ok_or(PlotError::NoData)?;
+
+        plot(&data_to_plot, species_to_plot, options)
     }
 
-    pub fn merge(&mut self, other: DefaultMonitor, species_to_plot: &[(&str, SpeciesRole)]) {
+    /// Appends the history of another run. To average over replicates, sample them with
+    /// `SamplingMonitor` and combine them with `statistics::EnsembleStatistics` instead.
+    pub fn merge(&mut self, other: DefaultMonitor, _species_to_plot: &[(&str, SpeciesRole)]) {
         self.history.extend(other.history);
     }
 }
 
-impl Monitor<Vec<Arc<Mutex<Reaction>>>> for DefaultMonitor {
-    fn record_state(&mut self, time: f64, reactions: &Vec<Arc<Mutex<Reaction>>>) {
-        let reaction_data: Vec<_> = reactions.iter().map(|reaction_arc| {
-                let reaction = reaction_arc.lock().unwrap();
-
-                // Create new Species instances to hold the current state
-                let reactants = reaction.reactants.iter()
-                    .map(|species_arc| {
-                        let species = species_arc.lock().unwrap();
-                        Arc::new(Mutex::new(Species {
-                            name: species.name.clone(),
-                            quantity: species.quantity
-                        }))
-                    })
-                    .collect();
-
-                let products = reaction.products.iter()
-                    .map(|species_arc| {
-                        let species = species_arc.lock().unwrap();
-                        Arc::new(Mutex::new(Species {
-                            name: species.name.clone(),
-                            quantity: species.quantity
-                        }))
-                    })
-                    .collect();
-
-                // Create a new Reaction instance to hold the current state
-                Arc::new(Mutex::new(Reaction {
-                    reactants,
-                    products,
-                    delay: reaction.delay,
-                    lambda: reaction.lambda,
-                    uuid: reaction.uuid,
-                    formula: reaction.formula.clone()
-                }))
-            })
-                .collect();
-
+impl Default for DefaultMonitor {
+    fn default() -> Self {
+        Self::new()
+    }
+}
 
-        let snapshot_data = SnapshotData::Reactions(reaction_data);
+impl Monitor<ChemicalSystem> for DefaultMonitor {
+    fn record_state(&mut self, time: f64, system: &ChemicalSystem) {
+        // The model is shared, only the quantities are copied
+        let snapshot_data = SnapshotData::Quantities(Arc::clone(&system.model), system.quantities.clone());
         let snapshot = SystemStateSnapshot {
             time,
             data: snapshot_data
@@ -163,42 +153,27 @@ impl Monitor<Vec<Arc<Mutex<Reaction>>>> for DefaultMonitor {
     }
 }
 
-impl FilterableMonitor<Vec<Arc<Mutex<Reaction>>>> for DefaultMonitor {
-    fn record_state_with_filter(&mut self, time: f64, reactions: &Vec<Arc<Mutex<Reaction>>>, species_to_record: &[(&str, SpeciesRole)]) {
+impl FilterableMonitor<ChemicalSystem> for DefaultMonitor {
+    fn record_state_with_filter(&mut self, time: f64, system: &ChemicalSystem, species_to_record: &[(&str, SpeciesRole)]) {
         let mut events = Vec::new();
 
-        if let Some(SystemStateSnapshot { data: SnapshotData::Reactions(recent_reactions), .. }) = self.history.last() {
-            let mut recent_species_map = HashMap::new();
-
-            for recent_reaction in recent_reactions {
-                let recent_reaction_guard = recent_reaction.lock().unwrap();
-
-                for recent_species in recent_reaction_guard.reactants.iter()
-                    .chain(recent_reaction_guard.products.iter()) {
-                    let recent_species_guard = recent_species.lock().unwrap();
-
-                    recent_species_map.insert(&recent_species_guard.name, &recent_species_guard.quantity);
-                }
+        for (name, &quantity) in system.model.species.iter().zip(&system.quantities) {
+            if !species_to_record.iter().any(|(species, _role)| *species == name.as_str()) {
+                continue;
             }
 
-            for reaction_arc in reactions {
-                let reaction_guard = reaction_arc.lock().unwrap();
-
-                for current_species_arc in reaction_guard.reactants.iter().chain(reaction_guard.products.iter()) {
-                    let current_species_guard = current_species_arc.lock().unwrap();
-
-                    if species_to_record.iter().any(|(name, _role)| name == current_species_guard.name.as_str()) {
-                        if let Some(&recent_quantity) = recent_species_map.get(&current_species_guard.name) {
-                            if recent_quantity != current_species_guard.quantity {
-                                events.push(SpeciesEvents {
-                                    species_name: current_species_guard.name.clone(),
-                                    new_quantity: current_species_guard.quantity
-                                })
-                            }
-                        }
-                    }
+            match self.recent_quantities.get_mut(name) {
+                Some(recent_quantity) if *recent_quantity == quantity => continue,
+                Some(recent_quantity) => *recent_quantity = quantity,
+                None => {
+                    self.recent_quantities.insert(name.clone(), quantity);
                 }
             }
+
+            events.push(SpeciesEvents {
+                species_name: name.clone(),
+                new_quantity: quantity
+            })
         }
 
         if !events.is_empty() {
@@ -211,4 +186,113 @@ impl FilterableMonitor<Vec<Arc<Mutex<Reaction>>>> for DefaultMonitor {
             self.history.push(snapshot)
         }
     }
-}
\ No newline at end of file
+}
+
+impl ResumableMonitor for DefaultMonitor {
+    /// Snapshots of whole states are taken to be of the system the monitor is restored for.
+    fn save_state(&self, state: &mut StateWriter) {
+        self.write_history(state, 0);
+    }
+
+    fn save_changes(&mut self, state: &mut StateWriter, from_start: bool) {
+        // History that was taken out since the last checkpoint is written again in full
+        let from = if from_start || self.saved > self.history.len() { 0 } else { self.saved };
+
+        self.write_history(state, from);
+        self.saved = self.history.len();
+    }
+
+    fn restore_state(&mut self, state: &mut StateReader, system: &ChemicalSystem) -> Result<(), CheckpointError> {
+        state.expect_tag("default monitor")?;
+
+        let replaces = state.read_bool()?;
+        let snapshot_count = state.read_usize()?;
+        let mut history = Vec::new();
+
+        for _ in 0..snapshot_count {
+            let time = state.read_f64()?;
+
+            let data = if state.read_bool()? {
+                let quantities = state.read_i32s()?;
+
+                if quantities.len() != system.model.species.len() {
+                    return Err(CheckpointError::Corrupt("snapshot does not match the species".to_string()));
+                }
+
+                SnapshotData::Quantities(Arc::clone(&system.model), quantities)
+            } else {
+                let event_count = state.read_usize()?;
+                let events = (0..event_count)
+                    .map(|_| Ok(SpeciesEvents {
+                        species_name: state.read_str()?,
+                        new_quantity: state.read_i32()?
+                    }))
+                    .collect::<Result<Vec<_>, CheckpointError>>()?;
+
+                SnapshotData::SpeciesEvents(events)
+            };
+
+            history.push(SystemStateSnapshot { time, data });
+        }
+
+        let recent_count = state.read_usize()?;
+        let mut recent_quantities = HashMap::new();
+
+        for _ in 0..recent_count {
+            let name = state.read_str()?;
+            recent_quantities.insert(name, state.read_i32()?);
+        }
+
+        if replaces {
+            self.history = history;
+        } else {
+            self.history.extend(history);
+        }
+
+        self.recent_quantities = recent_quantities;
+        self.saved = self.history.len();
+
+        Ok(())
+    }
+}
+
+impl DefaultMonitor {
+    // The history from snapshot `from` on, which replaces the restored history if it starts
+    // at the beginning and is added to it otherwise
+    fn write_history(&self, state: &mut StateWriter, from: usize) {
+        state.write_str("default monitor");
+        state.write_bool(from == 0);
+        state.write_usize(self.history.len() - from);
+
+        for snapshot in &self.history[from..] {
+            state.write_f64(snapshot.time);
+
+            match &snapshot.data {
+                SnapshotData::Quantities(_, quantities) => {
+                    state.write_bool(true);
+                    state.write_i32s(quantities);
+                }
+                SnapshotData::SpeciesEvents(events) => {
+                    state.write_bool(false);
+                    state.write_usize(events.len());
+
+                    for event in events {
+                        state.write_str(&event.species_name);
+                        state.write_i32(event.new_quantity);
+                    }
+                }
+            }
+        }
+
+        // Sorted so the same recordings always give the same bytes
+        let mut recent_quantities: Vec<_> = self.recent_quantities.iter().collect();
+        recent_quantities.sort();
+
+        state.write_usize(recent_quantities.len());
+
+        for (name, &quantity) in recent_quantities {
+            state.write_str(name);
+            state.write_i32(quantity);
+        }
+    }
+}
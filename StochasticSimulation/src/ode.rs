@@ -0,0 +1,425 @@
+use std::fmt;
+use std::sync::Arc;
+use crate::model::Model;
+use crate::monitor::FilterableMonitor;
+use crate::reaction::SpeciesRole;
+use crate::system::ChemicalSystem;
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum OdeMethod {
+    /// Explicit adaptive Runge-Kutta 5(4) pair.
+    DormandPrince,
+    /// Linearly implicit Rosenbrock 2(3) method for stiff networks.
+    Rosenbrock
+}
+
+#[derive(Clone, Debug)]
+pub struct OdeOptions {
+    pub method: OdeMethod,
+    pub relative_tolerance: f64,
+    pub absolute_tolerance: f64,
+    pub initial_step: Option<f64>,
+    pub max_step: f64,
+    /// Smallest step to try before giving up. Steps that no longer advance the time always
+    /// end the integration.
+    pub min_step: f64
+}
+
+impl Default for OdeOptions {
+    fn default() -> Self {
+        OdeOptions {
+            method: OdeMethod::DormandPrince,
+            relative_tolerance: 1e-6,
+            absolute_tolerance: 1e-8,
+            initial_step: None,
+            max_step: f64::INFINITY,
+            min_step: 0.0
+        }
+    }
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub enum OdeError {
+    /// No step of at least `OdeOptions::min_step` met the tolerances at `time`, usually because
+    /// the amounts grow without bound or stop being finite.
+    StepTooSmall { time: f64, step: f64 }
+}
+
+impl fmt::Display for OdeError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            OdeError::StepTooSmall { time, step } =>
+                write!(f, "step size {:e} at time {} is too small to meet the tolerances", step, time)
+        }
+    }
+}
+
+impl std::error::Error for OdeError {}
+
+/// The amounts of every species at each accepted step of a continuous solver.
+#[derive(Clone, Debug)]
+pub struct ContinuousTrajectory {
+    pub species: Vec<String>,
+    pub times: Vec<f64>,
+    pub amounts: Vec<Vec<f64>>
+}
+
+impl ContinuousTrajectory {
+    pub(crate) fn new(system: &ChemicalSystem) -> Self {
+        ContinuousTrajectory {
+            species: system.model.species.clone(),
+            times: Vec::new(),
+            amounts: Vec::new()
+        }
+    }
+
+    // Adds a step, and hands it to `monitor` with the amounts rounded to molecule counts.
+    // They are rounded into `rounded`, a copy of the solved system, which keeps its quantities.
+    pub(crate) fn record(&mut self,
+                         time: f64,
+                         amounts: &[f64],
+                         rounded: &mut ChemicalSystem,
+                         monitor: &mut dyn FilterableMonitor<ChemicalSystem>,
+                         species_to_record: &[(&str, SpeciesRole)]) {
+        for (quantity, amount) in rounded.quantities.iter_mut().zip(amounts) {
+            *quantity = amount.round() as i32;
+        }
+
+        monitor.record_state_with_filter(time, rounded, species_to_record);
+
+        self.times.push(time);
+        self.amounts.push(amounts.to_vec());
+    }
+}
+
+/// Mass action reaction rate equations of a `ChemicalSystem`, dx/dt = N a(x).
+#[derive(Clone)]
+pub struct RateEquations {
+    model: Arc<Model>
+}
+
+impl RateEquations {
+    pub fn new(system: &ChemicalSystem) -> Self {
+        RateEquations { model: Arc::clone(&system.model) }
+    }
+
+    pub fn dimension(&self) -> usize {
+        self.model.species.len()
+    }
+
+    pub fn derivative(&self, amounts: &[f64], derivative: &mut [f64]) {
+        derivative.iter_mut().for_each(|value| *value = 0.0);
+
+        for (reaction, changes) in self.model.changes.iter().enumerate() {
+            let rate = self.model.rate(reaction, amounts);
+
+            for &(species, change) in changes {
+                derivative[species] += change as f64 * rate;
+            }
+        }
+    }
+
+    pub fn jacobian(&self, amounts: &[f64]) -> Vec<Vec<f64>> {
+        let dimension = self.dimension();
+        let mut jacobian = vec![vec![0.0; dimension]; dimension];
+
+        for (reaction, reactants) in self.model.reactants.iter().enumerate() {
+            for &(species, _) in reactants {
+                let partial = self.model.rate_derivative(reaction, species, amounts);
+
+                for &(changed, change) in &self.model.changes[reaction] {
+                    jacobian[changed][species] += change as f64 * partial;
+                }
+            }
+        }
+
+        jacobian
+    }
+}
+
+impl ChemicalSystem {
+    /// Integrates the reaction rate equations from the current species quantities up to
+    /// `end_time`, leaving the system as it is. The returned trajectory holds the exact
+    /// amounts. Each accepted step is also passed to `monitor` as a copy of the system with
+    /// the amounts rounded to molecule counts, so the result can be plotted next to
+    /// stochastic trajectories.
+    ///
+    /// Fails if the step size has to shrink below `OdeOptions::min_step`, and `monitor` is
+    /// left with the steps up to there.
+    pub fn solve_ode(&self,
+                     end_time: f64,
+                     options: &OdeOptions,
+                     monitor: &mut dyn FilterableMonitor<ChemicalSystem>,
+                     species_to_record: &[(&str, SpeciesRole)]) -> Result<ContinuousTrajectory, OdeError> {
+        let equations = RateEquations::new(self);
+
+        let initial: Vec<f64> = self.quantities.iter().map(|&quantity| quantity as f64).collect();
+
+        let mut trajectory = ContinuousTrajectory::new(self);
+        let mut rounded = self.clone();
+
+        let mut record = |time: f64, amounts: &[f64]| {
+            trajectory.record(time, amounts, &mut rounded, monitor, species_to_record);
+        };
+
+        record(0.0, &initial);
+
+        match options.method {
+            OdeMethod::DormandPrince => dormand_prince(&equations, initial, end_time, options, &mut record)?,
+            OdeMethod::Rosenbrock => rosenbrock(&equations, initial, end_time, options, &mut record)?
+        }
+
+        Ok(trajectory)
+    }
+}
+
+fn error_norm(error: &[f64], previous: &[f64], next: &[f64], options: &OdeOptions) -> f64 {
+    let sum: f64 = error.iter().zip(previous).zip(next)
+        .map(|((error, previous), next)| {
+            let scale = options.absolute_tolerance + options.relative_tolerance * previous.abs().max(next.abs());
+            (error / scale).powi(2)
+        })
+        .sum();
+
+    (sum / error.len().max(1) as f64).sqrt()
+}
+
+fn initial_step(equations: &RateEquations, amounts: &[f64], end_time: f64, options: &OdeOptions) -> f64 {
+    if let Some(step) = options.initial_step {
+        return step;
+    }
+
+    let mut derivative = vec![0.0; amounts.len()];
+    equations.derivative(amounts, &mut derivative);
+
+    let scale = error_norm(&derivative, amounts, amounts, options);
+
+    if scale > 0.0 {
+        (0.01 / scale).min(end_time).min(options.max_step)
+    } else {
+        (end_time * 1e-3).min(options.max_step)
+    }
+}
+
+// Step size factor from an error estimate of a method of the given order. An error that is
+// not a number shrinks the step as much as possible, like an infinite one.
+fn step_factor(error: f64, order: i32) -> f64 {
+    if error == 0.0 {
+        5.0
+    } else if error.is_nan() {
+        0.2
+    } else {
+        (0.9 * error.powf(-1.0 / (order + 1) as f64)).clamp(0.2, 5.0)
+    }
+}
+
+// Ends an integration whose next step is too small to be taken
+fn check_step(time: f64, step: f64, options: &OdeOptions) -> Result<(), OdeError> {
+    if step < options.min_step || time + step <= time {
+        Err(OdeError::StepTooSmall { time, step })
+    } else {
+        Ok(())
+    }
+}
+
+fn dormand_prince(equations: &RateEquations,
+                  mut amounts: Vec<f64>,
+                  end_time: f64,
+                  options: &OdeOptions,
+                  record: &mut dyn FnMut(f64, &[f64])) -> Result<(), OdeError> {
+    const A: [[f64; 6]; 7] = [
+        [0.0, 0.0, 0.0, 0.0, 0.0, 0.0],
+        [1.0 / 5.0, 0.0, 0.0, 0.0, 0.0, 0.0],
+        [3.0 / 40.0, 9.0 / 40.0, 0.0, 0.0, 0.0, 0.0],
+        [44.0 / 45.0, -56.0 / 15.0, 32.0 / 9.0, 0.0, 0.0, 0.0],
+        [19372.0 / 6561.0, -25360.0 / 2187.0, 64448.0 / 6561.0, -212.0 / 729.0, 0.0, 0.0],
+        [9017.0 / 3168.0, -355.0 / 33.0, 46732.0 / 5247.0, 49.0 / 176.0, -5103.0 / 18656.0, 0.0],
+        [35.0 / 384.0, 0.0, 500.0 / 1113.0, 125.0 / 192.0, -2187.0 / 6784.0, 11.0 / 84.0]
+    ];
+    // Fifth order weights minus the embedded fourth order weights
+    const E: [f64; 7] = [
+        71.0 / 57600.0, 0.0, -71.0 / 16695.0, 71.0 / 1920.0, -17253.0 / 339200.0, 22.0 / 525.0, -1.0 / 40.0
+    ];
+
+    let dimension = amounts.len();
+    let mut time = 0.0;
+    let mut step = initial_step(equations, &amounts, end_time, options);
+    let mut stages = vec![vec![0.0; dimension]; 7];
+    let mut stage_amounts = vec![0.0; dimension];
+    let mut error = vec![0.0; dimension];
+
+    equations.derivative(&amounts, &mut stages[0]);
+
+    while time < end_time {
+        step = step.min(end_time - time).min(options.max_step);
+        check_step(time, step, options)?;
+
+        for (stage, weights) in A.iter().enumerate().skip(1) {
+            for i in 0..dimension {
+                stage_amounts[i] = amounts[i] + step * weights.iter().zip(&stages).take(stage)
+                    .map(|(weight, derivative)| weight * derivative[i])
+                    .sum::<f64>();
+            }
+
+            let (_, rest) = stages.split_at_mut(stage);
+            equations.derivative(&stage_amounts, &mut rest[0]);
+        }
+
+        // The seventh stage is evaluated at the fifth order solution
+        for i in 0..dimension {
+            error[i] = step * (0..7).map(|j| E[j] * stages[j][i]).sum::<f64>();
+        }
+
+        let norm = error_norm(&error, &amounts, &stage_amounts, options);
+
+        if norm <= 1.0 {
+            time += step;
+            amounts.copy_from_slice(&stage_amounts);
+            stages.swap(0, 6);
+            record(time, &amounts);
+        }
+
+        step *= step_factor(norm, 4);
+    }
+
+    Ok(())
+}
+
+fn rosenbrock(equations: &RateEquations,
+              mut amounts: Vec<f64>,
+              end_time: f64,
+              options: &OdeOptions,
+              record: &mut dyn FnMut(f64, &[f64])) -> Result<(), OdeError> {
+    // Shampine and Reichelt's modified Rosenbrock triple, as used by MATLAB's ode23s
+    let d = 1.0 / (2.0 + 2.0_f64.sqrt());
+    let e32 = 6.0 + 2.0_f64.sqrt();
+
+    let dimension = amounts.len();
+    let mut time = 0.0;
+    let mut step = initial_step(equations, &amounts, end_time, options);
+
+    let mut f0 = vec![0.0; dimension];
+    let mut f1 = vec![0.0; dimension];
+    let mut f2 = vec![0.0; dimension];
+    let mut stage_amounts = vec![0.0; dimension];
+    let mut next = vec![0.0; dimension];
+    let mut error = vec![0.0; dimension];
+
+    while time < end_time {
+        step = step.min(end_time - time).min(options.max_step);
+        check_step(time, step, options)?;
+
+        equations.derivative(&amounts, &mut f0);
+        let jacobian = equations.jacobian(&amounts);
+
+        let mut w = jacobian;
+        for (i, row) in w.iter_mut().enumerate() {
+            for value in row.iter_mut() {
+                *value *= -step * d;
+            }
+            row[i] += 1.0;
+        }
+
+        let lu = match LuDecomposition::new(w) {
+            Some(lu) => lu,
+            None => {
+                step *= 0.5;
+                continue;
+            }
+        };
+
+        let k1 = lu.solve(&f0);
+
+        for i in 0..dimension {
+            stage_amounts[i] = amounts[i] + 0.5 * step * k1[i];
+        }
+        equations.derivative(&stage_amounts, &mut f1);
+
+        let rhs: Vec<f64> = (0..dimension).map(|i| f1[i] - k1[i]).collect();
+        let k2: Vec<f64> = lu.solve(&rhs).iter().zip(&k1).map(|(k, k1)| k + k1).collect();
+
+        for i in 0..dimension {
+            next[i] = amounts[i] + step * k2[i];
+        }
+        equations.derivative(&next, &mut f2);
+
+        let rhs: Vec<f64> = (0..dimension)
+            .map(|i| f2[i] - e32 * (k2[i] - f1[i]) - 2.0 * (k1[i] - f0[i]))
+            .collect();
+        let k3 = lu.solve(&rhs);
+
+        for i in 0..dimension {
+            error[i] = step / 6.0 * (k1[i] - 2.0 * k2[i] + k3[i]);
+        }
+
+        let norm = error_norm(&error, &amounts, &next, options);
+
+        if norm <= 1.0 {
+            time += step;
+            amounts.copy_from_slice(&next);
+            record(time, &amounts);
+        }
+
+        step *= step_factor(norm, 2);
+    }
+
+    Ok(())
+}
+
+// Dense LU decomposition with partial pivoting, for the small systems of the Rosenbrock method
+struct LuDecomposition {
+    lu: Vec<Vec<f64>>,
+    pivots: Vec<usize>
+}
+
+impl LuDecomposition {
+    fn new(mut matrix: Vec<Vec<f64>>) -> Option<Self> {
+        let n = matrix.len();
+        let mut pivots: Vec<usize> = (0..n).collect();
+
+        for column in 0..n {
+            let pivot = (column..n)
+                .max_by(|&a, &b| matrix[a][column].abs().total_cmp(&matrix[b][column].abs()))?;
+
+            if matrix[pivot][column] == 0.0 {
+                return None;
+            }
+
+            matrix.swap(column, pivot);
+            pivots.swap(column, pivot);
+
+            let pivot_row = matrix[column].clone();
+
+            for row in matrix.iter_mut().skip(column + 1) {
+                let factor = row[column] / pivot_row[column];
+                row[column] = factor;
+
+                for (value, pivot_value) in row.iter_mut().zip(&pivot_row).skip(column + 1) {
+                    *value -= factor * pivot_value;
+                }
+            }
+        }
+
+        Some(LuDecomposition { lu: matrix, pivots })
+    }
+
+    fn solve(&self, rhs: &[f64]) -> Vec<f64> {
+        let n = self.lu.len();
+        let mut x: Vec<f64> = self.pivots.iter().map(|&pivot| rhs[pivot]).collect();
+
+        for row in 0..n {
+            for k in 0..row {
+                x[row] -= self.lu[row][k] * x[k];
+            }
+        }
+
+        for row in (0..n).rev() {
+            for k in row + 1..n {
+                x[row] -= self.lu[row][k] * x[k];
+            }
+            x[row] /= self.lu[row][row];
+        }
+
+        x
+    }
+}
@@ -0,0 +1,432 @@
+//! Text format for reaction networks, for example
+//!
+//! ```text
+//! # Catalysed conversion of A into B
+//! species A = 100, B = 0
+//! species C = 1
+//! param k = 0.001
+//!
+//! A + C ->[k] B + C
+//! B ->[0.0005] 0
+//! ```
+//!
+//! Species and parameters must be declared before they are used, and every declared species
+//! has to take part in a reaction. A species can be preceded by a stoichiometric
+//! coefficient, as in `2A ->[k] A2`. A side of a reaction that is `0` or empty consumes or
+//! produces nothing. Everything after `#` is a comment.
+
+use std::collections::{HashMap, HashSet};
+use std::fmt;
+use std::str::FromStr;
+use std::sync::{Arc, Mutex};
+use crate::reaction::{Coefficients, Reaction};
+use crate::species::{Species, species_builder};
+use crate::system::ChemicalSystem;
+
+#[derive(Clone, Debug, PartialEq)]
+pub enum ParseErrorKind {
+    UnknownSpecies(String),
+    UnknownParameter(String),
+    DuplicateName(String),
+    /// A species is declared but takes part in no reaction.
+    UnusedSpecies(String),
+    MalformedRate(String),
+    MalformedQuantity(String),
+    MalformedCoefficient(String),
+    Unexpected { expected: &'static str, found: String }
+}
+
+/// Error with the 1-based line and column where it was found.
+#[derive(Clone, Debug, PartialEq)]
+pub struct ParseError {
+    pub line: usize,
+    pub column: usize,
+    pub kind: ParseErrorKind
+}
+
+impl fmt::Display for ParseError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}:{}: ", self.line, self.column)?;
+
+        match &self.kind {
+            ParseErrorKind::UnknownSpecies(name) => write!(f, "unknown species `{}`", name),
+            ParseErrorKind::UnknownParameter(name) => write!(f, "unknown parameter `{}`", name),
+            ParseErrorKind::DuplicateName(name) => write!(f, "`{}` is already declared", name),
+            ParseErrorKind::UnusedSpecies(name) => write!(f, "species `{}` is declared but takes part in no reaction", name),
+            ParseErrorKind::MalformedRate(rate) => write!(f, "malformed rate `{}`", rate),
+            ParseErrorKind::MalformedQuantity(quantity) => write!(f, "malformed initial quantity `{}`", quantity),
+            ParseErrorKind::MalformedCoefficient(coefficient) => write!(f, "malformed stoichiometric coefficient `{}`", coefficient),
+            ParseErrorKind::Unexpected { expected, found } => write!(f, "expected {}, found {}", expected, found)
+        }
+    }
+}
+
+impl std::error::Error for ParseError {}
+
+#[derive(Clone, Debug, PartialEq)]
+enum Token {
+    Identifier(String),
+    Number(String),
+    Plus,
+    Arrow,
+    LeftBracket,
+    RightBracket,
+    Equals,
+    Comma
+}
+
+impl Token {
+    fn describe(token: Option<&(Token, usize)>) -> String {
+        match token {
+            None => "end of line".to_string(),
+            Some((Token::Identifier(text), _)) | Some((Token::Number(text), _)) => format!("`{}`", text),
+            Some((Token::Plus, _)) => "`+`".to_string(),
+            Some((Token::Arrow, _)) => "`->`".to_string(),
+            Some((Token::LeftBracket, _)) => "`[`".to_string(),
+            Some((Token::RightBracket, _)) => "`]`".to_string(),
+            Some((Token::Equals, _)) => "`=`".to_string(),
+            Some((Token::Comma, _)) => "`,`".to_string()
+        }
+    }
+}
+
+// Splits a line into tokens, each with the 1-based column it starts at
+fn tokenize(line: &str, line_number: usize) -> Result<Vec<(Token, usize)>, ParseError> {
+    let chars: Vec<char> = line.chars().collect();
+    let mut tokens = Vec::new();
+    let mut position = 0;
+
+    while position < chars.len() {
+        let c = chars[position];
+        let column = position + 1;
+
+        let starts_number = c.is_ascii_digit() || c == '.'
+            || (c == '-' && chars.get(position + 1).is_some_and(|next| next.is_ascii_digit() || *next == '.'));
+
+        if c == '#' {
+            break;
+        } else if c.is_whitespace() {
+            position += 1;
+        } else if c.is_alphabetic() || c == '_' {
+            let start = position;
+            while position < chars.len() && continues_name(chars[position]) {
+                position += 1;
+            }
+            tokens.push((Token::Identifier(chars[start..position].iter().collect()), column));
+        } else if starts_number {
+            let start = position;
+            position += 1;
+            while position < chars.len() {
+                let current = chars[position];
+
+                // An exponent unless the letter starts a longer name, as in `2Ex`. One
+                // without digits, like the `1e` of `->[1e]`, is kept in the number so that
+                // it is reported as malformed, and `side` splits `2E` up again.
+                let exponent_length = match (chars.get(position + 1), chars.get(position + 2)) {
+                    (Some(next), _) if next.is_ascii_digit() => 2,
+                    (Some('-' | '+'), Some(digit)) if digit.is_ascii_digit() => 3,
+                    (Some('-' | '+'), after) if after.is_none_or(|after| !(*after == '>' || after.is_whitespace() || continues_name(*after))) => 2,
+                    (Some(next), _) if continues_name(*next) => 0,
+                    _ => 1
+                };
+
+                if current.is_ascii_digit() || current == '.' {
+                    position += 1;
+                } else if (current == 'e' || current == 'E') && exponent_length > 0 {
+                    position += exponent_length;
+                } else {
+                    break;
+                }
+            }
+            tokens.push((Token::Number(chars[start..position].iter().collect()), column));
+        } else if c == '-' && chars.get(position + 1) == Some(&'>') {
+            tokens.push((Token::Arrow, column));
+            position += 2;
+        } else {
+            let token = match c {
+                '+' => Token::Plus,
+                '[' => Token::LeftBracket,
+                ']' => Token::RightBracket,
+                '=' => Token::Equals,
+                ',' => Token::Comma,
+                _ => return Err(ParseError {
+                    line: line_number,
+                    column,
+                    kind: ParseErrorKind::Unexpected { expected: "a token", found: format!("`{}`", c) }
+                })
+            };
+            tokens.push((token, column));
+            position += 1;
+        }
+    }
+
+    Ok(tokens)
+}
+
+fn continues_name(c: char) -> bool {
+    c.is_alphanumeric() || c == '_'
+}
+
+struct Parser {
+    species: HashMap<String, Arc<Mutex<Species>>>,
+    // Declared species with the line and column of their declaration, in order
+    declarations: Vec<(String, usize, usize)>,
+    used_species: HashSet<String>,
+    parameters: HashMap<String, f64>,
+    reactions: Vec<Arc<Mutex<Reaction>>>
+}
+
+struct Line {
+    tokens: Vec<(Token, usize)>,
+    position: usize,
+    number: usize,
+    // Column just past the end of the line, reported when a token is missing
+    end_column: usize
+}
+
+impl Line {
+    fn peek(&self) -> Option<&(Token, usize)> {
+        self.tokens.get(self.position)
+    }
+
+    fn next(&mut self) -> Option<(Token, usize)> {
+        let token = self.tokens.get(self.position).cloned();
+        self.position += 1;
+        token
+    }
+
+    fn column(&self) -> usize {
+        self.peek().map(|(_, column)| *column).unwrap_or(self.end_column)
+    }
+
+    fn error(&self, column: usize, kind: ParseErrorKind) -> ParseError {
+        ParseError { line: self.number, column, kind }
+    }
+
+    fn unexpected(&self, expected: &'static str) -> ParseError {
+        self.error(self.column(), ParseErrorKind::Unexpected { expected, found: Token::describe(self.peek()) })
+    }
+
+    fn expect(&mut self, token: Token, expected: &'static str) -> Result<(), ParseError> {
+        match self.peek() {
+            Some((found, _)) if *found == token => {
+                self.position += 1;
+                Ok(())
+            }
+            _ => Err(self.unexpected(expected))
+        }
+    }
+
+    fn identifier(&mut self, expected: &'static str) -> Result<(String, usize), ParseError> {
+        match self.peek() {
+            Some((Token::Identifier(name), column)) => {
+                let result = (name.clone(), *column);
+                self.position += 1;
+                Ok(result)
+            }
+            _ => Err(self.unexpected(expected))
+        }
+    }
+
+    fn end(&self) -> Result<(), ParseError> {
+        match self.peek() {
+            None => Ok(()),
+            Some(_) => Err(self.unexpected("end of line"))
+        }
+    }
+}
+
+impl Parser {
+    fn declare(&self, line: &Line, name: &str, column: usize) -> Result<(), ParseError> {
+        if self.species.contains_key(name) || self.parameters.contains_key(name) {
+            Err(line.error(column, ParseErrorKind::DuplicateName(name.to_string())))
+        } else {
+            Ok(())
+        }
+    }
+
+    // species A = 100, B = 0
+    fn species_declaration(&mut self, line: &mut Line) -> Result<(), ParseError> {
+        loop {
+            let (name, column) = line.identifier("a species name")?;
+            self.declare(line, &name, column)?;
+            line.expect(Token::Equals, "`=`")?;
+
+            let quantity = match line.next() {
+                Some((Token::Number(text), column)) => text.parse::<i32>()
+                    .ok()
+                    .filter(|quantity| *quantity >= 0)
+                    .ok_or_else(|| line.error(column, ParseErrorKind::MalformedQuantity(text)))?,
+                _ => {
+                    line.position -= 1;
+                    return Err(line.unexpected("an initial quantity"));
+                }
+            };
+
+            self.species.insert(name.clone(), species_builder(&name, quantity));
+            self.declarations.push((name, line.number, column));
+
+            if line.peek().is_none() {
+                return Ok(());
+            }
+
+            line.expect(Token::Comma, "`,` or end of line")?;
+        }
+    }
+
+    // param k = 0.001
+    fn parameter_declaration(&mut self, line: &mut Line) -> Result<(), ParseError> {
+        let (name, column) = line.identifier("a parameter name")?;
+        self.declare(line, &name, column)?;
+        line.expect(Token::Equals, "`=`")?;
+
+        let value = self.rate(line)?;
+        line.end()?;
+
+        self.parameters.insert(name, value);
+        Ok(())
+    }
+
+    // A number or a parameter, that must be finite and not negative
+    fn rate(&self, line: &mut Line) -> Result<f64, ParseError> {
+        match line.next() {
+            Some((Token::Number(text), column)) => text.parse::<f64>()
+                .ok()
+                .filter(|rate| rate.is_finite() && *rate >= 0.0)
+                .ok_or_else(|| line.error(column, ParseErrorKind::MalformedRate(text))),
+            Some((Token::Identifier(name), column)) => self.parameters.get(&name)
+                .copied()
+                .ok_or_else(|| line.error(column, ParseErrorKind::UnknownParameter(name))),
+            _ => {
+                line.position -= 1;
+                Err(line.unexpected("a rate"))
+            }
+        }
+    }
+
+    // A + 2C, or 0 or nothing for no species
+    fn side(&mut self, line: &mut Line, terminator: Option<Token>) -> Result<Coefficients, ParseError> {
+        let mut species = Vec::new();
+
+        let at_end = |line: &Line| match (&terminator, line.peek()) {
+            (_, None) => true,
+            (Some(terminator), Some((token, _))) => token == terminator,
+            _ => false
+        };
+
+        if at_end(line) {
+            return Ok(species);
+        }
+
+        if let Some((Token::Number(text), _)) = line.peek() {
+            let coefficient_follows = matches!(line.tokens.get(line.position + 1), Some((Token::Identifier(_), _)));
+
+            if text == "0" && !coefficient_follows {
+                line.position += 1;
+                return Ok(species);
+            }
+        }
+
+        loop {
+            let (coefficient, lexed_species) = match line.peek().cloned() {
+                Some((Token::Number(text), column)) => {
+                    line.position += 1;
+
+                    // `2E` was lexed as a number with an incomplete exponent
+                    let (digits, lexed_species) = match text.strip_suffix(['e', 'E']) {
+                        Some(digits) if !digits.is_empty() && digits.chars().all(|c| c.is_ascii_digit()) => {
+                            (digits, Some((text[digits.len()..].to_string(), column + digits.len())))
+                        }
+                        _ => (text.as_str(), None)
+                    };
+
+                    let coefficient = digits.parse::<u32>()
+                        .ok()
+                        .filter(|coefficient| *coefficient > 0)
+                        .ok_or_else(|| line.error(column, ParseErrorKind::MalformedCoefficient(text.clone())))?;
+
+                    (coefficient, lexed_species)
+                }
+                _ => (1, None)
+            };
+
+            let (name, column) = match lexed_species {
+                Some(lexed_species) => lexed_species,
+                None => line.identifier("a species name")?
+            };
+
+            let handle = self.species.get(&name)
+                .ok_or_else(|| line.error(column, ParseErrorKind::UnknownSpecies(name.clone())))?;
+            species.push((Arc::clone(handle), coefficient));
+            self.used_species.insert(name);
+
+            if at_end(line) {
+                return Ok(species);
+            }
+
+            line.expect(Token::Plus, "`+`")?;
+        }
+    }
+
+    // A + C ->[k] B + C
+    fn reaction(&mut self, line: &mut Line) -> Result<(), ParseError> {
+        let reactants = self.side(line, Some(Token::Arrow))?;
+
+        line.expect(Token::Arrow, "`->`")?;
+        line.expect(Token::LeftBracket, "`[` and a rate after `->`")?;
+        let rate = self.rate(line)?;
+        line.expect(Token::RightBracket, "`]`")?;
+
+        let products = self.side(line, None)?;
+        line.end()?;
+
+        self.reactions.push(Reaction::with_coefficients(reactants, products, rate));
+        Ok(())
+    }
+}
+
+/// Parses a reaction network written in the text format described at the top of this module.
+pub fn parse_system(source: &str) -> Result<ChemicalSystem, ParseError> {
+    let mut parser = Parser {
+        species: HashMap::new(),
+        declarations: Vec::new(),
+        used_species: HashSet::new(),
+        parameters: HashMap::new(),
+        reactions: Vec::new()
+    };
+
+    for (index, text) in source.lines().enumerate() {
+        let number = index + 1;
+        let tokens = tokenize(text, number)?;
+
+        let mut line = Line { tokens, position: 0, number, end_column: text.chars().count() + 1 };
+
+        match line.peek() {
+            None => continue,
+            Some((Token::Identifier(keyword), _)) if keyword == "species" => {
+                line.position += 1;
+                parser.species_declaration(&mut line)?;
+            }
+            Some((Token::Identifier(keyword), _)) if keyword == "param" => {
+                line.position += 1;
+                parser.parameter_declaration(&mut line)?;
+            }
+            Some(_) => parser.reaction(&mut line)?
+        }
+    }
+
+    // A system only holds the species of its reactions, so the quantity would be lost
+    if let Some((name, line, column)) = parser.declarations.into_iter().find(|(name, _, _)| !parser.used_species.contains(name)) {
+        return Err(ParseError { line, column, kind: ParseErrorKind::UnusedSpecies(name) });
+    }
+
+    Ok(ChemicalSystem::new(parser.reactions))
+}
+
+impl FromStr for ChemicalSystem {
+    type Err = ParseError;
+
+    fn from_str(source: &str) -> Result<Self, Self::Err> {
+        parse_system(source)
+    }
+}
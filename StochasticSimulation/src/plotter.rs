@@ -1,79 +1,700 @@
-use std::collections::{HashMap, HashSet};
+use std::fmt;
+use std::ops::Range;
+use std::path::{Path, PathBuf};
+use plotters::coord::ranged1d::{AsRangedCoord, ValueFormatter};
+use plotters::coord::types::RangedCoordf64;
+use plotters::coord::Shift;
 use plotters::prelude::*;
-use crate::monitor::SystemStateSnapshot;
+use crate::monitor::{SnapshotData, SystemStateSnapshot};
+use crate::ode::ContinuousTrajectory;
 use crate::reaction::SpeciesRole;
+use crate::sampling::SamplingMonitor;
+use crate::statistics::{DistributionHeatmap, EnsembleStatistics, Histogram};
 
-pub fn plot(data: &Vec<SystemStateSnapshot>, species_to_plot: &[(&str, SpeciesRole)]) {
-    let root_drawing_area = BitMapBackend::new("images/0.4.png", (600, 400))
-        .into_drawing_area();
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum AxisRange {
+    /// Fit the axis to the plotted data.
+    Auto,
+    Fixed(f64, f64)
+}
+
+#[derive(Clone, Debug)]
+pub struct PlotOptions {
+    /// Output file, drawn as a bitmap for `.png` and as vector graphics for `.svg`.
+    pub path: PathBuf,
+    pub width: u32,
+    pub height: u32,
+    /// Caption above the chart, left out when empty.
+    pub title: String,
+    pub x_label: String,
+    pub y_label: String,
+    pub y_range: AxisRange,
+    /// Logarithmic quantity axis. Quantities of zero are drawn at the bottom of the axis.
+    pub log_scale: bool,
+    /// Colours given to the species in turn, repeated when there are more species.
+    pub palette: Vec<RGBColor>
+}
+
+impl Default for PlotOptions {
+    fn default() -> Self {
+        PlotOptions {
+            path: PathBuf::from("images/plot.png"),
+            width: 600,
+            height: 400,
+            title: "Species quantities".to_string(),
+            x_label: "Time".to_string(),
+            y_label: "Quantity".to_string(),
+            y_range: AxisRange::Auto,
+            log_scale: false,
+            palette: vec![RED, GREEN, BLUE, MAGENTA, CYAN, YELLOW]
+        }
+    }
+}
+
+#[derive(Debug)]
+pub enum PlotError {
+    /// None of the requested species appear in the data.
+    NoData,
+    /// A fixed axis range that is empty, or not positive on a log scale.
+    InvalidRange(f64, f64),
+    /// An output path whose extension is not `.png` or `.svg`.
+    UnsupportedFormat(PathBuf),
+    /// A band edge at a probability the ensemble statistics were not computed for.
+    MissingQuantile(f64),
+    Drawing(String)
+}
+
+impl fmt::Display for PlotError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            PlotError::NoData => write!(f, "none of the species to plot appear in the data"),
+            PlotError::InvalidRange(low, high) => write!(f, "invalid axis range {}..{}", low, high),
+            PlotError::UnsupportedFormat(path) => write!(f, "cannot plot to `{}`, use a .png or .svg file", path.display()),
+            PlotError::MissingQuantile(probability) => write!(f, "no {} quantile in the ensemble statistics", probability),
+            PlotError::Drawing(message) => write!(f, "drawing failed: {}", message)
+        }
+    }
+}
+
+impl std::error::Error for PlotError {}
+
+impl<E: std::error::Error + Send + Sync> From<DrawingAreaErrorKind<E>> for PlotError {
+    fn from(error: DrawingAreaErrorKind<E>) -> Self {
+        PlotError::Drawing(error.to_string())
+    }
+}
+
+/// Draws the quantities of the given species over time, whatever their role.
+///
+/// Quantities only change at events, so every species is drawn as a step line that holds
+/// its last value up to the end of the run. Histories merged from several runs, where the
+/// time goes back to the start, are drawn as one line per run in the species' colour.
+pub fn plot(data: &[SystemStateSnapshot], species_to_plot: &[(&str, SpeciesRole)], options: &PlotOptions) -> Result<(), PlotError> {
+    render(&LinePlot::new(data, species_to_plot)?, options)
+}
+
+/// Like `plot`, but returns the chart as an SVG document instead of writing a file.
+pub fn plot_to_svg(data: &[SystemStateSnapshot], species_to_plot: &[(&str, SpeciesRole)], options: &PlotOptions) -> Result<String, PlotError> {
+    render_svg(&LinePlot::new(data, species_to_plot)?, options)
+}
+
+// Something that can be drawn on any backend, so every kind of plot supports every format
+trait Figure {
+    fn draw<DB: DrawingBackend>(&self, root: &DrawingArea<DB, Shift>, options: &PlotOptions) -> Result<(), PlotError>;
+}
+
+fn render(figure: &impl Figure, options: &PlotOptions) -> Result<(), PlotError> {
+    let size = (options.width, options.height);
+
+    match extension(&options.path).as_deref() {
+        Some("png") => {
+            let root_drawing_area = BitMapBackend::new(&options.path, size).into_drawing_area();
+
+            figure.draw(&root_drawing_area, options)?;
+            root_drawing_area.present()?;
+        }
+        Some("svg") => {
+            let root_drawing_area = SVGBackend::new(&options.path, size).into_drawing_area();
+
+            figure.draw(&root_drawing_area, options)?;
+            root_drawing_area.present()?;
+        }
+        _ => return Err(PlotError::UnsupportedFormat(options.path.clone()))
+    }
+
+    Ok(())
+}
+
+fn render_svg(figure: &impl Figure, options: &PlotOptions) -> Result<String, PlotError> {
+    let mut svg = String::new();
+
+    {
+        let root_drawing_area = SVGBackend::with_string(&mut svg, (options.width, options.height))
+            .into_drawing_area();
+
+        figure.draw(&root_drawing_area, options)?;
+        root_drawing_area.present()?;
+    }
+
+    Ok(svg)
+}
+
+fn extension(path: &Path) -> Option<String> {
+    path.extension().map(|extension| extension.to_string_lossy().to_lowercase())
+}
+
+struct LinePlot {
+    series: Vec<Series>
+}
 
-    root_drawing_area.fill(&WHITE).unwrap();
+impl LinePlot {
+    fn new(data: &[SystemStateSnapshot], species_to_plot: &[(&str, SpeciesRole)]) -> Result<Self, PlotError> {
+        let series = species_series(data, species_to_plot);
 
-    let min_time = data.first().map(|snap| snap.time).unwrap();
-    let max_time = data.last().map(|snap| snap.time). unwrap();
+        if series.is_empty() {
+            return Err(PlotError::NoData);
+        }
+
+        Ok(LinePlot { series })
+    }
+}
 
-    // Flatten the list of reactions from each snapshot into a continuous sequence
-    // of all reactions across all snapshots, and then find the maximum reaction.
-    let max_quantity = 100;
-        //data.iter()
-        //.flat_map(|snap| snap.reactions.iter())
-        //.max()
-        //.unwrap();
+impl Figure for LinePlot {
+    fn draw<DB: DrawingBackend>(&self, root: &DrawingArea<DB, Shift>, options: &PlotOptions) -> Result<(), PlotError> {
+        let points = || self.series.iter().flat_map(|species| species.runs.iter().flatten());
+
+        let min_time = points().map(|&(time, _)| time).fold(f64::INFINITY, f64::min);
+        let max_time = points().map(|&(time, _)| time).fold(f64::NEG_INFINITY, f64::max);
+
+        draw_axes(root, options, min_time..max_time, points().map(|&(_, quantity)| quantity), self)
+    }
+}
+
+// The step lines of one species, one per run in the data
+struct Series {
+    name: String,
+    runs: Vec<Vec<(f64, f64)>>
+}
 
-    let mut ctx = ChartBuilder::on(&root_drawing_area)
-        .set_label_area_size(LabelAreaPosition::Left, 40)
-        .set_label_area_size(LabelAreaPosition::Bottom, 40)
-        .caption("Demo", ("sans-serif", 40))
-        .build_cartesian_2d(min_time..max_time, 0..max_quantity)
-        .unwrap();
+fn species_series(data: &[SystemStateSnapshot], species_to_plot: &[(&str, SpeciesRole)]) -> Vec<Series> {
+    let mut names: Vec<&str> = Vec::new();
 
-    let species_to_plot_names: HashSet<&str> = species_to_plot.iter()
-        .map(|(name, _role) | *name)
+    for (name, _role) in species_to_plot {
+        if !names.contains(name) {
+            names.push(name);
+        }
+    }
+
+    let mut series: Vec<Series> = names.iter()
+        .map(|name| Series { name: name.to_string(), runs: Vec::new() })
         .collect();
+    let mut open_lines: Vec<Option<Vec<(f64, f64)>>> = vec![None; names.len()];
 
-    let mut series_data_by_species: HashMap<String, Vec<(f64, i32)>> = HashMap::new();
+    // Each line is held at its last quantity until the run ends
+    let close_lines = |series: &mut Vec<Series>, open_lines: &mut Vec<Option<Vec<(f64, f64)>>>, end_time: f64| {
+        for (species, line) in series.iter_mut().zip(open_lines.iter_mut()) {
+            if let Some(mut line) = line.take() {
+                let &(time, quantity) = line.last().unwrap();
 
-    for snapshots in data.iter() {
-        let time = snapshots.time;
+                if time < end_time {
+                    line.push((end_time, quantity));
+                }
+
+                species.runs.push(line);
+            }
+        }
+    };
 
-        for reaction in snapshots.reactions.iter() {
-            let reaction_guard = reaction.lock().unwrap();
+    let mut previous_time = f64::NEG_INFINITY;
+
+    for snapshot in data {
+        if snapshot.time < previous_time {
+            close_lines(&mut series, &mut open_lines, previous_time);
+        }
+
+        previous_time = snapshot.time;
+
+        let quantities: Box<dyn Iterator<Item = (&str, i32)>> = match &snapshot.data {
+            SnapshotData::Quantities(model, quantities) => {
+                Box::new(model.species.iter().map(String::as_str).zip(quantities.iter().copied()))
+            }
+            SnapshotData::SpeciesEvents(events) => {
+                Box::new(events.iter().map(|event| (event.species_name.as_str(), event.new_quantity)))
+            }
+        };
 
-            for species in reaction_guard.reactants.iter().chain(reaction_guard.products.iter()) {
-                let species_guard = species.lock().unwrap();
+        for (name, quantity) in quantities {
+            if let Some(index) = names.iter().position(|species| *species == name) {
+                let line = open_lines[index].get_or_insert_with(Vec::new);
 
-                if species_to_plot_names.contains(&species_guard.name.as_str()) {
-                    series_data_by_species
-                        .entry(species_guard.name.clone())
-                        .or_default()
-                        .push((time, species_guard.get_quantity()))
+                if let Some(&(_, previous)) = line.last() {
+                    line.push((snapshot.time, previous));
                 }
+
+                line.push((snapshot.time, quantity as f64));
             }
         }
     }
 
-    fn generate_color(index: usize) -> RGBColor {
-        let colors = [RED, GREEN, BLUE, MAGENTA, CYAN, YELLOW];  // Add more colors if necessary
-        colors[index % colors.len()]
+    close_lines(&mut series, &mut open_lines, previous_time);
+
+    series.retain(|species| !species.runs.is_empty());
+    series
+}
+
+// The contents of a chart with time or quantity on its x axis and quantity on its y axis,
+// drawn by `draw_chart` whether the y axis is linear or logarithmic
+trait Layers {
+    fn draw_layers<DB: DrawingBackend, Y: Ranged<ValueType = f64>>(&self,
+                                                                   ctx: &mut ChartContext<'_, DB, Cartesian2d<RangedCoordf64, Y>>,
+                                                                   options: &PlotOptions,
+                                                                   floor: f64) -> Result<(), PlotError>;
+}
+
+/// Sets up the axes for `x_range` and the y values that will be drawn, then draws `layers`.
+fn draw_axes<DB: DrawingBackend>(root: &DrawingArea<DB, Shift>,
+                                 options: &PlotOptions,
+                                 mut x_range: Range<f64>,
+                                 y_values: impl Iterator<Item = f64>,
+                                 layers: &impl Layers) -> Result<(), PlotError> {
+    root.fill(&WHITE)?;
+
+    if x_range.end <= x_range.start {
+        x_range.end = x_range.start + 1.0;
     }
 
-    for (index, (species_name, species_data)) in series_data_by_species.iter().enumerate() {
-        let color = generate_color(index);
+    let (lowest, highest) = y_values.fold((f64::INFINITY, 0.0_f64), |(lowest, highest), value| {
+        (if value > 0.0 { lowest.min(value) } else { lowest }, highest.max(value))
+    });
 
-        ctx.draw_series(LineSeries::new(
-            species_data.iter().cloned(),
-            &color
-        )).unwrap()
-            .label(species_name)
-            .legend(move |(x, y)| PathElement::new(vec![(x, y), (x + 20, y)], &color));
+    if options.log_scale {
+        let (low, high) = match options.y_range {
+            AxisRange::Fixed(low, high) if low > 0.0 && high > low => (low, high),
+            AxisRange::Fixed(low, high) => return Err(PlotError::InvalidRange(low, high)),
+            AxisRange::Auto => {
+                let low = if lowest.is_finite() { lowest * 0.9 } else { 0.9 };
+
+                (low, (highest * 1.1).max(low * 10.0))
+            }
+        };
+
+        draw_chart(root, options, x_range, (low..high).log_scale(), low, layers)
+    } else {
+        let (low, high) = match options.y_range {
+            AxisRange::Fixed(low, high) if high > low => (low, high),
+            AxisRange::Fixed(low, high) => return Err(PlotError::InvalidRange(low, high)),
+            AxisRange::Auto => (0.0, if highest > 0.0 { highest * 1.05 } else { 1.0 })
+        };
+
+        draw_chart(root, options, x_range, low..high, f64::NEG_INFINITY, layers)
     }
+}
+
+fn draw_chart<DB, Y>(root: &DrawingArea<DB, Shift>,
+                     options: &PlotOptions,
+                     x_range: Range<f64>,
+                     y_range: Y,
+                     floor: f64,
+                     layers: &impl Layers) -> Result<(), PlotError>
+where
+    DB: DrawingBackend,
+    Y: AsRangedCoord<Value = f64>,
+    Y::CoordDescType: ValueFormatter<f64>
+{
+    let mut builder = ChartBuilder::on(root);
 
-    ctx.configure_mesh().draw().unwrap();
+    builder
+        .margin(10)
+        .set_label_area_size(LabelAreaPosition::Left, 50)
+        .set_label_area_size(LabelAreaPosition::Bottom, 40);
+
+    if !options.title.is_empty() {
+        builder.caption(&options.title, ("sans-serif", 30));
+    }
+
+    let mut ctx = builder.build_cartesian_2d(x_range, y_range)?;
+
+    ctx.configure_mesh()
+        .x_desc(&options.x_label)
+        .y_desc(&options.y_label)
+        .draw()?;
+
+    layers.draw_layers(&mut ctx, options, floor)?;
 
     ctx.configure_series_labels()
         .position(SeriesLabelPosition::UpperRight)
-        .background_style(&WHITE.mix(0.8))
-        .border_style(&BLACK)
-        .draw()
-        .unwrap();
+        .background_style(WHITE.mix(0.8))
+        .border_style(BLACK)
+        .draw()?;
+
+    Ok(())
+}
+
+fn palette_color(options: &PlotOptions, index: usize) -> RGBColor {
+    if options.palette.is_empty() {
+        BLACK
+    } else {
+        options.palette[index % options.palette.len()]
+    }
+}
+
+impl Layers for LinePlot {
+    fn draw_layers<DB: DrawingBackend, Y: Ranged<ValueType = f64>>(&self,
+                                                                   ctx: &mut ChartContext<'_, DB, Cartesian2d<RangedCoordf64, Y>>,
+                                                                   options: &PlotOptions,
+                                                                   floor: f64) -> Result<(), PlotError> {
+        for (index, species) in self.series.iter().enumerate() {
+            let color = palette_color(options, index);
+
+            for (run, line) in species.runs.iter().enumerate() {
+                // A log axis cannot show zero, so those parts of the line run along its bottom
+                let points = line.iter().map(|&(time, quantity)| (time, quantity.max(floor)));
+                let drawn = ctx.draw_series(LineSeries::new(points, &color))?;
+
+                if run == 0 {
+                    drawn
+                        .label(species.name.as_str())
+                        .legend(move |(x, y)| PathElement::new(vec![(x, y), (x + 20, y)], color));
+                }
+            }
+        }
+
+        Ok(())
+    }
+}
+
+/// What to draw in an ensemble plot besides the mean of every species.
+#[derive(Clone)]
+pub struct EnsemblePlot<'a> {
+    pub statistics: &'a EnsembleStatistics,
+    /// Probabilities of the lower and upper edges of the shaded band. Both have to be among
+    /// the probabilities the statistics were computed for.
+    pub band: (f64, f64),
+    /// Replicates drawn as thin lines under the mean.
+    pub samples: Vec<&'a SamplingMonitor>,
+    /// Deterministic solution drawn as a dashed line.
+    pub ode: Option<&'a ContinuousTrajectory>
+}
+
+impl<'a> EnsemblePlot<'a> {
+    /// A plot of the mean and the 5% to 95% band, without samples or an ODE solution.
+    pub fn new(statistics: &'a EnsembleStatistics) -> Self {
+        EnsemblePlot {
+            statistics,
+            band: (0.05, 0.95),
+            samples: Vec::new(),
+            ode: None
+        }
+    }
+}
+
+/// Draws the ensemble mean of each species as a line inside a shaded quantile band.
+pub fn plot_ensemble(plot: &EnsemblePlot, species_to_plot: &[(&str, SpeciesRole)], options: &PlotOptions) -> Result<(), PlotError> {
+    render(&BandPlot::new(plot, species_to_plot)?, options)
+}
+
+/// Like `plot_ensemble`, but returns the chart as an SVG document instead of writing a file.
+pub fn plot_ensemble_to_svg(plot: &EnsemblePlot, species_to_plot: &[(&str, SpeciesRole)], options: &PlotOptions) -> Result<String, PlotError> {
+    render_svg(&BandPlot::new(plot, species_to_plot)?, options)
+}
+
+struct Band {
+    name: String,
+    mean: Vec<(f64, f64)>,
+    // Lower edge followed by the upper edge backwards, closing the shaded area
+    outline: Vec<(f64, f64)>,
+    samples: Vec<Vec<(f64, f64)>>,
+    ode: Option<Vec<(f64, f64)>>
+}
+
+struct BandPlot {
+    band: (f64, f64),
+    species: Vec<Band>
+}
+
+impl BandPlot {
+    fn new(plot: &EnsemblePlot, species_to_plot: &[(&str, SpeciesRole)]) -> Result<Self, PlotError> {
+        let statistics = plot.statistics;
+        let times = &statistics.times;
+        let mut species = Vec::new();
+
+        for (name, _role) in species_to_plot {
+            if species.iter().any(|band: &Band| band.name == *name) {
+                continue;
+            }
+
+            let Some(mean) = statistics.mean_of(name) else {
+                continue;
+            };
+
+            let edge = |probability: f64| {
+                statistics.quantile_of(name, probability).ok_or(PlotError::MissingQuantile(probability))
+            };
+            let (lower, upper) = (edge(plot.band.0)?, edge(plot.band.1)?);
+
+            let outline = times.iter().copied().zip(lower.iter().copied())
+                .chain(times.iter().copied().zip(upper.iter().copied()).rev())
+                .collect();
+
+            let samples = plot.samples.iter()
+                .filter_map(|monitor| {
+                    let series = monitor.series(name)?;
+
+                    Some(monitor.times().iter().zip(series).map(|(&time, quantity)| (time, quantity as f64)).collect())
+                })
+                .collect();
+
+            let ode = plot.ode.and_then(|trajectory| {
+                let column = trajectory.species.iter().position(|species| species == name)?;
+
+                Some(trajectory.times.iter().zip(&trajectory.amounts).map(|(&time, amounts)| (time, amounts[column])).collect())
+            });
+
+            species.push(Band {
+                name: name.to_string(),
+                mean: times.iter().copied().zip(mean.iter().copied()).collect(),
+                outline,
+                samples,
+                ode
+            });
+        }
+
+        if species.is_empty() {
+            return Err(PlotError::NoData);
+        }
+
+        Ok(BandPlot { band: plot.band, species })
+    }
+
+    fn points(&self) -> impl Iterator<Item = &(f64, f64)> {
+        self.species.iter().flat_map(|band| {
+            band.outline.iter()
+                .chain(band.samples.iter().flatten())
+                .chain(band.ode.iter().flatten())
+        })
+    }
+}
+
+impl Figure for BandPlot {
+    fn draw<DB: DrawingBackend>(&self, root: &DrawingArea<DB, Shift>, options: &PlotOptions) -> Result<(), PlotError> {
+        let min_time = self.points().map(|&(time, _)| time).fold(f64::INFINITY, f64::min);
+        let max_time = self.points().map(|&(time, _)| time).fold(f64::NEG_INFINITY, f64::max);
+
+        draw_axes(root, options, min_time..max_time, self.points().map(|&(_, quantity)| quantity), self)
+    }
+}
+
+impl Layers for BandPlot {
+    fn draw_layers<DB: DrawingBackend, Y: Ranged<ValueType = f64>>(&self,
+                                                                   ctx: &mut ChartContext<'_, DB, Cartesian2d<RangedCoordf64, Y>>,
+                                                                   options: &PlotOptions,
+                                                                   floor: f64) -> Result<(), PlotError> {
+        let clamp = |points: &[(f64, f64)]| -> Vec<(f64, f64)> {
+            points.iter().map(|&(time, quantity)| (time, quantity.max(floor))).collect()
+        };
+
+        let (lower, upper) = self.band;
+
+        for (index, band) in self.species.iter().enumerate() {
+            let color = palette_color(options, index);
+
+            ctx.draw_series(std::iter::once(Polygon::new(clamp(&band.outline), color.mix(0.2).filled())))?
+                .label(format!("{} {}-{}%", band.name, lower * 100.0, upper * 100.0))
+                .legend(move |(x, y)| Rectangle::new([(x, y - 4), (x + 20, y + 4)], color.mix(0.2).filled()));
+
+            for sample in &band.samples {
+                ctx.draw_series(LineSeries::new(clamp(sample), color.mix(0.4)))?;
+            }
+
+            if let Some(ode) = &band.ode {
+                ctx.draw_series(DashedLineSeries::new(clamp(ode), 6, 4, BLACK.stroke_width(1)))?
+                    .label(format!("{} ODE", band.name))
+                    .legend(move |(x, y)| PathElement::new(vec![(x, y), (x + 20, y)], BLACK));
+            }
+
+            ctx.draw_series(LineSeries::new(clamp(&band.mean), color.stroke_width(2)))?
+                .label(format!("{} mean", band.name))
+                .legend(move |(x, y)| PathElement::new(vec![(x, y), (x + 20, y)], color.stroke_width(2)));
+        }
+
+        Ok(())
+    }
+}
+
+/// Draws histograms over each other as translucent bars, with the number of replicates in
+/// every bin or, if `normalised`, their fraction. The axis labels are taken from `options`.
+pub fn plot_histograms(histograms: &[Histogram], normalised: bool, options: &PlotOptions) -> Result<(), PlotError> {
+    render(&HistogramPlot::new(histograms, normalised)?, options)
+}
+
+/// Like `plot_histograms`, but returns the chart as an SVG document instead of writing a file.
+pub fn plot_histograms_to_svg(histograms: &[Histogram], normalised: bool, options: &PlotOptions) -> Result<String, PlotError> {
+    render_svg(&HistogramPlot::new(histograms, normalised)?, options)
+}
+
+/// Draws how the distribution of a species evolves, with time along the x axis and its
+/// quantity along the y axis, shading every cell by the fraction of replicates in it.
+pub fn plot_heatmap(heatmap: &DistributionHeatmap, options: &PlotOptions) -> Result<(), PlotError> {
+    render(&HeatmapPlot::new(heatmap)?, options)
+}
+
+/// Like `plot_heatmap`, but returns the chart as an SVG document instead of writing a file.
+pub fn plot_heatmap_to_svg(heatmap: &DistributionHeatmap, options: &PlotOptions) -> Result<String, PlotError> {
+    render_svg(&HeatmapPlot::new(heatmap)?, options)
+}
+
+#[derive(Clone, Copy)]
+struct Bar {
+    start: f64,
+    end: f64,
+    height: f64
+}
+
+struct HistogramPlot {
+    // Label and bars of every histogram
+    histograms: Vec<(String, Vec<Bar>)>
+}
+
+impl HistogramPlot {
+    fn new(histograms: &[Histogram], normalised: bool) -> Result<Self, PlotError> {
+        let histograms: Vec<_> = histograms.iter()
+            .filter(|histogram| histogram.total() > 0)
+            .map(|histogram| {
+                let heights = if normalised {
+                    histogram.probabilities()
+                } else {
+                    histogram.counts.iter().map(|&count| count as f64).collect()
+                };
+
+                let bars = heights.into_iter().enumerate()
+                    .filter(|&(_, height)| height > 0.0)
+                    .map(|(bin, height)| {
+                        let start = histogram.bin_start(bin) as f64;
+
+                        Bar { start, end: start + histogram.width as f64, height }
+                    })
+                    .collect();
+
+                (format!("{} t={}", histogram.species, histogram.time), bars)
+            })
+            .collect();
+
+        if histograms.is_empty() {
+            return Err(PlotError::NoData);
+        }
+
+        Ok(HistogramPlot { histograms })
+    }
+
+    fn bars(&self) -> impl Iterator<Item = &Bar> {
+        self.histograms.iter().flat_map(|(_, bars)| bars)
+    }
+}
+
+impl Figure for HistogramPlot {
+    fn draw<DB: DrawingBackend>(&self, root: &DrawingArea<DB, Shift>, options: &PlotOptions) -> Result<(), PlotError> {
+        let low = self.bars().map(|bar| bar.start).fold(f64::INFINITY, f64::min);
+        let high = self.bars().map(|bar| bar.end).fold(f64::NEG_INFINITY, f64::max);
+
+        draw_axes(root, options, low..high, self.bars().map(|bar| bar.height), self)
+    }
+}
+
+impl Layers for HistogramPlot {
+    fn draw_layers<DB: DrawingBackend, Y: Ranged<ValueType = f64>>(&self,
+                                                                   ctx: &mut ChartContext<'_, DB, Cartesian2d<RangedCoordf64, Y>>,
+                                                                   options: &PlotOptions,
+                                                                   floor: f64) -> Result<(), PlotError> {
+        // Bars stand on zero, or on the bottom of a log axis
+        let bottom = floor.max(0.0);
+
+        for (index, (label, bars)) in self.histograms.iter().enumerate() {
+            let style = palette_color(options, index).mix(0.4).filled();
+
+            ctx.draw_series(bars.iter().map(|bar| {
+                Rectangle::new([(bar.start, bottom), (bar.end, bar.height.max(bottom))], style)
+            }))?
+                .label(label.as_str())
+                .legend(move |(x, y)| Rectangle::new([(x, y - 4), (x + 20, y + 4)], style));
+        }
+
+        Ok(())
+    }
+}
+
+#[derive(Clone, Copy)]
+struct Cell {
+    start: f64,
+    end: f64,
+    low: f64,
+    high: f64,
+    probability: f64
+}
+
+struct HeatmapPlot {
+    species: String,
+    // Only the cells some replicate is in
+    cells: Vec<Cell>,
+    highest: f64
+}
+
+impl HeatmapPlot {
+    fn new(heatmap: &DistributionHeatmap) -> Result<Self, PlotError> {
+        let times = &heatmap.times;
+        let mut cells = Vec::new();
+
+        for (point, probabilities) in heatmap.probabilities().into_iter().enumerate() {
+            // Every column lasts until the next grid point, and the last one as long as the one before
+            let start = times[point];
+            let end = match (times.get(point + 1), point.checked_sub(1)) {
+                (Some(&next), _) => next,
+                (None, Some(previous)) => start + (start - times[previous]),
+                (None, None) => start + 1.0
+            };
+
+            for (bin, probability) in probabilities.into_iter().enumerate() {
+                if probability > 0.0 {
+                    let low = heatmap.bin_start(bin) as f64;
+
+                    cells.push(Cell { start, end, low, high: low + heatmap.width as f64, probability });
+                }
+            }
+        }
+
+        if cells.is_empty() {
+            return Err(PlotError::NoData);
+        }
+
+        let highest = cells.iter().map(|cell| cell.probability).fold(0.0, f64::max);
+
+        Ok(HeatmapPlot { species: heatmap.species.clone(), cells, highest })
+    }
+}
+
+impl Figure for HeatmapPlot {
+    fn draw<DB: DrawingBackend>(&self, root: &DrawingArea<DB, Shift>, options: &PlotOptions) -> Result<(), PlotError> {
+        let start = self.cells.iter().map(|cell| cell.start).fold(f64::INFINITY, f64::min);
+        let end = self.cells.iter().map(|cell| cell.end).fold(f64::NEG_INFINITY, f64::max);
+        let quantities = self.cells.iter().flat_map(|cell| [cell.low, cell.high]);
+
+        draw_axes(root, options, start..end, quantities, self)
+    }
+}
+
+impl Layers for HeatmapPlot {
+    fn draw_layers<DB: DrawingBackend, Y: Ranged<ValueType = f64>>(&self,
+                                                                   ctx: &mut ChartContext<'_, DB, Cartesian2d<RangedCoordf64, Y>>,
+                                                                   options: &PlotOptions,
+                                                                   floor: f64) -> Result<(), PlotError> {
+        let color = palette_color(options, 0);
+
+        ctx.draw_series(self.cells.iter().map(|cell| {
+            Rectangle::new([(cell.start, cell.low.max(floor)), (cell.end, cell.high.max(floor))],
+                           color.mix(cell.probability / self.highest).filled())
+        }))?
+            .label(self.species.as_str())
+            .legend(move |(x, y)| Rectangle::new([(x, y - 4), (x + 20, y + 4)], color.filled()));
+
+        Ok(())
+    }
 }
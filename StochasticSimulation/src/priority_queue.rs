@@ -0,0 +1,95 @@
+/// Binary min-heap over a fixed set of indices, where the key of any index can be
+/// changed in place. Used by the next reaction method to hold putative firing times.
+#[derive(Clone, Debug, Default)]
+pub struct IndexedPriorityQueue {
+    heap: Vec<usize>,
+    positions: Vec<usize>,
+    keys: Vec<f64>
+}
+
+impl IndexedPriorityQueue {
+    pub fn new(keys: Vec<f64>) -> Self {
+        let heap: Vec<usize> = (0..keys.len()).collect();
+        let positions = heap.clone();
+
+        let mut queue = IndexedPriorityQueue { heap, positions, keys };
+
+        for position in (0..queue.heap.len() / 2).rev() {
+            queue.sift_down(position);
+        }
+
+        queue
+    }
+
+    pub fn len(&self) -> usize {
+        self.heap.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.heap.is_empty()
+    }
+
+    pub fn key(&self, index: usize) -> f64 {
+        self.keys[index]
+    }
+
+    /// The index with the smallest key, together with that key.
+    pub fn peek(&self) -> Option<(usize, f64)> {
+        self.heap.first().map(|&index| (index, self.keys[index]))
+    }
+
+    pub fn update(&mut self, index: usize, key: f64) {
+        let old_key = self.keys[index];
+        self.keys[index] = key;
+
+        let position = self.positions[index];
+
+        if key < old_key {
+            self.sift_up(position);
+        } else {
+            self.sift_down(position);
+        }
+    }
+
+    fn sift_up(&mut self, mut position: usize) {
+        while position > 0 {
+            let parent = (position - 1) / 2;
+
+            if self.keys[self.heap[position]] >= self.keys[self.heap[parent]] {
+                break;
+            }
+
+            self.swap(position, parent);
+            position = parent;
+        }
+    }
+
+    fn sift_down(&mut self, mut position: usize) {
+        loop {
+            let left = 2 * position + 1;
+            let right = left + 1;
+            let mut smallest = position;
+
+            if left < self.heap.len() && self.keys[self.heap[left]] < self.keys[self.heap[smallest]] {
+                smallest = left;
+            }
+
+            if right < self.heap.len() && self.keys[self.heap[right]] < self.keys[self.heap[smallest]] {
+                smallest = right;
+            }
+
+            if smallest == position {
+                break;
+            }
+
+            self.swap(position, smallest);
+            position = smallest;
+        }
+    }
+
+    fn swap(&mut self, a: usize, b: usize) {
+        self.heap.swap(a, b);
+        self.positions[self.heap[a]] = a;
+        self.positions[self.heap[b]] = b;
+    }
+}
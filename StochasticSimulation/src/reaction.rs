@@ -1,10 +1,5 @@
 use std::sync::{Arc, Mutex};
-use rand::prelude::StdRng;
-use rand::Rng;
-use rand_distr::Exp;
 use crate::species::Species;
-use crate::symbol_table::SymbolTable;
-use crate::visitor::Visitor;
 use uuid::Uuid;
 
 pub enum SpeciesRole {
@@ -13,11 +8,13 @@ pub enum SpeciesRole {
     Both
 }
 
+/// Species on one side of a reaction with their stoichiometric coefficients.
+pub type Coefficients = Vec<(Arc<Mutex<Species>>, u32)>;
+
 #[derive(Clone)]
 pub struct Reaction {
     pub(crate) reactants: Vec<Arc<Mutex<Species>>>,
     pub(crate) products: Vec<Arc<Mutex<Species>>>,
-    pub(crate) delay: f64,
     pub(crate) lambda: f64,
     pub(crate) uuid: Uuid,
     pub(crate) formula: String
@@ -30,58 +27,48 @@ impl Reaction {
            lambda: f64) -> Arc<Mutex<Reaction>> {
 
         let uuid = Uuid::new_v4();
-        let delay = f64::MAX;
-
-        let reactant_str = reactants.iter()
-            .map(|reactant| {
-                let reactant_guard = reactant.lock().unwrap();
-                reactant_guard.name.clone()
-            })
-            // turbofish - You specify what type should come out of collect
-            .collect::<Vec<String>>()
-            .join(" + ");
-
-        let product_str = products.iter()
-            .map(|product| {
-                let product_guard = product.lock().unwrap();
-                product_guard.name.clone()
-            })
-            .collect::<Vec<String>>()
-            .join(" + ");
+
+        let reactant_str = side_formula(&group_coefficients(reactants.iter().cloned(), Arc::ptr_eq));
+        let product_str = side_formula(&group_coefficients(products.iter().cloned(), Arc::ptr_eq));
 
         let formula = format!("{} -> {}", reactant_str, product_str);
 
-        Arc::new(Mutex::new(Reaction { reactants, products, delay, lambda, uuid, formula}))
+        Arc::new(Mutex::new(Reaction { reactants, products, lambda, uuid, formula}))
     }
 
-    fn accept(reaction: &Arc<Mutex<Reaction>>, visitor: &mut dyn Visitor) {
-        visitor.visit_reactions(reaction);
+    /// Builds a reaction from species and their stoichiometric coefficients,
+    /// so dimerisation `2A -> A2` is `[(a, 2)] -> [(a2, 1)]`.
+    pub fn with_coefficients(reactants: Coefficients,
+                             products: Coefficients,
+                             lambda: f64) -> Arc<Mutex<Reaction>> {
+        // A species listed n times is consumed or produced n times per firing
+        let expand = |side: Coefficients| -> Vec<Arc<Mutex<Species>>> {
+            side.into_iter()
+                .flat_map(|(species, coefficient)| std::iter::repeat_n(species, coefficient as usize))
+                .collect()
+        };
+
+        Reaction::new(expand(reactants), expand(products), lambda)
     }
 
-    pub(crate) fn compute_delay(&mut self, rng: &mut StdRng) -> f64 {
-        let mut lambda = self.lambda;
-
-        for reactant in &self.reactants {
-            let reactant_guard = reactant.lock().unwrap();
-            lambda *= reactant_guard.quantity as f64;
-        }
+    pub fn reactant_coefficients(&self) -> Coefficients {
+        group_coefficients(self.reactants.iter().cloned(), Arc::ptr_eq)
+    }
 
-        let exp = Exp::new(lambda).unwrap();
-        self.delay = rng.sample(exp);
-        self.delay
+    pub fn product_coefficients(&self) -> Coefficients {
+        group_coefficients(self.products.iter().cloned(), Arc::ptr_eq)
     }
 
     pub fn print_details(&self) {
-        //println!("Formula {}", self.formula);
-
         print!("Reactants: ");
         // Enumerate provides tuple with index and value
         // Used to keep track of position
-        for (index, reactant) in self.reactants.iter().enumerate() {
+        let reactants = self.reactant_coefficients();
+        for (index, (reactant, coefficient)) in reactants.iter().enumerate() {
             let reactant_guard = reactant.lock().unwrap();
-            print!("{} {}", reactant_guard.name, reactant_guard.quantity);
+            print!("{}{} {}", coefficient_prefix(*coefficient), reactant_guard.name, reactant_guard.quantity);
 
-            if index < self.reactants.len() - 1 {
+            if index < reactants.len() - 1 {
                 print!(", ");
             }
         }
@@ -89,14 +76,50 @@ impl Reaction {
         println!();
 
         print!("Products:  ");
-        for (index, products) in self.products.iter().enumerate() {
-            let product_guard = products.lock().unwrap();
-            print!("{} {}", product_guard.name, product_guard.quantity);
+        let products = self.product_coefficients();
+        for (index, (product, coefficient)) in products.iter().enumerate() {
+            let product_guard = product.lock().unwrap();
+            print!("{}{} {}", coefficient_prefix(*coefficient), product_guard.name, product_guard.quantity);
 
-            if index < self.products.len() - 1 {
+            if index < products.len() - 1 {
                 print!(", ");
             }
         }
         println!();
     }
-}
\ No newline at end of file
+}
+
+/// Repeated items grouped into coefficients, in order of first appearance. Two of them are the
+/// same when `same` says so, like `Arc::ptr_eq` for species handles or `usize::eq` for indices.
+pub(crate) fn group_coefficients<T>(items: impl IntoIterator<Item = T>, same: impl Fn(&T, &T) -> bool) -> Vec<(T, u32)> {
+    let mut grouped: Vec<(T, u32)> = Vec::new();
+
+    for item in items {
+        match grouped.iter_mut().find(|(existing, _)| same(existing, &item)) {
+            Some((_, coefficient)) => *coefficient += 1,
+            None => grouped.push((item, 1))
+        }
+    }
+
+    grouped
+}
+
+fn side_formula(side: &[(Arc<Mutex<Species>>, u32)]) -> String {
+    side.iter()
+        .map(|(species, coefficient)| {
+            let species_guard = species.lock().unwrap();
+
+            format!("{}{}", coefficient_prefix(*coefficient), species_guard.name)
+        })
+        // turbofish - You specify what type should come out of collect
+        .collect::<Vec<String>>()
+        .join(" + ")
+}
+
+fn coefficient_prefix(coefficient: u32) -> String {
+    if coefficient == 1 {
+        String::new()
+    } else {
+        coefficient.to_string()
+    }
+}
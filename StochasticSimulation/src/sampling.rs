@@ -0,0 +1,184 @@
+use crate::checkpoint::{CheckpointError, ResumableMonitor, StateReader, StateWriter};
+use crate::monitor::{FilterableMonitor, Monitor};
+use crate::reaction::SpeciesRole;
+use crate::system::ChemicalSystem;
+
+/// Records the species quantities on a fixed time grid instead of at every event, so
+/// replicates sampled on the same grid give arrays that line up.
+///
+/// Quantities are piecewise constant between events. A grid point gets the state set by the
+/// last event at or before it, which is known once a state at or after the grid point is
+/// recorded. Runs record their state when they reach the end time, which fills the grid up to
+/// there, and `finish` carries the final state forward to the grid points after a run that
+/// stopped earlier.
+#[derive(Clone, Debug)]
+pub struct SamplingMonitor {
+    times: Vec<f64>,
+    species: Vec<String>,
+    // Index in the model of every recorded species
+    indices: Vec<usize>,
+    // One row per grid point, in the order of `species`
+    samples: Vec<Vec<i32>>,
+    last: Option<Vec<i32>>
+}
+
+impl SamplingMonitor {
+    /// Grid points every `interval` from zero up to and including `end_time`.
+    pub fn with_interval(interval: f64, end_time: f64) -> Self {
+        assert!(interval > 0.0, "sampling interval must be positive");
+
+        // Multiplied rather than accumulated so the points do not drift, and rounding cannot
+        // put the last one past the end time, where it would not be sampled
+        let count = (end_time / interval + 1e-9).floor() as usize + 1;
+
+        Self::with_times((0..count).map(|index| (index as f64 * interval).min(end_time)).collect())
+    }
+
+    pub fn with_times(mut times: Vec<f64>) -> Self {
+        times.sort_by(f64::total_cmp);
+
+        SamplingMonitor {
+            times,
+            species: Vec::new(),
+            indices: Vec::new(),
+            samples: Vec::new(),
+            last: None
+        }
+    }
+
+    /// A complete monitor holding samples recorded elsewhere, such as read back from a file,
+    /// with one row per grid point in the order of `species`.
+    pub fn from_samples(times: Vec<f64>, species: Vec<String>, samples: Vec<Vec<i32>>) -> Self {
+        assert_eq!(times.len(), samples.len(), "every grid point needs a row of samples");
+        assert!(samples.iter().all(|row| row.len() == species.len()), "every row needs a sample of every species");
+
+        SamplingMonitor {
+            times,
+            species,
+            indices: Vec::new(),
+            last: samples.last().cloned(),
+            samples
+        }
+    }
+
+    pub fn times(&self) -> &[f64] {
+        &self.times
+    }
+
+    /// Names of the recorded species, fixed by the first recorded state.
+    pub fn species(&self) -> &[String] {
+        &self.species
+    }
+
+    /// Quantities at the grid points filled so far, one row per point.
+    pub fn samples(&self) -> &[Vec<i32>] {
+        &self.samples
+    }
+
+    /// Quantities of one species at the grid points filled so far.
+    pub fn series(&self, species: &str) -> Option<Vec<i32>> {
+        let column = self.species.iter().position(|name| name == species)?;
+
+        Some(self.samples.iter().map(|row| row[column]).collect())
+    }
+
+    pub fn is_complete(&self) -> bool {
+        self.samples.len() == self.times.len()
+    }
+
+    /// Fills the remaining grid points with the last recorded state.
+    pub fn finish(&mut self) {
+        if let Some(last) = &self.last {
+            while self.samples.len() < self.times.len() {
+                self.samples.push(last.clone());
+            }
+        }
+    }
+
+    fn record(&mut self, time: f64, system: &ChemicalSystem, species_to_record: Option<&[(&str, SpeciesRole)]>) {
+        let quantities = &system.quantities;
+
+        if self.last.is_none() {
+            let model = &system.model;
+
+            self.indices = (0..model.species.len())
+                .filter(|&index| species_to_record.is_none_or(|selected| {
+                    selected.iter().any(|(name, _role)| *name == model.species[index])
+                }))
+                .collect();
+            self.species = self.indices.iter().map(|&index| model.species[index].clone()).collect();
+            self.samples.reserve(self.times.len());
+            self.last = Some(self.indices.iter().map(|&index| quantities[index]).collect());
+        }
+
+        let last = self.last.as_mut().unwrap();
+
+        // Grid points before this event still had the previous state
+        while self.samples.len() < self.times.len() && self.times[self.samples.len()] < time {
+            self.samples.push(last.clone());
+        }
+
+        for (value, &index) in last.iter_mut().zip(&self.indices) {
+            *value = quantities[index];
+        }
+
+        // and one at its time has the new state, like the end time of a run
+        while self.samples.len() < self.times.len() && self.times[self.samples.len()] == time {
+            self.samples.push(last.clone());
+        }
+    }
+}
+
+impl Monitor<ChemicalSystem> for SamplingMonitor {
+    fn record_state(&mut self, time: f64, system: &ChemicalSystem) {
+        self.record(time, system, None);
+    }
+}
+
+impl FilterableMonitor<ChemicalSystem> for SamplingMonitor {
+    /// Only the species named in `species_to_record` are sampled, whatever their role.
+    fn record_state_with_filter(&mut self, time: f64, system: &ChemicalSystem, species_to_record: &[(&str, SpeciesRole)]) {
+        self.record(time, system, Some(species_to_record));
+    }
+}
+
+impl ResumableMonitor for SamplingMonitor {
+    fn save_state(&self, state: &mut StateWriter) {
+        state.write_str("sampling monitor");
+        state.write_f64s(&self.times);
+        state.write_strings(&self.species);
+        state.write_u64s(&self.indices.iter().map(|&index| index as u64).collect::<Vec<_>>());
+        state.write_usize(self.samples.len());
+        self.samples.iter().for_each(|row| state.write_i32s(row));
+        state.write_bool(self.last.is_some());
+
+        if let Some(last) = &self.last {
+            state.write_i32s(last);
+        }
+    }
+
+    fn restore_state(&mut self, state: &mut StateReader, system: &ChemicalSystem) -> Result<(), CheckpointError> {
+        state.expect_tag("sampling monitor")?;
+
+        let times = state.read_f64s()?;
+        let species = state.read_strings()?;
+        let indices = state.read_u64s()?.into_iter().map(|index| index as usize).collect::<Vec<_>>();
+        let rows = state.read_usize()?;
+        let samples = (0..rows).map(|_| state.read_i32s()).collect::<Result<Vec<_>, _>>()?;
+        let last = if state.read_bool()? { Some(state.read_i32s()?) } else { None };
+
+        let species_count = system.model.species.len();
+
+        if indices.len() != species.len() || indices.iter().any(|&index| index >= species_count) {
+            return Err(CheckpointError::Corrupt("sampled species do not match the system".to_string()));
+        }
+
+        self.times = times;
+        self.species = species;
+        self.indices = indices;
+        self.samples = samples;
+        self.last = last;
+
+        Ok(())
+    }
+}
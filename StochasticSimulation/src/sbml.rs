@@ -0,0 +1,722 @@
+//! SBML Level 3 Core import and export.
+//!
+//! Compartments, species, global and local parameters and reactions whose kinetic law is
+//! a mass action product of rate constants and reactant species are imported. Species
+//! amounts are taken to be molecule counts, and species keep the `name` they have in the
+//! file, or their id if they have none. Anything that cannot be expressed as a
+//! `ChemicalSystem` is listed in `SbmlImport::unsupported` instead of being dropped silently.
+//!
+//! Exported models use a single compartment of size 1 and species in amounts, so the
+//! kinetic laws are rates in molecules per unit of time.
+
+use std::collections::{BTreeMap, HashMap, HashSet};
+use std::fmt;
+use std::path::Path;
+use std::sync::{Arc, Mutex};
+use roxmltree::{Document, Node};
+use crate::reaction::{Coefficients, Reaction};
+use crate::species::{Species, species_builder};
+use crate::system::ChemicalSystem;
+
+#[derive(Debug)]
+pub enum SbmlError {
+    Io(std::io::Error),
+    Xml(roxmltree::Error),
+    NotSbml,
+    UnsupportedLevel { level: String, version: String },
+    MissingModel,
+    MissingAttribute { element: String, attribute: &'static str },
+    InvalidNumber { element: String, attribute: &'static str, value: String },
+    UnknownSpecies { reaction: String, species: String }
+}
+
+impl fmt::Display for SbmlError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            SbmlError::Io(error) => write!(f, "could not read SBML file: {}", error),
+            SbmlError::Xml(error) => write!(f, "invalid XML: {}", error),
+            SbmlError::NotSbml => write!(f, "the document is not SBML"),
+            SbmlError::UnsupportedLevel { level, version } =>
+                write!(f, "SBML level {} version {} is not supported, only level 3", level, version),
+            SbmlError::MissingModel => write!(f, "the document has no model"),
+            SbmlError::MissingAttribute { element, attribute } =>
+                write!(f, "<{}> is missing the required attribute `{}`", element, attribute),
+            SbmlError::InvalidNumber { element, attribute, value } =>
+                write!(f, "<{}> attribute `{}` is not a number: `{}`", element, attribute, value),
+            SbmlError::UnknownSpecies { reaction, species } =>
+                write!(f, "reaction `{}` refers to unknown species `{}`", reaction, species)
+        }
+    }
+}
+
+impl std::error::Error for SbmlError {}
+
+impl From<std::io::Error> for SbmlError {
+    fn from(error: std::io::Error) -> Self {
+        SbmlError::Io(error)
+    }
+}
+
+impl From<roxmltree::Error> for SbmlError {
+    fn from(error: roxmltree::Error) -> Self {
+        SbmlError::Xml(error)
+    }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum ImportAction {
+    /// Ignored, the rest of the model is imported without it.
+    Skipped,
+    /// The element, usually a reaction, is left out of the imported system.
+    Rejected,
+    /// Imported, but not exactly, like a fractional initial amount that was rounded.
+    Approximated
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub struct UnsupportedConstruct {
+    pub construct: String,
+    pub id: Option<String>,
+    pub action: ImportAction,
+    pub reason: String
+}
+
+impl fmt::Display for UnsupportedConstruct {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let action = match self.action {
+            ImportAction::Skipped => "skipped",
+            ImportAction::Rejected => "rejected",
+            ImportAction::Approximated => "approximated"
+        };
+
+        match &self.id {
+            Some(id) => write!(f, "{} <{}> `{}`: {}", action, self.construct, id, self.reason),
+            None => write!(f, "{} <{}>: {}", action, self.construct, self.reason)
+        }
+    }
+}
+
+pub struct SbmlImport {
+    pub system: ChemicalSystem,
+    /// Name and initial molecule count of every species of the model, in file order. It
+    /// includes species that take part in no reaction, which `system` leaves out. The counts
+    /// are those read from the file, not the quantities of `system`, which change as it is
+    /// simulated.
+    pub species: Vec<(String, i32)>,
+    pub unsupported: Vec<UnsupportedConstruct>
+}
+
+pub fn read_sbml(path: impl AsRef<Path>) -> Result<SbmlImport, SbmlError> {
+    import_sbml(&std::fs::read_to_string(path)?)
+}
+
+pub fn import_sbml(xml: &str) -> Result<SbmlImport, SbmlError> {
+    let document = Document::parse(xml)?;
+    let root = document.root_element();
+
+    if root.tag_name().name() != "sbml" {
+        return Err(SbmlError::NotSbml);
+    }
+
+    let level = root.attribute("level").unwrap_or("");
+    if level != "3" {
+        return Err(SbmlError::UnsupportedLevel {
+            level: level.to_string(),
+            version: root.attribute("version").unwrap_or("").to_string()
+        });
+    }
+
+    let model = child(root, "model").ok_or(SbmlError::MissingModel)?;
+
+    let mut importer = Importer {
+        compartments: HashMap::new(),
+        parameters: HashMap::new(),
+        species: HashMap::new(),
+        species_order: Vec::new(),
+        species_names: HashSet::new(),
+        reactions: Vec::new(),
+        unsupported: Vec::new()
+    };
+
+    for list in model.children().filter(Node::is_element) {
+        match list.tag_name().name() {
+            "listOfCompartments" => importer.compartments(list)?,
+            "listOfParameters" => importer.parameters(list)?,
+            "listOfSpecies" => importer.species(list)?,
+            "listOfFunctionDefinitions" | "listOfInitialAssignments" | "listOfRules"
+            | "listOfConstraints" | "listOfEvents" => importer.skip_all(list),
+            _ => {}
+        }
+    }
+
+    // Reactions last, every species and parameter they refer to is known by then
+    if let Some(list) = child(model, "listOfReactions") {
+        importer.reactions(list)?;
+    }
+
+    let species = importer.species_order.iter()
+        .map(|id| {
+            let species_guard = importer.species[id].handle.lock().unwrap();
+
+            (species_guard.name.clone(), species_guard.quantity)
+        })
+        .collect();
+
+    Ok(SbmlImport {
+        system: ChemicalSystem::new(importer.reactions),
+        species,
+        unsupported: importer.unsupported
+    })
+}
+
+fn child<'a, 'input>(node: Node<'a, 'input>, name: &str) -> Option<Node<'a, 'input>> {
+    node.children().find(|child| child.is_element() && child.tag_name().name() == name)
+}
+
+fn children<'a, 'input: 'a>(node: Node<'a, 'input>, name: &'a str) -> impl Iterator<Item = Node<'a, 'input>> + 'a {
+    node.children().filter(move |child| child.is_element() && child.tag_name().name() == name)
+}
+
+fn required<'a>(node: Node<'a, '_>, attribute: &'static str) -> Result<&'a str, SbmlError> {
+    node.attribute(attribute).ok_or_else(|| SbmlError::MissingAttribute {
+        element: node.tag_name().name().to_string(),
+        attribute
+    })
+}
+
+fn number(node: Node, attribute: &'static str) -> Result<Option<f64>, SbmlError> {
+    node.attribute(attribute)
+        .map(|value| value.trim().parse::<f64>().map_err(|_| SbmlError::InvalidNumber {
+            element: node.tag_name().name().to_string(),
+            attribute,
+            value: value.to_string()
+        }))
+        .transpose()
+}
+
+// Species and reactions are known by their name, ids only have letters, digits and underscores
+fn name<'a>(node: Node<'a, '_>) -> Result<&'a str, SbmlError> {
+    match node.attribute("name") {
+        Some(name) => Ok(name),
+        None => required(node, "id")
+    }
+}
+
+fn flag(node: Node, attribute: &str) -> bool {
+    matches!(node.attribute(attribute), Some("true") | Some("1"))
+}
+
+struct ImportedSpecies {
+    handle: Arc<Mutex<Species>>,
+    compartment: String,
+    // Rate laws refer to concentrations unless this is set
+    only_substance_units: bool,
+    // Boundary and constant species are not changed by reactions
+    fixed: bool
+}
+
+struct Importer {
+    compartments: HashMap<String, f64>,
+    parameters: HashMap<String, f64>,
+    species: HashMap<String, ImportedSpecies>,
+    species_order: Vec<String>,
+    species_names: HashSet<String>,
+    reactions: Vec<Arc<Mutex<Reaction>>>,
+    unsupported: Vec<UnsupportedConstruct>
+}
+
+impl Importer {
+    fn report(&mut self, node: Node, action: ImportAction, reason: impl Into<String>) {
+        let id = node.attribute("id").or_else(|| node.attribute("variable")).map(str::to_string);
+
+        self.unsupported.push(UnsupportedConstruct {
+            construct: node.tag_name().name().to_string(),
+            id,
+            action,
+            reason: reason.into()
+        });
+    }
+
+    fn skip_all(&mut self, list: Node) {
+        for element in list.children().filter(Node::is_element) {
+            let reason = match element.tag_name().name() {
+                "functionDefinition" => "function definitions are not supported",
+                "initialAssignment" => "initial assignments are not supported, the declared initial values are used",
+                "assignmentRule" | "rateRule" | "algebraicRule" => "rules are not supported",
+                "constraint" => "constraints are not checked",
+                "event" => "events are not supported",
+                _ => "not supported"
+            };
+
+            self.report(element, ImportAction::Skipped, reason);
+        }
+    }
+
+    fn compartments(&mut self, list: Node) -> Result<(), SbmlError> {
+        for compartment in children(list, "compartment") {
+            let id = required(compartment, "id")?;
+            let size = number(compartment, "size")?.unwrap_or(1.0);
+
+            self.compartments.insert(id.to_string(), size);
+        }
+
+        Ok(())
+    }
+
+    fn parameters(&mut self, list: Node) -> Result<(), SbmlError> {
+        for parameter in children(list, "parameter") {
+            let id = required(parameter, "id")?;
+
+            match number(parameter, "value")? {
+                Some(value) => { self.parameters.insert(id.to_string(), value); }
+                None => self.report(parameter, ImportAction::Skipped, "parameter has no value")
+            }
+        }
+
+        Ok(())
+    }
+
+    fn species(&mut self, list: Node) -> Result<(), SbmlError> {
+        for species in children(list, "species") {
+            let id = required(species, "id")?;
+            let compartment = required(species, "compartment")?;
+            let size = self.compartments.get(compartment).copied().unwrap_or(1.0);
+
+            let amount = match (number(species, "initialAmount")?, number(species, "initialConcentration")?) {
+                (Some(amount), _) => amount,
+                (None, Some(concentration)) => concentration * size,
+                (None, None) => {
+                    self.report(species, ImportAction::Approximated, "no initial amount, starting from 0");
+                    0.0
+                }
+            };
+
+            let quantity = amount.round().max(0.0);
+            if quantity != amount {
+                self.report(species, ImportAction::Approximated,
+                            format!("initial amount {} rounded to the molecule count {}", amount, quantity));
+            }
+
+            // Names need not be unique in SBML, but species are looked up by them
+            let mut name = name(species)?;
+            if !self.species_names.insert(name.to_string()) {
+                self.report(species, ImportAction::Approximated, format!("name `{}` is taken by another species, using the id", name));
+                name = id;
+                self.species_names.insert(id.to_string());
+            }
+
+            self.species.insert(id.to_string(), ImportedSpecies {
+                handle: species_builder(name, quantity as i32),
+                compartment: compartment.to_string(),
+                only_substance_units: flag(species, "hasOnlySubstanceUnits"),
+                fixed: flag(species, "boundaryCondition") || flag(species, "constant")
+            });
+            self.species_order.push(id.to_string());
+        }
+
+        Ok(())
+    }
+
+    fn species_references(&self, reaction: Node, reaction_name: &str, list: &str) -> Result<Result<Vec<(String, u32)>, String>, SbmlError> {
+        let mut references = Vec::new();
+
+        for reference in child(reaction, list).into_iter().flat_map(|list| list.children().filter(Node::is_element)) {
+            let species = required(reference, "species")?;
+
+            if !self.species.contains_key(species) {
+                return Err(SbmlError::UnknownSpecies { reaction: reaction_name.to_string(), species: species.to_string() });
+            }
+
+            let stoichiometry = number(reference, "stoichiometry")?.unwrap_or(1.0);
+
+            if stoichiometry.fract() != 0.0 || stoichiometry < 0.0 {
+                return Ok(Err(format!("stoichiometry {} of `{}` is not a whole number", stoichiometry, species)));
+            }
+
+            if stoichiometry > 0.0 {
+                references.push((species.to_string(), stoichiometry as u32));
+            }
+        }
+
+        Ok(Ok(references))
+    }
+
+    fn reactions(&mut self, list: Node) -> Result<(), SbmlError> {
+        for reaction in children(list, "reaction") {
+            let name = name(reaction)?;
+
+            let reactants = match self.species_references(reaction, name, "listOfReactants")? {
+                Ok(reactants) => reactants,
+                Err(reason) => {
+                    self.report(reaction, ImportAction::Rejected, reason);
+                    continue;
+                }
+            };
+
+            let products = match self.species_references(reaction, name, "listOfProducts")? {
+                Ok(products) => products,
+                Err(reason) => {
+                    self.report(reaction, ImportAction::Rejected, reason);
+                    continue;
+                }
+            };
+
+            let modifiers: Vec<String> = child(reaction, "listOfModifiers").into_iter()
+                .flat_map(|list| children(list, "modifierSpeciesReference"))
+                .filter_map(|modifier| modifier.attribute("species").map(str::to_string))
+                .collect();
+
+            if flag(reaction, "fast") {
+                self.report(reaction, ImportAction::Approximated, "fast reactions are simulated like any other reaction");
+            }
+
+            match self.mass_action(reaction, &reactants, &products, &modifiers) {
+                Ok(rates) => {
+                    for (reactants, products, lambda) in rates {
+                        self.reactions.push(Reaction::with_coefficients(reactants, products, lambda));
+                    }
+                }
+                Err(reason) => self.report(reaction, ImportAction::Rejected, reason)
+            }
+        }
+
+        Ok(())
+    }
+
+    // The reactions, in both directions for a reversible law, that a kinetic law describes
+    fn mass_action(&self,
+                   reaction: Node,
+                   reactants: &[(String, u32)],
+                   products: &[(String, u32)],
+                   modifiers: &[String]) -> Result<Vec<(Coefficients, Coefficients, f64)>, String> {
+        let kinetic_law = child(reaction, "kineticLaw").ok_or("reaction has no kinetic law")?;
+        let math = child(kinetic_law, "math").ok_or("kinetic law has no math")?;
+        let expression = math.children().find(Node::is_element)
+            .ok_or("kinetic law is empty")
+            .and_then(|node| parse_math(node).map_err(|_| "kinetic law uses MathML that is not supported"))?;
+
+        let mut local_parameters = HashMap::new();
+        for list in ["listOfLocalParameters", "listOfParameters"] {
+            for parameter in child(kinetic_law, list).into_iter().flat_map(|list| list.children().filter(Node::is_element)) {
+                if let (Some(id), Some(value)) = (parameter.attribute("id"), parameter.attribute("value")) {
+                    let value = value.trim().parse::<f64>().map_err(|_| format!("local parameter `{}` is not a number", id))?;
+                    local_parameters.insert(id.to_string(), value);
+                }
+            }
+        }
+
+        let terms = match &expression {
+            Expression::Apply(operator, arguments) if operator == "minus" && arguments.len() == 2 => {
+                vec![(&arguments[0], reactants, products), (&arguments[1], products, reactants)]
+            }
+            _ => vec![(&expression, reactants, products)]
+        };
+
+        terms.into_iter()
+            .map(|(term, consumed, produced)| {
+                let monomial = self.monomial(term, &local_parameters)
+                    .ok_or("kinetic law is not a mass action product of constants and species")?;
+
+                self.mass_action_reaction(monomial, consumed, produced, modifiers)
+            })
+            .collect()
+    }
+
+    fn mass_action_reaction(&self,
+                            monomial: Monomial,
+                            consumed: &[(String, u32)],
+                            produced: &[(String, u32)],
+                            modifiers: &[String]) -> Result<(Coefficients, Coefficients, f64), String> {
+        let mut powers = monomial.powers;
+        let mut reactant_coefficients: Vec<(String, u32)> = Vec::new();
+
+        for (species, coefficient) in consumed {
+            match powers.remove(species) {
+                Some(power) if power == *coefficient as i32 => reactant_coefficients.push((species.clone(), *coefficient)),
+                _ => return Err(format!("rate is not proportional to `{}` to the power of its stoichiometry {}", species, coefficient))
+            }
+        }
+
+        // Modifiers that appear in the law take part as catalysts
+        let mut catalysts = Vec::new();
+        for (species, power) in powers {
+            if power <= 0 || !modifiers.contains(&species) {
+                return Err(format!("rate depends on `{}`, which is not a reactant or modifier", species));
+            }
+
+            catalysts.push((species, power as u32));
+        }
+
+        reactant_coefficients.extend(catalysts.iter().cloned());
+
+        // Turn rate = k * [A]^n into propensity = lambda * C(A, n), with concentrations
+        // divided out by the compartment size
+        let mut lambda = monomial.constant;
+        for (species, coefficient) in &reactant_coefficients {
+            let imported = &self.species[species];
+            let factorial: f64 = (1..=*coefficient).map(|k| k as f64).product();
+
+            lambda *= factorial;
+
+            if !imported.only_substance_units {
+                let size = self.compartments.get(&imported.compartment).copied().unwrap_or(1.0);
+                lambda /= size.powi(*coefficient as i32);
+            }
+        }
+
+        let mut product_coefficients: Vec<(String, u32)> = produced.to_vec();
+        product_coefficients.extend(catalysts);
+
+        // Boundary species keep their amount, whatever the reaction does to them
+        for species in &self.species_order {
+            if self.species[species].fixed {
+                let consumed = reactant_coefficients.iter().find(|(id, _)| id == species).map(|(_, coefficient)| *coefficient);
+
+                product_coefficients.retain(|(id, _)| id != species);
+                if let Some(coefficient) = consumed {
+                    product_coefficients.push((species.clone(), coefficient));
+                }
+            }
+        }
+
+        let handles = |coefficients: Vec<(String, u32)>| -> Coefficients {
+            coefficients.into_iter()
+                .map(|(species, coefficient)| (Arc::clone(&self.species[&species].handle), coefficient))
+                .collect()
+        };
+
+        Ok((handles(reactant_coefficients), handles(product_coefficients), lambda))
+    }
+
+    // A constant times species raised to integer powers
+    fn monomial(&self, expression: &Expression, local_parameters: &HashMap<String, f64>) -> Option<Monomial> {
+        match expression {
+            Expression::Number(value) => Some(Monomial::constant(*value)),
+            Expression::Identifier(id) => {
+                if let Some(value) = local_parameters.get(id) {
+                    Some(Monomial::constant(*value))
+                } else if self.species.contains_key(id) {
+                    Some(Monomial { constant: 1.0, powers: BTreeMap::from([(id.clone(), 1)]) })
+                } else {
+                    self.parameters.get(id)
+                        .or_else(|| self.compartments.get(id))
+                        .map(|value| Monomial::constant(*value))
+                }
+            }
+            Expression::Apply(operator, arguments) => match (operator.as_str(), arguments.as_slice()) {
+                ("times", _) => arguments.iter().try_fold(Monomial::constant(1.0), |product, argument| {
+                    Some(product.multiply(self.monomial(argument, local_parameters)?, 1))
+                }),
+                ("divide", [numerator, denominator]) => {
+                    let denominator = self.monomial(denominator, local_parameters)?;
+                    Some(self.monomial(numerator, local_parameters)?.multiply(denominator, -1))
+                }
+                ("power", [base, exponent]) => {
+                    let exponent = self.monomial(exponent, local_parameters)?;
+
+                    if !exponent.powers.is_empty() || exponent.constant.fract() != 0.0 {
+                        return None;
+                    }
+
+                    let base = self.monomial(base, local_parameters)?;
+                    let power = exponent.constant as i32;
+
+                    Some(Monomial {
+                        constant: base.constant.powi(power),
+                        powers: base.powers.into_iter().map(|(species, exponent)| (species, exponent * power)).collect()
+                    })
+                }
+                _ => None
+            }
+        }
+    }
+}
+
+struct Monomial {
+    constant: f64,
+    powers: BTreeMap<String, i32>
+}
+
+impl Monomial {
+    fn constant(constant: f64) -> Self {
+        Monomial { constant, powers: BTreeMap::new() }
+    }
+
+    // self * other^sign, where sign is 1 or -1
+    fn multiply(mut self, other: Monomial, sign: i32) -> Self {
+        self.constant *= other.constant.powi(sign);
+
+        for (species, power) in other.powers {
+            *self.powers.entry(species).or_default() += power * sign;
+        }
+
+        self.powers.retain(|_, power| *power != 0);
+        self
+    }
+}
+
+enum Expression {
+    Number(f64),
+    Identifier(String),
+    Apply(String, Vec<Expression>)
+}
+
+fn parse_math(node: Node) -> Result<Expression, ()> {
+    match node.tag_name().name() {
+        "ci" => Ok(Expression::Identifier(node.text().unwrap_or("").trim().to_string())),
+        "cn" => {
+            // e-notation and rational numbers are split in two by a <sep/>
+            let parts: Vec<f64> = node.children()
+                .filter(|child| child.is_text())
+                .filter_map(|child| child.text())
+                .map(str::trim)
+                .filter(|text| !text.is_empty())
+                .map(|text| text.parse::<f64>().map_err(|_| ()))
+                .collect::<Result<_, _>>()?;
+
+            match (node.attribute("type"), parts.as_slice()) {
+                (Some("e-notation"), [mantissa, exponent]) => Ok(Expression::Number(mantissa * 10f64.powf(*exponent))),
+                (Some("rational"), [numerator, denominator]) => Ok(Expression::Number(numerator / denominator)),
+                (_, [value]) => Ok(Expression::Number(*value)),
+                _ => Err(())
+            }
+        }
+        "apply" => {
+            let mut elements = node.children().filter(Node::is_element);
+            let operator = elements.next().ok_or(())?.tag_name().name().to_string();
+            let arguments = elements.map(parse_math).collect::<Result<_, _>>()?;
+
+            Ok(Expression::Apply(operator, arguments))
+        }
+        "semantics" => node.children().find(Node::is_element).ok_or(()).and_then(parse_math),
+        _ => Err(())
+    }
+}
+
+pub fn write_sbml(system: &ChemicalSystem, path: impl AsRef<Path>) -> std::io::Result<()> {
+    std::fs::write(path, export_sbml(system))
+}
+
+/// Serialises the reactions of a system and the species they involve to SBML Level 3
+/// Version 1, with the formula of every reaction as its name.
+pub fn export_sbml(system: &ChemicalSystem) -> String {
+    let model = &system.model;
+
+    let mut used_ids = HashSet::new();
+    let ids: Vec<String> = model.species.iter().map(|name| unique_id(name, &mut used_ids)).collect();
+    // The exporter's own ids come after the species', so that species keep their names
+    let model_id = unique_id("model", &mut used_ids);
+    let compartment_id = unique_id("default", &mut used_ids);
+    let parameter_id = unique_id("k", &mut used_ids);
+    let reaction_ids: Vec<String> = (1..=model.reaction_count())
+        .map(|reaction| unique_id(&format!("R{}", reaction), &mut used_ids))
+        .collect();
+
+    let mut xml = String::new();
+    xml.push_str("<?xml version=\"1.0\" encoding=\"UTF-8\"?>\n");
+    xml.push_str("<sbml xmlns=\"http://www.sbml.org/sbml/level3/version1/core\" level=\"3\" version=\"1\">\n");
+    xml.push_str(&format!("  <model id=\"{}\">\n", model_id));
+    xml.push_str("    <listOfCompartments>\n");
+    xml.push_str(&format!("      <compartment id=\"{}\" spatialDimensions=\"3\" size=\"1\" constant=\"true\"/>\n", compartment_id));
+    xml.push_str("    </listOfCompartments>\n");
+
+    xml.push_str("    <listOfSpecies>\n");
+    for ((id, name), quantity) in ids.iter().zip(&model.species).zip(&system.quantities) {
+        xml.push_str(&format!(
+            "      <species id=\"{}\" name=\"{}\" compartment=\"{}\" initialAmount=\"{}\" \
+             hasOnlySubstanceUnits=\"true\" boundaryCondition=\"false\" constant=\"false\"/>\n",
+            id, escape(name), compartment_id, quantity
+        ));
+    }
+    xml.push_str("    </listOfSpecies>\n");
+
+    xml.push_str("    <listOfReactions>\n");
+    for (reaction, reaction_id) in reaction_ids.iter().enumerate() {
+        let reactants = &model.reactants[reaction];
+        let products = &model.products[reaction];
+
+        xml.push_str(&format!(
+            "      <reaction id=\"{}\" name=\"{}\" reversible=\"false\" fast=\"false\">\n",
+            reaction_id, escape(model.formula(reaction))
+        ));
+
+        for (list, side) in [("listOfReactants", reactants), ("listOfProducts", products)] {
+            if side.is_empty() {
+                continue;
+            }
+
+            xml.push_str(&format!("        <{}>\n", list));
+            for (species, coefficient) in side.iter() {
+                xml.push_str(&format!(
+                    "          <speciesReference species=\"{}\" stoichiometry=\"{}\" constant=\"true\"/>\n",
+                    ids[*species], coefficient
+                ));
+            }
+            xml.push_str(&format!("        </{}>\n", list));
+        }
+
+        // The deterministic rate k * A^n is the large population limit of the
+        // propensity lambda * C(A, n), so k = lambda / n!
+        let factorials: f64 = reactants.iter()
+            .map(|(_, coefficient)| (1..=*coefficient).map(|k| k as f64).product::<f64>())
+            .product();
+        let rate_constant = model.lambdas[reaction] / factorials;
+
+        let mut factors = vec![format!("<ci> {} </ci>", parameter_id)];
+        for (species, coefficient) in reactants.iter() {
+            let id = &ids[*species];
+
+            factors.push(if *coefficient == 1 {
+                format!("<ci> {} </ci>", id)
+            } else {
+                format!("<apply><power/><ci> {} </ci><cn type=\"integer\"> {} </cn></apply>", id, coefficient)
+            });
+        }
+
+        let math = if factors.len() == 1 {
+            factors.remove(0)
+        } else {
+            format!("<apply><times/>{}</apply>", factors.concat())
+        };
+
+        xml.push_str("        <kineticLaw>\n");
+        xml.push_str(&format!("          <math xmlns=\"http://www.w3.org/1998/Math/MathML\">{}</math>\n", math));
+        xml.push_str("          <listOfLocalParameters>\n");
+        xml.push_str(&format!("            <localParameter id=\"{}\" value=\"{:e}\"/>\n", parameter_id, rate_constant));
+        xml.push_str("          </listOfLocalParameters>\n");
+        xml.push_str("        </kineticLaw>\n");
+        xml.push_str("      </reaction>\n");
+    }
+    xml.push_str("    </listOfReactions>\n");
+
+    xml.push_str("  </model>\n");
+    xml.push_str("</sbml>\n");
+    xml
+}
+
+// SBML ids are letters, digits and underscores, not starting with a digit
+fn unique_id(name: &str, used_ids: &mut HashSet<String>) -> String {
+    let mut id: String = name.chars()
+        .map(|c| if c.is_ascii_alphanumeric() || c == '_' { c } else { '_' })
+        .collect();
+
+    if !id.starts_with(|c: char| c.is_ascii_alphabetic() || c == '_') {
+        id.insert(0, '_');
+    }
+
+    let mut candidate = id.clone();
+    let mut suffix = 2;
+    while !used_ids.insert(candidate.clone()) {
+        candidate = format!("{}_{}", id, suffix);
+        suffix += 1;
+    }
+
+    candidate
+}
+
+fn escape(text: &str) -> String {
+    text.replace('&', "&amp;")
+        .replace('<', "&lt;")
+        .replace('>', "&gt;")
+        .replace('"', "&quot;")
+}
@@ -0,0 +1,286 @@
+use std::convert::Infallible;
+use std::sync::Arc;
+use std::time::Instant;
+use rand_chacha::ChaCha12Rng;
+use crate::checkpoint::{Checkpoint, CheckpointError, StateReader, StateWriter};
+use crate::monitor::FilterableMonitor;
+use crate::reaction::SpeciesRole;
+use crate::stopping::{SimulationSummary, StopConditions};
+use crate::system::{run, summarise, ChemicalSystem};
+use crate::visitor::Visitor;
+
+/// A run that is advanced by the caller, one event or one stretch of time at a time, for
+/// simulations embedded in another loop. `ChemicalSystem::simulation_until` drives one of
+/// these and hands every step to a monitor.
+///
+/// As an `Iterator` it yields every event with a copy of the state after it, and ends when
+/// the system is absorbed. `step` gives the same without copying the state.
+pub struct Simulation<'a> {
+    pub(crate) system: &'a mut ChemicalSystem,
+    pub(crate) visitor: &'a mut dyn Visitor,
+    pub(crate) rng: &'a mut ChaCha12Rng,
+    pub(crate) time: f64,
+    pub(crate) steps: u64
+}
+
+/// An event and the state it left, borrowed from the `Simulation` until the next step.
+pub struct StepView<'s, 'a> {
+    simulation: &'s Simulation<'a>
+}
+
+/// An event yielded by iterating a `Simulation`.
+#[derive(Clone, Debug, PartialEq)]
+pub struct Event {
+    pub time: f64,
+    /// The reaction that fired, or `None` for a leap that fired several.
+    pub reaction: Option<usize>,
+    /// Quantity of every species after the event, indexed like `Model::species`.
+    pub quantities: Vec<i32>
+}
+
+// What a step towards a horizon did
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub(crate) enum Advance {
+    Fired,
+    /// The next event is past the horizon, so the run was stopped at the horizon instead.
+    Reached,
+    /// No reaction can fire.
+    Absorbed
+}
+
+impl<'a> Simulation<'a> {
+    /// A run of `system` from time zero, in its current state. The visitor is reset, so one
+    /// can be reused for several runs.
+    pub fn new(system: &'a mut ChemicalSystem, visitor: &'a mut dyn Visitor, rng: &'a mut ChaCha12Rng) -> Self {
+        // Firing times scheduled in an earlier run would carry over into this one
+        visitor.reset();
+
+        Simulation {
+            system,
+            visitor,
+            rng,
+            time: 0.0,
+            steps: 0
+        }
+    }
+
+    pub fn time(&self) -> f64 {
+        self.time
+    }
+
+    /// Events, or leaps, so far.
+    pub fn steps(&self) -> u64 {
+        self.steps
+    }
+
+    pub fn system(&self) -> &ChemicalSystem {
+        self.system
+    }
+
+    /// The system, to change quantities between steps. The visitor is told to forget what it
+    /// kept from earlier steps, so the change is taken into account.
+    pub fn system_mut(&mut self) -> &mut ChemicalSystem {
+        self.visitor.reset();
+        self.system
+    }
+
+    /// Continues the run `checkpoint` was taken from. `system` has to be of the same reaction
+    /// network and `visitor` for the same algorithm, and their state and that of `rng` are
+    /// replaced with the checkpointed one.
+    pub fn resume(system: &'a mut ChemicalSystem,
+                  visitor: &'a mut dyn Visitor,
+                  rng: &'a mut ChaCha12Rng,
+                  checkpoint: &Checkpoint) -> Result<Self, CheckpointError> {
+        rng.clone_from(&checkpoint.rng);
+
+        Self::restore(system, visitor, rng, checkpoint)
+    }
+
+    // Like `resume`, but keeps `rng` as it is
+    pub(crate) fn restore(system: &'a mut ChemicalSystem,
+                          visitor: &'a mut dyn Visitor,
+                          rng: &'a mut ChaCha12Rng,
+                          checkpoint: &Checkpoint) -> Result<Self, CheckpointError> {
+        if !checkpoint.is_of(&system.model) {
+            return Err(CheckpointError::WrongModel);
+        }
+
+        let mut state = StateReader::new(&checkpoint.visitor);
+
+        // Stateless visitors read nothing, so leftover state was saved by another algorithm
+        visitor.restore_state(&mut state, system)?;
+        state.finish().map_err(|_| CheckpointError::Corrupt(format!("visitor state is not for {:?}", checkpoint.algorithm)))?;
+
+        system.quantities.clone_from(&checkpoint.quantities);
+
+        Ok(Simulation {
+            system,
+            visitor,
+            rng,
+            time: checkpoint.time,
+            steps: checkpoint.steps
+        })
+    }
+
+    /// The run as it stands, to be resumed later. `Checkpoint::save_monitor` adds what a
+    /// monitor recorded. The generator is kept where it is in its stream, so the run goes on
+    /// the same way whether it is resumed from the checkpoint or not.
+    pub fn checkpoint(&self) -> Checkpoint {
+        let mut visitor = StateWriter::new();
+        self.visitor.save_state(&mut visitor);
+
+        let model = &self.system.model;
+
+        Checkpoint {
+            algorithm: self.system.algorithm,
+            species: model.species.clone(),
+            formulas: model.formulas.clone(),
+            lambdas: model.lambdas.clone(),
+            quantities: self.system.quantities.clone(),
+            time: self.time,
+            steps: self.steps,
+            rng: self.rng.clone(),
+            visitor: visitor.into_bytes(),
+            monitor: None,
+            journal_length: None,
+            journal: None,
+            source: None
+        }
+    }
+
+    /// Goes on until one of `conditions` is met, recording the state after every step, as
+    /// `ChemicalSystem::simulation_until` does from time zero. The current state is taken to
+    /// be recorded already.
+    pub fn run_until(&mut self,
+                     conditions: &StopConditions,
+                     monitor: &mut dyn FilterableMonitor<ChemicalSystem>,
+                     species_to_record: &[(&str, SpeciesRole)]) -> SimulationSummary {
+        let start_time_instant = Instant::now();
+
+        let Ok(reason) = run(self, conditions, monitor, species_to_record, start_time_instant,
+                             |_, _| Ok::<(), Infallible>(()));
+
+        summarise(reason, self, start_time_instant)
+    }
+
+    /// Fires the next event. Returns `None`, leaving the run as it is, if no reaction can fire.
+    pub fn step(&mut self) -> Option<StepView<'_, 'a>> {
+        match self.advance(f64::INFINITY) {
+            Advance::Fired => Some(StepView { simulation: self }),
+            _ => None
+        }
+    }
+
+    /// Fires every event up to `time` and leaves the run at `time`, in the state the last of
+    /// them left. Returns the number of steps taken.
+    pub fn step_until(&mut self, time: f64) -> u64 {
+        let start = self.steps;
+
+        while self.time < time {
+            match self.advance(time) {
+                Advance::Fired => {}
+                Advance::Reached => break,
+                Advance::Absorbed => {
+                    // Nothing changes any more, so the state holds until then
+                    if time.is_finite() {
+                        self.time = time;
+                    }
+
+                    break;
+                }
+            }
+        }
+
+        self.steps - start
+    }
+
+    /// Like `step_until`, `dt` after the current time.
+    pub fn advance_by(&mut self, dt: f64) -> u64 {
+        self.step_until(self.time + dt)
+    }
+
+    /// Takes one step, unless it would end after `horizon`.
+    ///
+    /// An event past the horizon is undone, and by the memorylessness of waiting times the
+    /// state at the horizon is the one before it. The visitor is reset, since what it planned
+    /// was not carried out.
+    pub(crate) fn advance(&mut self, horizon: f64) -> Advance {
+        if self.time >= horizon {
+            return Advance::Reached;
+        }
+
+        self.visitor.limit_delay(horizon - self.time);
+        self.system.accept(self.visitor, self.rng);
+
+        let Some(delay) = self.visitor.min_delay() else {
+            return Advance::Absorbed;
+        };
+
+        if self.time + delay > horizon {
+            let model = Arc::clone(&self.system.model);
+            let quantities = &mut self.system.quantities;
+
+            self.visitor.fired_reactions(&mut |reaction, count| {
+                for &(species, change) in &model.changes[reaction] {
+                    quantities[species] -= change * count as i32;
+                }
+            });
+
+            self.visitor.reset();
+            self.time = horizon;
+
+            return Advance::Reached;
+        }
+
+        self.time += delay;
+        self.steps += 1;
+
+        Advance::Fired
+    }
+}
+
+impl StepView<'_, '_> {
+    pub fn time(&self) -> f64 {
+        self.simulation.time
+    }
+
+    /// The reaction that fired, or `None` for a leap that fired several.
+    pub fn reaction(&self) -> Option<usize> {
+        let mut fired = None;
+        let mut count = 0;
+
+        self.simulation.visitor.fired_reactions(&mut |reaction, times| {
+            fired = Some(reaction);
+            count += times;
+        });
+
+        fired.filter(|_| count == 1)
+    }
+
+    /// Calls `record` with every reaction that fired and how many times it did.
+    pub fn fired_reactions(&self, record: &mut dyn FnMut(usize, u32)) {
+        self.simulation.visitor.fired_reactions(record);
+    }
+
+    pub fn system(&self) -> &ChemicalSystem {
+        self.simulation.system
+    }
+
+    pub fn quantities(&self) -> &[i32] {
+        &self.simulation.system.quantities
+    }
+}
+
+impl Iterator for Simulation<'_> {
+    type Item = Event;
+
+    fn next(&mut self) -> Option<Event> {
+        let step = self.step()?;
+
+        Some(Event {
+            time: step.time(),
+            reaction: step.reaction(),
+            quantities: step.quantities().to_vec()
+        })
+    }
+}
@@ -10,7 +10,7 @@ pub struct Species {
 }
 
 impl Species {
-    fn new(name: String, quantity: i32) -> Arc<Mutex<Species>> {
+    pub fn new(name: String, quantity: i32) -> Arc<Mutex<Species>> {
         Arc::new(Mutex::new(Species {name, quantity}))
     }
 
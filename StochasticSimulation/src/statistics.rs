@@ -0,0 +1,473 @@
+use crate::sampling::SamplingMonitor;
+
+/// Mean, standard deviation and quantiles of every sampled species at every grid point,
+/// across the replicates of an ensemble.
+#[derive(Clone, Debug)]
+pub struct EnsembleStatistics {
+    pub times: Vec<f64>,
+    pub species: Vec<String>,
+    pub replicates: usize,
+    // Indexed by species, then grid point
+    pub mean: Vec<Vec<f64>>,
+    pub std_dev: Vec<Vec<f64>>,
+    pub probabilities: Vec<f64>,
+    // Indexed by probability, then species, then grid point
+    pub quantiles: Vec<Vec<Vec<f64>>>
+}
+
+impl EnsembleStatistics {
+    /// Exact statistics of replicates sampled on the same grid. Every sample is looked at
+    /// at once, so all the monitors have to be kept; `StreamingStatistics` does not need that.
+    ///
+    /// Returns `None` when there are no replicates.
+    pub fn from_samples<'a>(monitors: impl IntoIterator<Item = &'a SamplingMonitor>, probabilities: &[f64]) -> Option<Self> {
+        let monitors: Vec<&SamplingMonitor> = monitors.into_iter().collect();
+        let first = monitors.first()?;
+
+        for monitor in &monitors {
+            check_layout(first, monitor);
+        }
+
+        let times = first.times().to_vec();
+        let species = first.species().to_vec();
+
+        let mut mean = vec![vec![0.0; times.len()]; species.len()];
+        let mut std_dev = vec![vec![0.0; times.len()]; species.len()];
+        let mut quantiles = vec![vec![vec![0.0; times.len()]; species.len()]; probabilities.len()];
+        let mut values = Vec::with_capacity(monitors.len());
+
+        for column in 0..species.len() {
+            for point in 0..times.len() {
+                values.clear();
+                values.extend(monitors.iter().map(|monitor| monitor.samples()[point][column] as f64));
+
+                let mut moments = Moments::default();
+                values.iter().for_each(|&value| moments.add(value));
+
+                mean[column][point] = moments.mean;
+                std_dev[column][point] = moments.std_dev();
+
+                values.sort_by(f64::total_cmp);
+
+                for (quantile, &probability) in quantiles.iter_mut().zip(probabilities) {
+                    quantile[column][point] = sorted_quantile(&values, probability);
+                }
+            }
+        }
+
+        Some(EnsembleStatistics {
+            times,
+            species,
+            replicates: monitors.len(),
+            mean,
+            std_dev,
+            probabilities: probabilities.to_vec(),
+            quantiles
+        })
+    }
+
+    pub fn mean_of(&self, species: &str) -> Option<&[f64]> {
+        self.column(species).map(|column| self.mean[column].as_slice())
+    }
+
+    pub fn std_dev_of(&self, species: &str) -> Option<&[f64]> {
+        self.column(species).map(|column| self.std_dev[column].as_slice())
+    }
+
+    /// The quantile series of a species for one of the probabilities the statistics were
+    /// computed for.
+    pub fn quantile_of(&self, species: &str, probability: f64) -> Option<&[f64]> {
+        let column = self.column(species)?;
+        let index = self.probabilities.iter().position(|&p| p == probability)?;
+
+        Some(self.quantiles[index][column].as_slice())
+    }
+
+    fn column(&self, species: &str) -> Option<usize> {
+        self.species.iter().position(|name| name == species)
+    }
+}
+
+/// Accumulates ensemble statistics one replicate at a time, in memory that does not grow
+/// with the number of replicates. Means and variances use Welford's update and quantiles
+/// the P² estimator of Jain and Chlamtac, so quantiles are approximate.
+#[derive(Clone, Debug)]
+pub struct StreamingStatistics {
+    probabilities: Vec<f64>,
+    times: Vec<f64>,
+    species: Vec<String>,
+    replicates: usize,
+    // Indexed by species, then grid point
+    moments: Vec<Vec<Moments>>,
+    // Indexed by probability, then species, then grid point
+    estimators: Vec<Vec<Vec<P2Quantile>>>
+}
+
+impl StreamingStatistics {
+    pub fn new(probabilities: &[f64]) -> Self {
+        StreamingStatistics {
+            probabilities: probabilities.to_vec(),
+            times: Vec::new(),
+            species: Vec::new(),
+            replicates: 0,
+            moments: Vec::new(),
+            estimators: Vec::new()
+        }
+    }
+
+    pub fn replicates(&self) -> usize {
+        self.replicates
+    }
+
+    /// Adds a finished replicate. Every replicate must be sampled on the same grid and
+    /// species as the first one.
+    pub fn add(&mut self, monitor: &SamplingMonitor) {
+        assert!(monitor.is_complete(), "replicate has unfilled grid points, call `finish` first");
+
+        if self.replicates == 0 {
+            self.times = monitor.times().to_vec();
+            self.species = monitor.species().to_vec();
+            self.moments = vec![vec![Moments::default(); self.times.len()]; self.species.len()];
+            self.estimators = self.probabilities.iter()
+                .map(|&probability| vec![vec![P2Quantile::new(probability); self.times.len()]; self.species.len()])
+                .collect();
+        } else {
+            assert!(self.times == monitor.times() && self.species == monitor.species(),
+                    "replicate was sampled on a different grid or species");
+        }
+
+        for (point, row) in monitor.samples().iter().enumerate() {
+            for (column, &quantity) in row.iter().enumerate() {
+                let value = quantity as f64;
+
+                self.moments[column][point].add(value);
+
+                for estimator in self.estimators.iter_mut() {
+                    estimator[column][point].add(value);
+                }
+            }
+        }
+
+        self.replicates += 1;
+    }
+
+    /// The statistics of the replicates added so far, or `None` if there are none.
+    pub fn statistics(&self) -> Option<EnsembleStatistics> {
+        if self.replicates == 0 {
+            return None;
+        }
+
+        let map = |moments: &[Vec<Moments>], value: fn(&Moments) -> f64| -> Vec<Vec<f64>> {
+            moments.iter().map(|series| series.iter().map(value).collect()).collect()
+        };
+
+        Some(EnsembleStatistics {
+            times: self.times.clone(),
+            species: self.species.clone(),
+            replicates: self.replicates,
+            mean: map(&self.moments, |moments| moments.mean),
+            std_dev: map(&self.moments, Moments::std_dev),
+            probabilities: self.probabilities.clone(),
+            quantiles: self.estimators.iter()
+                .map(|estimator| {
+                    estimator.iter()
+                        .map(|series| series.iter().map(P2Quantile::value).collect())
+                        .collect()
+                })
+                .collect()
+        })
+    }
+}
+
+/// Distribution of the quantity of one species across the replicates of an ensemble at one
+/// grid point, in bins of `width` copies starting at `low`.
+#[derive(Clone, Debug)]
+pub struct Histogram {
+    pub species: String,
+    pub time: f64,
+    pub low: i32,
+    pub width: u32,
+    pub counts: Vec<usize>
+}
+
+impl Histogram {
+    /// The histogram of `species` at `time`, which has to be one of the grid points the
+    /// replicates were sampled on. Returns `None` when there are no replicates, or the
+    /// species or time was not sampled.
+    pub fn from_samples<'a>(monitors: impl IntoIterator<Item = &'a SamplingMonitor>, species: &str, time: f64, width: u32) -> Option<Self> {
+        let monitors: Vec<&SamplingMonitor> = monitors.into_iter().collect();
+        let first = monitors.first()?;
+
+        for monitor in &monitors {
+            check_layout(first, monitor);
+        }
+
+        let column = first.species().iter().position(|name| name == species)?;
+        let point = grid_point(first.times(), time)?;
+        let values: Vec<i32> = monitors.iter().map(|monitor| monitor.samples()[point][column]).collect();
+        let (low, bins) = bins(values.iter().copied(), width);
+        let mut counts = vec![0; bins];
+
+        for value in values {
+            counts[bin(value, low, width)] += 1;
+        }
+
+        Some(Histogram {
+            species: species.to_string(),
+            time: first.times()[point],
+            low,
+            width: width.max(1),
+            counts
+        })
+    }
+
+    pub fn total(&self) -> usize {
+        self.counts.iter().sum()
+    }
+
+    /// The counts divided by the number of replicates.
+    pub fn probabilities(&self) -> Vec<f64> {
+        let total = self.total().max(1) as f64;
+
+        self.counts.iter().map(|&count| count as f64 / total).collect()
+    }
+
+    /// The first quantity in a bin; the bin holds `width` quantities from there.
+    pub fn bin_start(&self, bin: usize) -> i32 {
+        self.low + (bin as u32 * self.width) as i32
+    }
+}
+
+/// Histograms of one species at every grid point, over the same bins, showing how its
+/// distribution across the replicates evolves.
+#[derive(Clone, Debug)]
+pub struct DistributionHeatmap {
+    pub species: String,
+    pub times: Vec<f64>,
+    pub low: i32,
+    pub width: u32,
+    // Indexed by grid point, then bin
+    pub counts: Vec<Vec<usize>>
+}
+
+impl DistributionHeatmap {
+    /// Returns `None` when there are no replicates or the species was not sampled.
+    pub fn from_samples<'a>(monitors: impl IntoIterator<Item = &'a SamplingMonitor>, species: &str, width: u32) -> Option<Self> {
+        let monitors: Vec<&SamplingMonitor> = monitors.into_iter().collect();
+        let first = monitors.first()?;
+
+        for monitor in &monitors {
+            check_layout(first, monitor);
+        }
+
+        let column = first.species().iter().position(|name| name == species)?;
+        let values = || monitors.iter().flat_map(|monitor| monitor.samples().iter().map(move |row| row[column]));
+        let (low, bins) = bins(values(), width);
+        let mut counts = vec![vec![0; bins]; first.times().len()];
+
+        for monitor in &monitors {
+            for (point, row) in monitor.samples().iter().enumerate() {
+                counts[point][bin(row[column], low, width)] += 1;
+            }
+        }
+
+        Some(DistributionHeatmap {
+            species: species.to_string(),
+            times: first.times().to_vec(),
+            low,
+            width: width.max(1),
+            counts
+        })
+    }
+
+    /// The counts at every grid point divided by the number of replicates.
+    pub fn probabilities(&self) -> Vec<Vec<f64>> {
+        self.counts.iter()
+            .map(|counts| {
+                let total = counts.iter().sum::<usize>().max(1) as f64;
+
+                counts.iter().map(|&count| count as f64 / total).collect()
+            })
+            .collect()
+    }
+
+    pub fn bin_start(&self, bin: usize) -> i32 {
+        self.low + (bin as u32 * self.width) as i32
+    }
+}
+
+// The grid point at `time`, allowing for rounding in how the grid was computed
+fn grid_point(times: &[f64], time: f64) -> Option<usize> {
+    let tolerance = 1e-9 * time.abs().max(1.0);
+
+    times.iter().position(|&point| (point - time).abs() <= tolerance)
+}
+
+// First bin start and number of bins covering every value. Bins are aligned to multiples
+// of the width, so histograms of different times line up.
+fn bins(values: impl Iterator<Item = i32>, width: u32) -> (i32, usize) {
+    let width = width.max(1) as i64;
+    let (min, max) = values.fold((i32::MAX, i32::MIN), |(min, max), value| (min.min(value), max.max(value)));
+
+    if min > max {
+        return (0, 0);
+    }
+
+    let low = (min as i64).div_euclid(width) * width;
+
+    (low as i32, ((max as i64 - low) / width + 1) as usize)
+}
+
+fn bin(value: i32, low: i32, width: u32) -> usize {
+    ((value as i64 - low as i64) / width.max(1) as i64) as usize
+}
+
+fn check_layout(first: &SamplingMonitor, monitor: &SamplingMonitor) {
+    assert!(monitor.is_complete(), "replicate has unfilled grid points, call `finish` first");
+    assert!(first.times() == monitor.times() && first.species() == monitor.species(),
+            "replicate was sampled on a different grid or species");
+}
+
+// Linear interpolation between the closest ranks, like numpy's default
+fn sorted_quantile(sorted: &[f64], probability: f64) -> f64 {
+    let rank = probability.clamp(0.0, 1.0) * (sorted.len() - 1) as f64;
+    let lower = rank.floor() as usize;
+    let upper = rank.ceil() as usize;
+
+    sorted[lower] + (rank - lower as f64) * (sorted[upper] - sorted[lower])
+}
+
+// Welford's running mean and sum of squared deviations
+#[derive(Clone, Copy, Debug, Default)]
+struct Moments {
+    count: usize,
+    mean: f64,
+    squares: f64
+}
+
+impl Moments {
+    fn add(&mut self, value: f64) {
+        self.count += 1;
+
+        let delta = value - self.mean;
+        self.mean += delta / self.count as f64;
+        self.squares += delta * (value - self.mean);
+    }
+
+    // Sample standard deviation, zero for fewer than two values
+    fn std_dev(&self) -> f64 {
+        if self.count < 2 {
+            0.0
+        } else {
+            (self.squares / (self.count - 1) as f64).sqrt()
+        }
+    }
+}
+
+// P² estimate of one quantile from five markers, Jain and Chlamtac (1985)
+#[derive(Clone, Debug)]
+struct P2Quantile {
+    probability: f64,
+    count: usize,
+    heights: [f64; 5],
+    positions: [f64; 5],
+    desired: [f64; 5],
+    increments: [f64; 5]
+}
+
+impl P2Quantile {
+    fn new(probability: f64) -> Self {
+        let p = probability.clamp(0.0, 1.0);
+
+        P2Quantile {
+            probability: p,
+            count: 0,
+            heights: [0.0; 5],
+            positions: [1.0, 2.0, 3.0, 4.0, 5.0],
+            desired: [1.0, 1.0 + 2.0 * p, 1.0 + 4.0 * p, 3.0 + 2.0 * p, 5.0],
+            increments: [0.0, p / 2.0, p, (1.0 + p) / 2.0, 1.0]
+        }
+    }
+
+    fn add(&mut self, value: f64) {
+        // The first five values are kept as they are and become the initial markers
+        if self.count < 5 {
+            self.heights[self.count] = value;
+            self.count += 1;
+
+            if self.count == 5 {
+                self.heights.sort_by(f64::total_cmp);
+            }
+
+            return;
+        }
+
+        self.count += 1;
+
+        let cell = if value < self.heights[0] {
+            self.heights[0] = value;
+            0
+        } else if value >= self.heights[4] {
+            self.heights[4] = value;
+            3
+        } else {
+            (0..4).find(|&i| value < self.heights[i + 1]).unwrap()
+        };
+
+        for position in &mut self.positions[cell + 1..] {
+            *position += 1.0;
+        }
+
+        for (desired, increment) in self.desired.iter_mut().zip(&self.increments) {
+            *desired += increment;
+        }
+
+        for i in 1..4 {
+            let offset = self.desired[i] - self.positions[i];
+
+            if (offset >= 1.0 && self.positions[i + 1] - self.positions[i] > 1.0)
+                || (offset <= -1.0 && self.positions[i - 1] - self.positions[i] < -1.0) {
+                let step = offset.signum();
+                let parabolic = self.parabolic(i, step);
+
+                self.heights[i] = if self.heights[i - 1] < parabolic && parabolic < self.heights[i + 1] {
+                    parabolic
+                } else {
+                    self.linear(i, step)
+                };
+                self.positions[i] += step;
+            }
+        }
+    }
+
+    fn parabolic(&self, i: usize, step: f64) -> f64 {
+        let (q, n) = (&self.heights, &self.positions);
+
+        q[i] + step / (n[i + 1] - n[i - 1]) * (
+            (n[i] - n[i - 1] + step) * (q[i + 1] - q[i]) / (n[i + 1] - n[i])
+                + (n[i + 1] - n[i] - step) * (q[i] - q[i - 1]) / (n[i] - n[i - 1])
+        )
+    }
+
+    fn linear(&self, i: usize, step: f64) -> f64 {
+        let neighbour = if step > 0.0 { i + 1 } else { i - 1 };
+
+        self.heights[i] + step * (self.heights[neighbour] - self.heights[i]) / (self.positions[neighbour] - self.positions[i])
+    }
+
+    fn value(&self) -> f64 {
+        if self.count >= 5 {
+            return self.heights[2];
+        }
+
+        // Too few values for the markers, so the quantile is exact
+        let mut sorted = self.heights[..self.count].to_vec();
+        sorted.sort_by(f64::total_cmp);
+
+        if sorted.is_empty() {
+            f64::NAN
+        } else {
+            sorted_quantile(&sorted, self.probability)
+        }
+    }
+}
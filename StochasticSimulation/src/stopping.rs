@@ -0,0 +1,114 @@
+use std::fmt;
+use std::sync::Arc;
+use std::time::{Duration, Instant};
+use crate::system::ChemicalSystem;
+
+type Predicate = Arc<dyn Fn(&ChemicalSystem) -> bool + Send + Sync>;
+
+/// When a simulation stops. Whichever condition is met first ends the run.
+///
+/// A run always stops at its end time, which may be infinite when another condition is
+/// certain to end it, and when no reaction can fire any more.
+#[derive(Clone)]
+pub struct StopConditions {
+    pub(crate) end_time: f64,
+    pub(crate) max_steps: Option<u64>,
+    pub(crate) wall_clock: Option<Duration>,
+    pub(crate) predicates: Vec<Predicate>
+}
+
+impl StopConditions {
+    pub fn at(end_time: f64) -> Self {
+        StopConditions {
+            end_time,
+            max_steps: None,
+            wall_clock: None,
+            predicates: Vec::new()
+        }
+    }
+
+    /// Stops after this many events, or leaps when tau-leaping.
+    pub fn max_steps(mut self, steps: u64) -> Self {
+        self.max_steps = Some(steps);
+        self
+    }
+
+    /// Stops once the run has taken this long in real time.
+    pub fn wall_clock(mut self, budget: Duration) -> Self {
+        self.wall_clock = Some(budget);
+        self
+    }
+
+    /// Stops as soon as `predicate` holds, checked on the initial state and after every step.
+    /// Conditions are numbered in the order they were added, for `StopReason::Condition`.
+    pub fn stop_when(mut self, predicate: impl Fn(&ChemicalSystem) -> bool + Send + Sync + 'static) -> Self {
+        self.predicates.push(Arc::new(predicate));
+        self
+    }
+
+    pub fn end_time(&self) -> f64 {
+        self.end_time
+    }
+
+    // The reason to stop after `steps` steps of a run started at `start`, other than time
+    pub(crate) fn check(&self, system: &ChemicalSystem, steps: u64, start: Instant) -> Option<StopReason> {
+        if let Some(index) = self.predicates.iter().position(|predicate| predicate(system)) {
+            return Some(StopReason::Condition(index));
+        }
+
+        if self.max_steps.is_some_and(|max_steps| steps >= max_steps) {
+            return Some(StopReason::MaxSteps);
+        }
+
+        if self.wall_clock.is_some_and(|budget| start.elapsed() >= budget) {
+            return Some(StopReason::WallClock);
+        }
+
+        None
+    }
+}
+
+impl fmt::Debug for StopConditions {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("StopConditions")
+            .field("end_time", &self.end_time)
+            .field("max_steps", &self.max_steps)
+            .field("wall_clock", &self.wall_clock)
+            .field("predicates", &self.predicates.len())
+            .finish()
+    }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum StopReason {
+    EndTime,
+    MaxSteps,
+    /// Every propensity is zero, so nothing can happen any more.
+    Absorbed,
+    WallClock,
+    /// The predicate with this index, in the order they were added, holds.
+    Condition(usize)
+}
+
+impl fmt::Display for StopReason {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            StopReason::EndTime => write!(f, "reached the end time"),
+            StopReason::MaxSteps => write!(f, "took the maximum number of steps"),
+            StopReason::Absorbed => write!(f, "reached an absorbing state"),
+            StopReason::WallClock => write!(f, "ran out of wall clock time"),
+            StopReason::Condition(index) => write!(f, "stop condition {} holds", index)
+        }
+    }
+}
+
+/// How a run ended.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct SimulationSummary {
+    pub reason: StopReason,
+    /// Simulated time the run stopped at. An absorbed run is taken to last until its end time
+    /// when that is finite, since nothing changes any more.
+    pub time: f64,
+    pub steps: u64,
+    pub elapsed: Duration
+}
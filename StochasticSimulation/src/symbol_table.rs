@@ -4,21 +4,30 @@ use uuid::Uuid;
 
 #[derive(Clone)]
 pub struct SymbolTable<T> {
-    pub symbols: HashMap<Uuid, Arc<Mutex<T>>>
+    pub symbols: HashMap<Uuid, Arc<Mutex<T>>>,
+    // Insertion order of the ids, so iterating the table does not depend on the hasher
+    order: Vec<Uuid>
 }
 
 impl<T> SymbolTable<T> {
     pub(crate) fn new() -> Self {
         Self {
-            symbols: HashMap::new()
+            symbols: HashMap::new(),
+            order: Vec::new()
         }
     }
 
     pub(crate) fn insert(&mut self, id: Uuid, species: Arc<Mutex<T>>) {
-        self.symbols.insert(id, species);
+        if self.symbols.insert(id, species).is_none() {
+            self.order.push(id);
+        }
     }
 
-    pub(crate) fn lookup(&self, id: Uuid) -> Option<Arc<Mutex<T>>> {
+    pub fn lookup(&self, id: Uuid) -> Option<Arc<Mutex<T>>> {
         self.symbols.get(&id).cloned()
     }
-}
\ No newline at end of file
+
+    pub(crate) fn values(&self) -> impl Iterator<Item = &Arc<Mutex<T>>> {
+        self.order.iter().map(|id| &self.symbols[id])
+    }
+}
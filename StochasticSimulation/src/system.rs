@@ -1,14 +1,49 @@
 use std::sync::{Arc, Mutex};
 use std::time::Instant;
-use rand::rngs::StdRng;
-use crate::monitor::{FilterableMonitor, Monitor};
+use rand_chacha::ChaCha12Rng;
+use crate::checkpoint::{Checkpoint, CheckpointError, CheckpointOptions, MonitorJournal, ResumableMonitor};
+use crate::model::Model;
+use crate::monitor::FilterableMonitor;
 use crate::reaction::{Reaction, SpeciesRole};
+use crate::simulation::{Advance, Simulation};
+use crate::stopping::{SimulationSummary, StopConditions, StopReason};
 use crate::symbol_table::SymbolTable;
-use crate::visitor::Visitor;
+use crate::tau_leaping::{TauLeapingOptions, TauLeapingVisitor};
+use crate::visitor::{DirectMethodVisitor, NextReactionVisitor, SystemVisitor, Visitor};
 
+/// The stochastic simulation algorithm used by `ChemicalSystem::simulate`.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Algorithm {
+    /// Samples a delay for every reaction on every step and fires the earliest one.
+    FirstReaction,
+    /// Gillespie's direct method.
+    Direct,
+    /// Gibson and Bruck's next reaction method, which scales better with many reactions.
+    NextReaction,
+    /// Adaptive tau-leaping with the default `TauLeapingOptions`.
+    TauLeaping
+}
+
+impl Algorithm {
+    pub fn visitor(&self) -> Box<dyn Visitor> {
+        match self {
+            Algorithm::FirstReaction => Box::new(SystemVisitor::new()),
+            Algorithm::Direct => Box::new(DirectMethodVisitor::new()),
+            Algorithm::NextReaction => Box::new(NextReactionVisitor::new()),
+            Algorithm::TauLeaping => Box::new(TauLeapingVisitor::new(TauLeapingOptions::default()))
+        }
+    }
+}
+
+/// A compiled reaction network and the species quantities of one trajectory through it.
+///
+/// The species and reactions passed to `new` only describe the network. Simulating changes
+/// the quantities held by the system, and cloning it shares the model and copies the state.
 #[derive(Clone)]
 pub struct ChemicalSystem {
-    pub(crate) symbol_table: SymbolTable<Reaction>
+    pub(crate) model: Arc<Model>,
+    pub(crate) quantities: Vec<i32>,
+    pub(crate) algorithm: Algorithm
 }
 
 impl ChemicalSystem {
@@ -16,40 +51,217 @@ impl ChemicalSystem {
         let mut symbol_table = SymbolTable::new();
 
         for reaction in &reactions {
-            let mut reaction_guard = reaction.lock().unwrap();
+            let reaction_guard = reaction.lock().unwrap();
 
             symbol_table.insert(reaction_guard.uuid, reaction.clone())
         }
 
-        Self {symbol_table}
+        let (model, quantities) = Model::compile(symbol_table.values());
+
+        Self {model: Arc::new(model), quantities, algorithm: Algorithm::Direct}
+    }
+
+    pub fn model(&self) -> &Model {
+        &self.model
+    }
+
+    /// Current quantity of every species, indexed like `Model::species`.
+    pub fn quantities(&self) -> &[i32] {
+        &self.quantities
+    }
+
+    pub fn quantity(&self, species: &str) -> Option<i32> {
+        self.model.species_index(species).map(|index| self.quantities[index])
+    }
+
+    pub fn set_algorithm(&mut self, algorithm: Algorithm) {
+        self.algorithm = algorithm;
     }
 
-    pub fn accept(&self, visitor: &mut dyn Visitor, rng: &mut StdRng) {
+    pub fn accept(&mut self, visitor: &mut dyn Visitor, rng: &mut ChaCha12Rng) {
         visitor.visit_system(rng, self);
     }
 
+    /// Runs `simulation` with a fresh visitor for the selected algorithm.
+    pub fn simulate(&mut self,
+                    end_time: f64,
+                    rng: &mut ChaCha12Rng,
+                    monitor: &mut dyn FilterableMonitor<ChemicalSystem>,
+                    species_to_record: &[(&str, SpeciesRole)]) -> SimulationSummary {
+        self.simulate_until(&StopConditions::at(end_time), rng, monitor, species_to_record)
+    }
+
+    /// Runs `simulation_until` with a fresh visitor for the selected algorithm.
+    pub fn simulate_until(&mut self,
+                          conditions: &StopConditions,
+                          rng: &mut ChaCha12Rng,
+                          monitor: &mut dyn FilterableMonitor<ChemicalSystem>,
+                          species_to_record: &[(&str, SpeciesRole)]) -> SimulationSummary {
+        let mut visitor = self.algorithm.visitor();
+
+        self.simulation_until(conditions, visitor.as_mut(), rng, monitor, species_to_record)
+    }
+
     pub fn simulation(&mut self,
                       end_time: f64,
                       visitor: &mut dyn Visitor,
-                      rng: &mut StdRng,
-                      monitor: &mut dyn FilterableMonitor<Vec<Arc<Mutex<Reaction>>>>,
-                      species_to_record: &[(&str, SpeciesRole)]) {
+                      rng: &mut ChaCha12Rng,
+                      monitor: &mut dyn FilterableMonitor<ChemicalSystem>,
+                      species_to_record: &[(&str, SpeciesRole)]) -> SimulationSummary {
+        self.simulation_until(&StopConditions::at(end_time), visitor, rng, monitor, species_to_record)
+    }
+
+    /// Simulates from time zero until one of `conditions` is met, recording the state after
+    /// every step. This is a `Simulation` stepped to the end time.
+    ///
+    /// An event that would happen after the end time is undone, and the final state is
+    /// recorded at the end time instead.
+    pub fn simulation_until(&mut self,
+                            conditions: &StopConditions,
+                            visitor: &mut dyn Visitor,
+                            rng: &mut ChaCha12Rng,
+                            monitor: &mut dyn FilterableMonitor<ChemicalSystem>,
+                            species_to_record: &[(&str, SpeciesRole)]) -> SimulationSummary {
+
+        let mut simulation = Simulation::new(self, visitor, rng);
+
+        monitor.record_state_with_filter(simulation.time, simulation.system, species_to_record);
+        simulation.run_until(conditions, monitor, species_to_record)
+    }
+
+    /// Like `simulate_until`, but writes checkpoints of the run and of what `monitor` recorded
+    /// as set by `checkpoints`, so that a run that is killed can be taken up with `resume`.
+    pub fn simulate_with_checkpoints(&mut self,
+                                     conditions: &StopConditions,
+                                     checkpoints: &CheckpointOptions,
+                                     rng: &mut ChaCha12Rng,
+                                     monitor: &mut dyn ResumableMonitor,
+                                     species_to_record: &[(&str, SpeciesRole)]) -> Result<SimulationSummary, CheckpointError> {
+
+        let start_time_instant = Instant::now();
+        let mut visitor = self.algorithm.visitor();
+        let mut simulation = Simulation::new(self, visitor.as_mut(), rng);
+
+        monitor.record_state_with_filter(simulation.time, simulation.system, species_to_record);
+
+        let journal = MonitorJournal::new(checkpoints, None);
+        let reason = run_with_checkpoints(&mut simulation, conditions, checkpoints, journal, monitor, species_to_record, start_time_instant)?;
+
+        Ok(summarise(reason, &simulation, start_time_instant))
+    }
+
+    /// Continues the run `checkpoint` was taken from until one of `conditions` is met, with
+    /// the algorithm it was taken with, and goes on writing checkpoints as set by
+    /// `checkpoints`. `monitor` first gets back what was recorded up to the checkpoint, if
+    /// that was stored, so it ends up as if the run had never stopped.
+    ///
+    /// The system has to be of the same reaction network, and its quantities are replaced
+    /// with the checkpointed ones.
+    pub fn resume(&mut self,
+                  checkpoint: &Checkpoint,
+                  conditions: &StopConditions,
+                  checkpoints: &CheckpointOptions,
+                  monitor: &mut dyn ResumableMonitor,
+                  species_to_record: &[(&str, SpeciesRole)]) -> Result<SimulationSummary, CheckpointError> {
 
         let start_time_instant = Instant::now();
-        let mut start_time = 0.0;
+        let mut visitor = checkpoint.algorithm.visitor();
+        let mut rng = checkpoint.rng();
+
+        self.algorithm = checkpoint.algorithm;
+
+        let mut simulation = Simulation::resume(self, visitor.as_mut(), &mut rng, checkpoint)?;
+
+        checkpoint.restore_monitor(monitor, simulation.system)?;
+
+        let journal = MonitorJournal::new(checkpoints, Some(checkpoint));
+        let reason = run_with_checkpoints(&mut simulation, conditions, checkpoints, journal, monitor, species_to_record, start_time_instant)?;
+
+        Ok(summarise(reason, &simulation, start_time_instant))
+    }
+}
+
+// Steps `simulation` until one of `conditions` is met, recording the state after every step,
+// and calls `after_step` after every event
+pub(crate) fn run<'a, M, E>(simulation: &mut Simulation<'a>,
+                            conditions: &StopConditions,
+                            monitor: &mut M,
+                            species_to_record: &[(&str, SpeciesRole)],
+                            start_time_instant: Instant,
+                            mut after_step: impl FnMut(&mut Simulation<'a>, &mut M) -> Result<(), E>) -> Result<StopReason, E>
+where
+    M: FilterableMonitor<ChemicalSystem> + ?Sized
+{
+    let end_time = conditions.end_time;
+
+    let reason = loop {
+        if let Some(reason) = conditions.check(simulation.system, simulation.steps, start_time_instant) {
+            break reason;
+        }
+
+        // A shortened leap can end exactly at the end time, which was recorded already
+        if simulation.time >= end_time {
+            break StopReason::EndTime;
+        }
+
+        match simulation.advance(end_time) {
+            Advance::Fired => {
+                let Simulation { system, visitor, time, .. } = &*simulation;
+
+                visitor.fired_reactions(&mut |reaction, count| monitor.record_firing(*time, system, reaction, count));
+                monitor.record_state_with_filter(*time, system, species_to_record);
+
+                after_step(simulation, monitor)?;
+            }
+            Advance::Reached => {
+                monitor.record_state_with_filter(simulation.time, simulation.system, species_to_record);
+
+                break StopReason::EndTime;
+            }
+            Advance::Absorbed => {
+                // Nothing changes any more, so the state holds until the end
+                if end_time.is_finite() {
+                    simulation.time = end_time;
+                    monitor.record_state_with_filter(simulation.time, simulation.system, species_to_record);
+                }
 
-        while start_time <= end_time {
-            self.accept(visitor, rng);
+                break StopReason::Absorbed;
+            }
+        }
+    };
+
+    Ok(reason)
+}
 
-            let min_delay = visitor.min_delay().unwrap_or(f64::MAX);
+fn run_with_checkpoints(simulation: &mut Simulation<'_>,
+                        conditions: &StopConditions,
+                        checkpoints: &CheckpointOptions,
+                        mut journal: MonitorJournal,
+                        monitor: &mut dyn ResumableMonitor,
+                        species_to_record: &[(&str, SpeciesRole)],
+                        start_time_instant: Instant) -> Result<StopReason, CheckpointError> {
+    let mut previous_time = simulation.time;
 
-            start_time += min_delay;
+    run(simulation, conditions, monitor, species_to_record, start_time_instant, |simulation, monitor| {
+        if checkpoints.is_due(previous_time, simulation.time, simulation.steps) {
+            let mut checkpoint = simulation.checkpoint();
 
-            let reactions_vec: Vec<_> = self.symbol_table.symbols.values().cloned().collect();
-            monitor.record_state_with_filter(start_time, &reactions_vec, &species_to_record);
+            // The journal is added to before the checkpoint that refers to it is written
+            checkpoint.journal_length = Some(journal.append(monitor)?);
+            checkpoint.write(&checkpoints.path)?;
         }
 
-        let duration = Instant::now() - start_time_instant;
-        println!("Simulation took: {:?}", duration);
+        previous_time = simulation.time;
+
+        Ok(())
+    })
+}
+
+pub(crate) fn summarise(reason: StopReason, simulation: &Simulation<'_>, start_time_instant: Instant) -> SimulationSummary {
+    SimulationSummary {
+        reason,
+        time: simulation.time,
+        steps: simulation.steps,
+        elapsed: start_time_instant.elapsed()
     }
-}
\ No newline at end of file
+}
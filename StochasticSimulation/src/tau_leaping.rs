@@ -0,0 +1,325 @@
+use std::sync::Arc;
+use rand::Rng;
+use rand_chacha::ChaCha12Rng;
+use rand_distr::{Exp, Poisson};
+use crate::checkpoint::{CheckpointError, StateReader, StateWriter};
+use crate::model::Model;
+use crate::system::ChemicalSystem;
+use crate::visitor::{DirectMethodVisitor, Visitor};
+
+#[derive(Clone, Debug)]
+pub struct TauLeapingOptions {
+    /// Leap size for explicit tau-leaping. `None` selects the step adaptively
+    /// with the Cao, Gillespie and Petzold bound on relative propensity change.
+    pub fixed_tau: Option<f64>,
+    /// Allowed relative change in propensities during one leap.
+    pub epsilon: f64,
+    /// Reactions that can fire fewer times than this before exhausting a reactant are
+    /// critical, and fire at most once per leap.
+    pub critical_threshold: i32,
+    /// Adaptive leaping is abandoned when tau is smaller than this many expected SSA steps.
+    /// A fixed tau is always leaped.
+    pub ssa_threshold: f64,
+    /// Number of exact steps taken every time adaptive leaping is abandoned.
+    pub ssa_steps: usize
+}
+
+impl Default for TauLeapingOptions {
+    fn default() -> Self {
+        TauLeapingOptions {
+            fixed_tau: None,
+            epsilon: 0.03,
+            critical_threshold: 10,
+            ssa_threshold: 10.0,
+            ssa_steps: 100
+        }
+    }
+}
+
+/// Tau-leaping: fires every non-critical reaction a Poisson distributed number of times
+/// per step. Adaptive leaping falls back to the exact direct method when leaps would be too
+/// short, while a fixed tau is only halved when a leap would drive a species negative.
+#[derive(Clone)]
+pub struct TauLeapingVisitor {
+    options: TauLeapingOptions,
+    min_delay: Option<f64>,
+    reaction_with_min_delay: Option<usize>,
+    exact: DirectMethodVisitor,
+    ssa_steps_remaining: usize,
+    // Whether the last visit leaped, leaving its firings in `firings`
+    leaped: bool,
+    // Longest leap the simulation can use
+    max_delay: f64,
+    // Per leap buffers, kept so that leaping does not allocate
+    propensities: Vec<f64>,
+    critical: Vec<bool>,
+    firings: Vec<i32>,
+    new_quantities: Vec<i64>,
+    highest_order: Vec<f64>,
+    mean_change: Vec<f64>,
+    variance_change: Vec<f64>
+}
+
+impl TauLeapingVisitor {
+    pub fn new(options: TauLeapingOptions) -> Self {
+        TauLeapingVisitor {
+            options,
+            min_delay: None,
+            reaction_with_min_delay: None,
+            exact: DirectMethodVisitor::new(),
+            ssa_steps_remaining: 0,
+            leaped: false,
+            max_delay: f64::INFINITY,
+            propensities: Vec::new(),
+            critical: Vec::new(),
+            firings: Vec::new(),
+            new_quantities: Vec::new(),
+            highest_order: Vec::new(),
+            mean_change: Vec::new(),
+            variance_change: Vec::new()
+        }
+    }
+
+    fn exact_step(&mut self, rng: &mut ChaCha12Rng, system: &mut ChemicalSystem) {
+        self.ssa_steps_remaining = self.ssa_steps_remaining.saturating_sub(1);
+        self.exact.visit_system(rng, system);
+        self.min_delay = self.exact.min_delay();
+        self.reaction_with_min_delay = self.exact.reaction_with_min_delay();
+    }
+
+    // Number of times a reaction can fire before one of its reactants runs out
+    fn firings_left(model: &Model, reaction: usize, quantities: &[i32]) -> i32 {
+        model.changes[reaction].iter()
+            .filter(|(_, change)| *change < 0)
+            .map(|&(species, change)| quantities[species] / -change)
+            .min()
+            .unwrap_or(i32::MAX)
+    }
+
+    // Cao, Gillespie and Petzold (2006), equation 33
+    fn adaptive_tau(&mut self, model: &Model, quantities: &[i32]) -> f64 {
+        let species_count = model.species.len();
+
+        for buffer in [&mut self.highest_order, &mut self.mean_change, &mut self.variance_change] {
+            buffer.clear();
+            buffer.resize(species_count, 0.0);
+        }
+
+        for (reaction, reactants) in model.reactants.iter().enumerate() {
+            let order: u32 = reactants.iter().map(|(_, count)| count).sum();
+
+            for &(species, count) in reactants {
+                // Clamped so the small copy number corrections stay finite
+                let x = quantities[species].max(3) as f64;
+
+                let g = match (order, count) {
+                    (2, 2) => 2.0 + 1.0 / (x - 1.0),
+                    (3, 2) => 1.5 * (2.0 + 1.0 / (x - 1.0)),
+                    (3, 3) => 3.0 + 1.0 / (x - 1.0) + 2.0 / (x - 2.0),
+                    _ => order as f64
+                };
+
+                self.highest_order[species] = self.highest_order[species].max(g);
+            }
+
+            if self.critical[reaction] {
+                continue;
+            }
+
+            for &(species, change) in &model.changes[reaction] {
+                self.mean_change[species] += change as f64 * self.propensities[reaction];
+                self.variance_change[species] += (change * change) as f64 * self.propensities[reaction];
+            }
+        }
+
+        let mut tau = f64::INFINITY;
+
+        for (species, &quantity) in quantities.iter().enumerate() {
+            // Only species that are consumed by some reaction can limit the step
+            if self.highest_order[species] == 0.0 {
+                continue;
+            }
+
+            let bound = (self.options.epsilon * quantity as f64 / self.highest_order[species]).max(1.0);
+
+            if self.mean_change[species] != 0.0 {
+                tau = tau.min(bound / self.mean_change[species].abs());
+            }
+
+            if self.variance_change[species] != 0.0 {
+                tau = tau.min(bound * bound / self.variance_change[species]);
+            }
+        }
+
+        tau
+    }
+
+    fn leap(&mut self, rng: &mut ChaCha12Rng, system: &mut ChemicalSystem) {
+        let model = Arc::clone(&system.model);
+
+        self.propensities.clear();
+        self.propensities.extend((0..model.reaction_count()).map(|reaction| model.propensity(reaction, &system.quantities)));
+        let total_propensity: f64 = self.propensities.iter().sum();
+
+        // Nothing can fire, so there is no leap to take and no exact step either
+        if total_propensity <= 0.0 {
+            return;
+        }
+
+        self.critical.clear();
+        self.critical.extend(self.propensities.iter().enumerate()
+            .map(|(reaction, &propensity)| {
+                propensity > 0.0
+                    && Self::firings_left(&model, reaction, &system.quantities) < self.options.critical_threshold
+            }));
+
+        let mut tau_prime = match self.options.fixed_tau {
+            Some(tau) => tau,
+            None => {
+                let tau = self.adaptive_tau(&model, &system.quantities);
+
+                // Leaping over fewer than a handful of events is less efficient than simulating them
+                if !tau.is_finite() || tau < self.options.ssa_threshold / total_propensity {
+                    self.ssa_steps_remaining = self.options.ssa_steps;
+                    self.exact_step(rng, system);
+                    return;
+                }
+
+                tau
+            }
+        };
+
+        // Leaping past the end of the simulation would count events that never happen
+        tau_prime = tau_prime.min(self.max_delay);
+
+        let critical_propensity: f64 = self.propensities.iter().zip(&self.critical)
+            .filter(|(_, &is_critical)| is_critical)
+            .map(|(propensity, _)| propensity)
+            .sum();
+
+        loop {
+            let tau_critical = if critical_propensity > 0.0 {
+                rng.sample(Exp::new(critical_propensity).unwrap())
+            } else {
+                f64::INFINITY
+            };
+
+            let tau = tau_prime.min(tau_critical);
+
+            self.firings.clear();
+            self.firings.resize(self.propensities.len(), 0);
+
+            for (reaction, &propensity) in self.propensities.iter().enumerate() {
+                if !self.critical[reaction] && propensity > 0.0 {
+                    self.firings[reaction] = rng.sample(Poisson::new(propensity * tau).unwrap()) as i32;
+                }
+            }
+
+            // At most one critical reaction fires, chosen in proportion to its propensity
+            let mut fired_critical = None;
+
+            if tau_critical <= tau_prime {
+                let threshold = rng.gen::<f64>() * critical_propensity;
+                let mut cumulative = 0.0;
+
+                for (reaction, &propensity) in self.propensities.iter().enumerate() {
+                    if self.critical[reaction] {
+                        cumulative += propensity;
+                        fired_critical = Some(reaction);
+
+                        if threshold < cumulative {
+                            break;
+                        }
+                    }
+                }
+
+                if let Some(reaction) = fired_critical {
+                    self.firings[reaction] = 1;
+                }
+            }
+
+            self.new_quantities.clear();
+            self.new_quantities.extend(system.quantities.iter().map(|&quantity| quantity as i64));
+
+            for (reaction, &count) in self.firings.iter().enumerate() {
+                for &(species, change) in &model.changes[reaction] {
+                    self.new_quantities[species] += change as i64 * count as i64;
+                }
+            }
+
+            // A leap that drives a species negative is rejected and retried with half the step
+            if self.new_quantities.iter().any(|&quantity| quantity < 0) {
+                tau_prime /= 2.0;
+                continue;
+            }
+
+            for (quantity, &new_quantity) in system.quantities.iter_mut().zip(&self.new_quantities) {
+                *quantity = new_quantity as i32;
+            }
+
+            self.min_delay = Some(tau);
+            self.reaction_with_min_delay = fired_critical;
+            self.leaped = true;
+            return;
+        }
+    }
+}
+
+impl Visitor for TauLeapingVisitor {
+    fn min_delay(&self) -> Option<f64> {
+        self.min_delay
+    }
+
+    fn reaction_with_min_delay(&self) -> Option<usize> {
+        self.reaction_with_min_delay
+    }
+
+    fn visit_system(&mut self, rng: &mut ChaCha12Rng, system: &mut ChemicalSystem) {
+        self.min_delay = None;
+        self.reaction_with_min_delay = None;
+        self.leaped = false;
+
+        if self.ssa_steps_remaining > 0 {
+            self.exact_step(rng, system);
+            return;
+        }
+
+        self.leap(rng, system);
+    }
+
+    fn visit_reactions(&mut self, rng: &mut ChaCha12Rng, system: &ChemicalSystem, reaction: usize) {
+        self.exact.visit_reactions(rng, system, reaction);
+    }
+
+    fn limit_delay(&mut self, max_delay: f64) {
+        self.max_delay = max_delay;
+    }
+
+    fn fired_reactions(&self, record: &mut dyn FnMut(usize, u32)) {
+        if self.leaped {
+            for (reaction, &count) in self.firings.iter().enumerate() {
+                if count > 0 {
+                    record(reaction, count as u32);
+                }
+            }
+        } else {
+            self.exact.fired_reactions(record);
+        }
+    }
+
+    fn save_state(&self, state: &mut StateWriter) {
+        state.write_str("tau-leaping");
+        state.write_usize(self.ssa_steps_remaining);
+    }
+
+    fn restore_state(&mut self, state: &mut StateReader, _system: &ChemicalSystem) -> Result<(), CheckpointError> {
+        state.expect_tag("tau-leaping")?;
+
+        self.min_delay = None;
+        self.reaction_with_min_delay = None;
+        self.leaped = false;
+        self.ssa_steps_remaining = state.read_usize()?;
+
+        Ok(())
+    }
+}
@@ -1,35 +1,56 @@
-use std::sync::{Arc, Mutex};
-use crate::reaction::Reaction;
+use std::sync::Arc;
+use crate::checkpoint::{CheckpointError, StateReader, StateWriter};
+use crate::model::Model;
+use crate::priority_queue::IndexedPriorityQueue;
 use crate::system::ChemicalSystem;
-use crate::species::Species;
-use rand::{SeedableRng};
-use rand::prelude::StdRng;
+use rand::Rng;
+use rand_chacha::ChaCha12Rng;
+use rand_distr::Exp;
 
 
 pub trait Visitor {
     fn min_delay(&self) -> Option<f64>;
-    fn reaction_with_min_delay(&self) -> Option<Arc<Mutex<Reaction>>>;
-    fn visit_system(&mut self, rng: &mut StdRng, system: &ChemicalSystem/*monitor: &mut dyn Monitor*/);
-    fn visit_reactions(&mut self, reaction: &Arc<Mutex<Reaction>>);
-    fn visit_reactants(&mut self, reactants: &Arc<Mutex<Species>>) -> Result<(), &'static str>;
-    fn visit_products(&mut self, products: &Arc<Mutex<Species>>);
+    /// Index in the system's `Model` of the reaction fired by the last visit.
+    fn reaction_with_min_delay(&self) -> Option<usize>;
+    fn visit_system(&mut self, rng: &mut ChaCha12Rng, system: &mut ChemicalSystem/*monitor: &mut dyn Monitor*/);
+    fn visit_reactions(&mut self, rng: &mut ChaCha12Rng, system: &ChemicalSystem, reaction: usize);
+
+    /// Tells the visitor that steps longer than `max_delay` will not be used. Exact methods
+    /// ignore it, as their overshooting event is undone, but a leap can be shortened instead.
+    fn limit_delay(&mut self, _max_delay: f64) {}
+
+    /// Forgets anything kept from earlier visits, after the system was changed from outside or
+    /// the last visit was undone.
+    fn reset(&mut self) {}
+
+    /// Calls `record` with every reaction fired by the last visit and how many times it fired.
+    fn fired_reactions(&self, record: &mut dyn FnMut(usize, u32)) {
+        if let (Some(_), Some(reaction)) = (self.min_delay(), self.reaction_with_min_delay()) {
+            record(reaction, 1);
+        }
+    }
+
+    /// Writes what the visitor keeps from one visit to the next into a checkpoint.
+    fn save_state(&self, _state: &mut StateWriter) {}
+
+    /// Takes up the state written by `save_state`, to continue a run of `system`.
+    fn restore_state(&mut self, _state: &mut StateReader, _system: &ChemicalSystem) -> Result<(), CheckpointError> {
+        Ok(())
+    }
 }
 
+/// First reaction method: samples a delay for every reaction and fires the earliest one.
 #[derive(Clone)]
 pub struct SystemVisitor {
     min_delay: Option<f64>,
-    reaction_with_min_delay: Option<Arc<Mutex<Reaction>>>,
-    rng: StdRng
+    reaction_with_min_delay: Option<usize>
 }
 
 impl SystemVisitor {
     pub(crate) fn new() -> Self {
-        let seed = [0; 32];
-        let rng = StdRng::from_seed(seed);
         SystemVisitor {
             min_delay: None,
-            reaction_with_min_delay: None,
-            rng
+            reaction_with_min_delay: None
         }
     }
 }
@@ -39,73 +60,282 @@ impl Visitor for SystemVisitor {
         self.min_delay
     }
 
-    fn reaction_with_min_delay(&self) -> Option<Arc<Mutex<Reaction>>> {
-        // Arc::clone is used
-        self.reaction_with_min_delay.clone()
+    fn reaction_with_min_delay(&self) -> Option<usize> {
+        self.reaction_with_min_delay
     }
 
-    fn visit_system(&mut self, rng: &mut StdRng, system: &ChemicalSystem) {
+    fn visit_system(&mut self, rng: &mut ChaCha12Rng, system: &mut ChemicalSystem) {
         self.min_delay = None;
-        //self.reaction_with_min_delay = None;
+        self.reaction_with_min_delay = None;
 
-        let reaction_symbol_table = &system.symbol_table;
-
-        for reaction in reaction_symbol_table.symbols.values() {
-            self.visit_reactions(reaction);
+        for reaction in 0..system.model.reaction_count() {
+            self.visit_reactions(rng, system, reaction);
         }
 
-        if let Some(reaction_with_min_delay) = self.reaction_with_min_delay.take() {
-            let reaction_guard = reaction_with_min_delay.lock().unwrap();
-
-            let reactant_sufficient = reaction_guard.reactants.iter().all(|reactant| {
-                let reaction_guard = reactant.lock().unwrap();
-
-                reaction_guard.quantity >= 1
-            });
-
-            if reactant_sufficient {
-                for reactant in reaction_guard.reactants.iter() {
-                    self.visit_reactants(reactant).unwrap();
-                }
-
-                for product in reaction_guard.products.iter() {
-                    self.visit_products(product);
-                }
+        if let Some(reaction) = self.reaction_with_min_delay {
+            if system.model.can_fire(reaction, &system.quantities) {
+                system.model.fire(reaction, &mut system.quantities);
+            } else {
+                // Nothing happened, so time must not advance either
+                self.min_delay = None;
             }
         }
     }
 
-    fn visit_reactions(&mut self, reaction: &Arc<Mutex<Reaction>>) {
-        let mut reaction_guard = reaction.lock().unwrap();
-        let delay = reaction_guard.compute_delay(&mut self.rng);
+    fn visit_reactions(&mut self, rng: &mut ChaCha12Rng, system: &ChemicalSystem, reaction: usize) {
+        let propensity = system.model.propensity(reaction, &system.quantities);
+
+        // Reactions with zero propensity never fire
+        if propensity <= 0.0 {
+            return;
+        }
+
+        let delay = rng.sample(Exp::new(propensity).unwrap());
 
         match self.min_delay {
             None => {
                 self.min_delay = Some(delay);
-                self.reaction_with_min_delay = Some(Arc::clone(reaction));
+                self.reaction_with_min_delay = Some(reaction);
             }
             Some(min_delay) if delay < min_delay => {
                 self.min_delay = Some(delay);
-                self.reaction_with_min_delay = Some(Arc::clone(reaction));
+                self.reaction_with_min_delay = Some(reaction);
             }
             _ => {}
         }
     }
+}
+
+/// Gillespie's direct method: one exponential draw for the time to the next event
+/// and one categorical draw, weighted by propensity, for which reaction fires.
+#[derive(Clone)]
+pub struct DirectMethodVisitor {
+    min_delay: Option<f64>,
+    reaction_with_min_delay: Option<usize>,
+    propensities: Vec<f64>,
+    total_propensity: f64
+}
+
+impl DirectMethodVisitor {
+    pub(crate) fn new() -> Self {
+        DirectMethodVisitor {
+            min_delay: None,
+            reaction_with_min_delay: None,
+            propensities: Vec::new(),
+            total_propensity: 0.0
+        }
+    }
+
+    fn select_reaction(&self, rng: &mut ChaCha12Rng) -> Option<usize> {
+        let threshold = rng.gen::<f64>() * self.total_propensity;
+        let mut cumulative = 0.0;
+
+        for (reaction, propensity) in self.propensities.iter().enumerate() {
+            cumulative += propensity;
+
+            if threshold < cumulative {
+                return Some(reaction);
+            }
+        }
+
+        // Floating point rounding can leave the threshold just above the final sum
+        self.propensities.iter().rposition(|&propensity| propensity > 0.0)
+    }
+}
+
+impl Visitor for DirectMethodVisitor {
+    fn min_delay(&self) -> Option<f64> {
+        self.min_delay
+    }
+
+    fn reaction_with_min_delay(&self) -> Option<usize> {
+        self.reaction_with_min_delay
+    }
+
+    fn visit_system(&mut self, rng: &mut ChaCha12Rng, system: &mut ChemicalSystem) {
+        self.min_delay = None;
+        self.reaction_with_min_delay = None;
+        self.propensities.clear();
+        self.total_propensity = 0.0;
+
+        for reaction in 0..system.model.reaction_count() {
+            self.visit_reactions(rng, system, reaction);
+        }
+
+        // A total propensity of zero leaves no next event to draw
+        if self.total_propensity <= 0.0 {
+            return;
+        }
+
+        let exp = Exp::new(self.total_propensity).unwrap();
+        let delay = rng.sample(exp);
+
+        if let Some(selected_reaction) = self.select_reaction(rng) {
+            system.model.fire(selected_reaction, &mut system.quantities);
+
+            self.min_delay = Some(delay);
+            self.reaction_with_min_delay = Some(selected_reaction);
+        }
+    }
+
+    fn visit_reactions(&mut self, _rng: &mut ChaCha12Rng, system: &ChemicalSystem, reaction: usize) {
+        let propensity = system.model.propensity(reaction, &system.quantities);
+
+        self.total_propensity += propensity;
+        self.propensities.push(propensity);
+    }
+}
+
+/// Gibson and Bruck's next reaction method. Putative firing times are kept in an indexed
+/// priority queue, and after each firing only the reactions that depend on it are updated.
+///
+/// The visitor keeps state between steps, which `Simulation::new` resets at the start of
+/// every run.
+#[derive(Clone)]
+pub struct NextReactionVisitor {
+    min_delay: Option<f64>,
+    reaction_with_min_delay: Option<usize>,
+    model: Option<Arc<Model>>,
+    propensities: Vec<f64>,
+    firing_times: Vec<f64>,
+    queue: IndexedPriorityQueue,
+    time: f64
+}
+
+impl NextReactionVisitor {
+    pub(crate) fn new() -> Self {
+        NextReactionVisitor {
+            min_delay: None,
+            reaction_with_min_delay: None,
+            model: None,
+            propensities: Vec::new(),
+            firing_times: Vec::new(),
+            queue: IndexedPriorityQueue::default(),
+            time: 0.0
+        }
+    }
+
+    fn initialize(&mut self, rng: &mut ChaCha12Rng, system: &ChemicalSystem) {
+        self.propensities.clear();
+        self.firing_times.clear();
+        self.time = 0.0;
+
+        for reaction in 0..system.model.reaction_count() {
+            self.visit_reactions(rng, system, reaction);
+        }
+
+        self.model = Some(Arc::clone(&system.model));
+        self.queue = IndexedPriorityQueue::new(self.firing_times.clone());
+    }
 
-    fn visit_reactants(&mut self, reactants: &Arc<Mutex<Species>>) -> Result<(), &'static str> {
-        let mut reactants_guard = reactants.lock().unwrap();
+    fn is_initialized_for(&self, system: &ChemicalSystem) -> bool {
+        self.model.as_ref().is_some_and(|model| Arc::ptr_eq(model, &system.model))
+    }
 
-        if reactants_guard.quantity >= 1 {
-            reactants_guard.quantity -= 1;
-            Ok(())
+    fn sample_firing_time(&self, rng: &mut ChaCha12Rng, propensity: f64) -> f64 {
+        if propensity > 0.0 {
+            self.time + rng.sample(Exp::new(propensity).unwrap())
         } else {
-            Err("")
+            f64::INFINITY
         }
     }
+}
 
-    fn visit_products(&mut self, products: &Arc<Mutex<Species>>) {
-        let mut products_guard = products.lock().unwrap();
+impl Visitor for NextReactionVisitor {
+    fn min_delay(&self) -> Option<f64> {
+        self.min_delay
+    }
 
-        products_guard.quantity += 1;
+    fn reaction_with_min_delay(&self) -> Option<usize> {
+        self.reaction_with_min_delay
     }
-}
\ No newline at end of file
+
+    fn visit_system(&mut self, rng: &mut ChaCha12Rng, system: &mut ChemicalSystem) {
+        self.min_delay = None;
+        self.reaction_with_min_delay = None;
+
+        if !self.is_initialized_for(system) {
+            self.initialize(rng, system);
+        }
+
+        let (fired, firing_time) = match self.queue.peek() {
+            Some((fired, firing_time)) if firing_time.is_finite() => (fired, firing_time),
+            // Every propensity is zero, the system has reached an absorbing state
+            _ => return
+        };
+
+        system.model.fire(fired, &mut system.quantities);
+
+        self.min_delay = Some(firing_time - self.time);
+        self.reaction_with_min_delay = Some(fired);
+        self.time = firing_time;
+
+        for &dependent in system.model.dependents(fired) {
+            let old_propensity = self.propensities[dependent];
+            let new_propensity = system.model.propensity(dependent, &system.quantities);
+
+            let firing_time = if dependent != fired && old_propensity > 0.0 && new_propensity > 0.0 {
+                // Rescale the remaining waiting time instead of drawing a new one
+                self.time + (old_propensity / new_propensity) * (self.queue.key(dependent) - self.time)
+            } else {
+                self.sample_firing_time(rng, new_propensity)
+            };
+
+            self.propensities[dependent] = new_propensity;
+            self.firing_times[dependent] = firing_time;
+            self.queue.update(dependent, firing_time);
+        }
+    }
+
+    fn visit_reactions(&mut self, rng: &mut ChaCha12Rng, system: &ChemicalSystem, reaction: usize) {
+        let propensity = system.model.propensity(reaction, &system.quantities);
+        let firing_time = self.sample_firing_time(rng, propensity);
+
+        self.propensities.push(propensity);
+        self.firing_times.push(firing_time);
+    }
+
+    fn reset(&mut self) {
+        // Every firing time is drawn again on the next visit
+        self.model = None;
+    }
+
+    fn save_state(&self, state: &mut StateWriter) {
+        state.write_str("next reaction");
+        state.write_bool(self.model.is_some());
+
+        if self.model.is_some() {
+            state.write_f64(self.time);
+            state.write_f64s(&self.propensities);
+            state.write_f64s(&self.firing_times);
+        }
+    }
+
+    fn restore_state(&mut self, state: &mut StateReader, system: &ChemicalSystem) -> Result<(), CheckpointError> {
+        state.expect_tag("next reaction")?;
+
+        self.min_delay = None;
+        self.reaction_with_min_delay = None;
+
+        if !state.read_bool()? {
+            self.model = None;
+            return Ok(());
+        }
+
+        let time = state.read_f64()?;
+        let propensities = state.read_f64s()?;
+        let firing_times = state.read_f64s()?;
+
+        if propensities.len() != system.model.reaction_count() || firing_times.len() != propensities.len() {
+            return Err(CheckpointError::Corrupt("firing times do not match the reactions".to_string()));
+        }
+
+        self.time = time;
+        self.propensities = propensities;
+        self.queue = IndexedPriorityQueue::new(firing_times.clone());
+        self.firing_times = firing_times;
+        self.model = Some(Arc::clone(&system.model));
+
+        Ok(())
+    }
+}
@@ -0,0 +1,237 @@
+use std::fs;
+use std::path::{Path, PathBuf};
+use rand::SeedableRng;
+use rand_chacha::ChaCha12Rng;
+use stochastic_simulation::checkpoint::{Checkpoint, CheckpointError, CheckpointOptions};
+use stochastic_simulation::monitor::{DefaultMonitor, SnapshotData};
+use stochastic_simulation::reaction::{Reaction, SpeciesRole};
+use stochastic_simulation::simulation::Simulation;
+use stochastic_simulation::species::species_builder;
+use stochastic_simulation::stopping::StopConditions;
+use stochastic_simulation::system::{Algorithm, ChemicalSystem};
+
+const ALGORITHMS: [Algorithm; 4] = [Algorithm::FirstReaction, Algorithm::Direct, Algorithm::NextReaction, Algorithm::TauLeaping];
+const SPECIES: &[(&str, SpeciesRole)] = &[("A", SpeciesRole::Reactant), ("C", SpeciesRole::Product)];
+
+fn network(algorithm: Algorithm) -> ChemicalSystem {
+    let a = species_builder("A", 300);
+    let b = species_builder("B", 200);
+    let c = species_builder("C", 0);
+
+    let mut system = ChemicalSystem::new(vec![
+        Reaction::new(vec![a.clone(), b.clone()], vec![c.clone()], 0.001),
+        Reaction::new(vec![c], vec![a.clone(), b.clone()], 0.1),
+        Reaction::new(vec![a.clone()], vec![b.clone()], 0.05),
+        Reaction::new(vec![b], vec![a], 0.04)
+    ]);
+
+    system.set_algorithm(algorithm);
+    system
+}
+
+fn checkpoint_path(name: &str) -> PathBuf {
+    std::env::temp_dir().join(format!("stochastic-simulation-{}-{}.checkpoint", std::process::id(), name))
+}
+
+fn journal_path(path: &Path) -> PathBuf {
+    PathBuf::from(format!("{}.monitor", path.display()))
+}
+
+// The checkpoint at `path` and its journal, and what it restores into a new monitor
+fn written(path: &Path) -> (Vec<u8>, Vec<u8>, DefaultMonitor) {
+    let mut monitor = DefaultMonitor::new();
+
+    Checkpoint::read(path).unwrap().restore_monitor(&mut monitor, &network(Algorithm::Direct)).unwrap();
+
+    (fs::read(path).unwrap(), fs::read(journal_path(path)).unwrap(), monitor)
+}
+
+// Bit for bit copy of what a monitor recorded
+fn history(monitor: &DefaultMonitor) -> Vec<(u64, Vec<(String, i32)>)> {
+    monitor.history.iter()
+        .map(|snapshot| {
+            let events = match &snapshot.data {
+                SnapshotData::Quantities(model, quantities) => model.species().iter().cloned().zip(quantities.iter().copied()).collect(),
+                SnapshotData::SpeciesEvents(events) => events.iter().map(|event| (event.species_name.clone(), event.new_quantity)).collect()
+            };
+
+            (snapshot.time.to_bits(), events)
+        })
+        .collect()
+}
+
+#[test]
+fn resumed_run_continues_bit_for_bit() {
+    for algorithm in ALGORITHMS {
+        let path = checkpoint_path(&format!("{:?}", algorithm));
+        let mut options = CheckpointOptions::new(&path);
+        options.every_steps = Some(30);
+
+        let conditions = StopConditions::at(10.0);
+
+        let mut uninterrupted = network(algorithm);
+        let mut uninterrupted_monitor = DefaultMonitor::new();
+        let full = uninterrupted.simulate_with_checkpoints(&conditions, &options, &mut ChaCha12Rng::seed_from_u64(7), &mut uninterrupted_monitor, SPECIES).unwrap();
+        let (last_checkpoint, journal, restored) = written(&path);
+
+        // Taking checkpoints does not change the run
+        let mut unchecked = network(algorithm);
+        let mut unchecked_monitor = DefaultMonitor::new();
+        let plain = unchecked.simulate_until(&conditions, &mut ChaCha12Rng::seed_from_u64(7), &mut unchecked_monitor, SPECIES);
+
+        assert_eq!((plain.steps, plain.time.to_bits()), (full.steps, full.time.to_bits()), "{:?}", algorithm);
+        assert_eq!(unchecked.quantities(), uninterrupted.quantities(), "{:?}", algorithm);
+        assert_eq!(history(&unchecked_monitor), history(&uninterrupted_monitor), "{:?}", algorithm);
+
+        // Killed after the third checkpoint
+        let mut killed = network(algorithm);
+        killed.simulate_with_checkpoints(&StopConditions::at(10.0).max_steps(100), &options, &mut ChaCha12Rng::seed_from_u64(7), &mut DefaultMonitor::new(), SPECIES).unwrap();
+
+        let checkpoint = Checkpoint::read(&path).unwrap();
+        assert_eq!(checkpoint.steps(), 90, "{:?}", algorithm);
+
+        let mut resumed = network(algorithm);
+        let mut resumed_monitor = DefaultMonitor::new();
+        let summary = resumed.resume(&checkpoint, &conditions, &options, &mut resumed_monitor, SPECIES).unwrap();
+
+        // The resumed run goes on with the journal of the one that was killed
+        let (resumed_checkpoint, resumed_journal, resumed_restored) = written(&path);
+
+        fs::remove_file(&path).unwrap();
+        fs::remove_file(journal_path(&path)).unwrap();
+
+        assert_eq!(summary.steps, full.steps, "{:?}", algorithm);
+        assert_eq!(summary.time.to_bits(), full.time.to_bits(), "{:?}", algorithm);
+        assert_eq!(resumed.quantities(), uninterrupted.quantities(), "{:?}", algorithm);
+        assert_eq!(history(&resumed_monitor), history(&uninterrupted_monitor), "{:?}", algorithm);
+        assert!(resumed_checkpoint == last_checkpoint && resumed_journal == journal, "{:?}", algorithm);
+        assert_eq!(history(&resumed_restored), history(&restored), "{:?}", algorithm);
+    }
+}
+
+#[test]
+fn monitor_journal_grows_by_what_was_recorded_since_the_last_checkpoint() {
+    let path = checkpoint_path("journal");
+    let mut options = CheckpointOptions::new(&path);
+    options.every_steps = Some(10);
+
+    let mut system = network(Algorithm::Direct);
+    let mut monitor = DefaultMonitor::new();
+    let summary = system.simulate_with_checkpoints(&StopConditions::at(20.0), &options, &mut ChaCha12Rng::seed_from_u64(2), &mut monitor, SPECIES).unwrap();
+
+    let journal = fs::metadata(journal_path(&path)).unwrap().len();
+    let mut whole = Checkpoint::read(&path).unwrap();
+    whole.save_monitor(&monitor);
+    let whole = whole.to_bytes().len() as u64;
+    let unread = Checkpoint::from_bytes(&fs::read(&path).unwrap()).unwrap();
+
+    fs::remove_file(&path).unwrap();
+    fs::remove_file(journal_path(&path)).unwrap();
+
+    // Written whole at every checkpoint, the history would take up the square of its length
+    assert!(summary.steps > 500);
+    assert!(journal < 2 * whole, "journal of {} bytes for {} bytes of history", journal, whole);
+    // Read without its journal, the checkpoint cannot restore the monitor
+    assert!(matches!(unread.restore_monitor(&mut DefaultMonitor::new(), &system), Err(CheckpointError::MissingJournal)));
+}
+
+#[test]
+fn stepped_run_continues_the_same_from_a_checkpoint() {
+    for algorithm in ALGORITHMS {
+        let mut system = network(algorithm);
+        let mut visitor = algorithm.visitor();
+        let mut rng = ChaCha12Rng::seed_from_u64(3);
+        let mut simulation = Simulation::new(&mut system, visitor.as_mut(), &mut rng);
+
+        for _ in 0..30 {
+            simulation.step();
+        }
+
+        let checkpoint = simulation.checkpoint();
+        let continued: Vec<_> = simulation.take(200).collect();
+
+        let mut system = network(algorithm);
+        let mut visitor = algorithm.visitor();
+        let mut rng = ChaCha12Rng::seed_from_u64(0);
+        let resumed: Vec<_> = Simulation::resume(&mut system, visitor.as_mut(), &mut rng, &checkpoint).unwrap().take(200).collect();
+
+        assert_eq!(resumed, continued, "{:?}", algorithm);
+    }
+}
+
+#[test]
+fn bytes_round_trip() {
+    let mut system = network(Algorithm::NextReaction);
+    let mut visitor = Algorithm::NextReaction.visitor();
+    let mut rng = ChaCha12Rng::seed_from_u64(5);
+    let mut monitor = DefaultMonitor::new();
+
+    let mut simulation = Simulation::new(&mut system, visitor.as_mut(), &mut rng);
+    simulation.step_until(1.0);
+
+    let mut checkpoint = simulation.checkpoint();
+    checkpoint.save_monitor(&monitor);
+
+    // The generator is stored where the run left it
+    assert_eq!(checkpoint.rng(), rng);
+
+    let bytes = checkpoint.to_bytes();
+    let read = Checkpoint::from_bytes(&bytes).unwrap();
+
+    assert_eq!(read.to_bytes(), bytes);
+    assert_eq!(read.algorithm(), Algorithm::NextReaction);
+    assert_eq!(read.time().to_bits(), checkpoint.time().to_bits());
+    assert_eq!(read.steps(), checkpoint.steps());
+    assert_eq!(read.quantities(), checkpoint.quantities());
+    assert_eq!(read.rng(), checkpoint.rng());
+
+    read.restore_monitor(&mut monitor, &network(Algorithm::NextReaction)).unwrap();
+}
+
+#[test]
+fn truncated_and_corrupt_input_is_rejected() {
+    let mut system = network(Algorithm::NextReaction);
+    let mut visitor = Algorithm::NextReaction.visitor();
+    let mut rng = ChaCha12Rng::seed_from_u64(5);
+
+    let mut simulation = Simulation::new(&mut system, visitor.as_mut(), &mut rng);
+    simulation.step_until(1.0);
+
+    let bytes = simulation.checkpoint().to_bytes();
+
+    for length in 0..bytes.len() {
+        assert!(Checkpoint::from_bytes(&bytes[..length]).is_err(), "accepted {} of {} bytes", length, bytes.len());
+    }
+
+    let mut extended = bytes.clone();
+    extended.push(0);
+    assert!(matches!(Checkpoint::from_bytes(&extended), Err(CheckpointError::Corrupt(_))));
+
+    let mut magic = bytes.clone();
+    magic[0] ^= 0xff;
+    assert!(matches!(Checkpoint::from_bytes(&magic), Err(CheckpointError::NotACheckpoint)));
+
+    // The version follows the eight byte magic, and the algorithm follows the version
+    let mut version = bytes.clone();
+    version[8] = 99;
+    assert!(matches!(Checkpoint::from_bytes(&version), Err(CheckpointError::UnsupportedVersion(99))));
+
+    let mut algorithm = bytes.clone();
+    algorithm[12] = 99;
+    assert!(matches!(Checkpoint::from_bytes(&algorithm), Err(CheckpointError::Corrupt(_))));
+
+    // A huge length must not be trusted
+    let mut length = bytes.clone();
+    length[16..24].copy_from_slice(&u64::MAX.to_le_bytes());
+    assert!(Checkpoint::from_bytes(&length).is_err());
+
+    let checkpoint = Checkpoint::from_bytes(&bytes).unwrap();
+    let other = ChemicalSystem::new(vec![Reaction::new(vec![species_builder("A", 1)], vec![species_builder("B", 0)], 1.0)]);
+    let mut other_system = other.clone();
+    let mut other_visitor = Algorithm::NextReaction.visitor();
+    assert!(matches!(Simulation::resume(&mut other_system, other_visitor.as_mut(), &mut rng, &checkpoint), Err(CheckpointError::WrongModel)));
+
+    let mut direct_system = network(Algorithm::Direct);
+    let mut direct_visitor = Algorithm::Direct.visitor();
+    assert!(matches!(Simulation::resume(&mut direct_system, direct_visitor.as_mut(), &mut rng, &checkpoint), Err(CheckpointError::Corrupt(_))));
+}
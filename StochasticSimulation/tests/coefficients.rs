@@ -0,0 +1,71 @@
+use rand::SeedableRng;
+use rand_chacha::ChaCha12Rng;
+use stochastic_simulation::monitor::DefaultMonitor;
+use stochastic_simulation::reaction::Reaction;
+use stochastic_simulation::species::species_builder;
+use stochastic_simulation::stopping::StopReason;
+use stochastic_simulation::system::{Algorithm, ChemicalSystem};
+
+#[test]
+fn repeated_species_and_coefficients_are_the_same_reaction() {
+    let a = species_builder("A", 10);
+    let a2 = species_builder("A2", 0);
+
+    let listed = Reaction::new(vec![a.clone(), a.clone()], vec![a2.clone()], 1.0);
+    let counted = Reaction::with_coefficients(vec![(a.clone(), 2)], vec![(a2.clone(), 1)], 1.0);
+
+    for reaction in [&listed, &counted] {
+        let reaction = reaction.lock().unwrap();
+        let reactants = reaction.reactant_coefficients();
+
+        assert_eq!(reactants.len(), 1);
+        assert_eq!(reactants[0].1, 2);
+    }
+
+    let listed = ChemicalSystem::new(vec![listed]);
+    let counted = ChemicalSystem::new(vec![counted]);
+
+    assert_eq!(listed.model().formula(0), "2A -> A2");
+    assert_eq!(counted.model().formula(0), "2A -> A2");
+    assert_eq!(listed.model().propensity(0, listed.quantities()), counted.model().propensity(0, counted.quantities()));
+}
+
+#[test]
+fn propensities_count_combinations_of_molecules() {
+    let a = species_builder("A", 10);
+    let b = species_builder("B", 4);
+    let system = ChemicalSystem::new(vec![
+        Reaction::with_coefficients(vec![(a.clone(), 2)], vec![], 0.5),
+        Reaction::with_coefficients(vec![(a.clone(), 3), (b.clone(), 1)], vec![], 2.0),
+        Reaction::with_coefficients(vec![(b.clone(), 5)], vec![], 1.0)
+    ]);
+    let model = system.model();
+
+    // C(10, 2) = 45, not 10 * 10
+    assert_eq!(model.propensity(0, system.quantities()), 0.5 * 45.0);
+    // C(10, 3) * C(4, 1) = 120 * 4
+    assert_eq!(model.propensity(1, system.quantities()), 2.0 * 480.0);
+    // Five B out of four cannot react
+    assert_eq!(model.propensity(2, system.quantities()), 0.0);
+    // Rate equations use the large population limit A^2 / 2!
+    assert_eq!(model.rate(0, &[10.0, 4.0]), 0.5 * 50.0);
+}
+
+#[test]
+fn firings_change_quantities_by_their_coefficients() {
+    let a = species_builder("A", 101);
+    let a2 = species_builder("A2", 0);
+    let system = ChemicalSystem::new(vec![Reaction::with_coefficients(vec![(a.clone(), 2)], vec![(a2.clone(), 1)], 1.0)]);
+
+    for algorithm in [Algorithm::FirstReaction, Algorithm::Direct, Algorithm::NextReaction, Algorithm::TauLeaping] {
+        let mut replicate = system.clone();
+        replicate.set_algorithm(algorithm);
+
+        let summary = replicate.simulate(f64::INFINITY, &mut ChaCha12Rng::seed_from_u64(4), &mut DefaultMonitor::new(), &[]);
+
+        // A single molecule is left over, which cannot dimerise
+        assert_eq!(summary.reason, StopReason::Absorbed, "{:?}", algorithm);
+        assert_eq!(replicate.quantity("A"), Some(1), "{:?}", algorithm);
+        assert_eq!(replicate.quantity("A2"), Some(50), "{:?}", algorithm);
+    }
+}
@@ -0,0 +1,118 @@
+#![cfg(feature = "arrow")]
+
+use std::fs::{self, File};
+use std::io::Cursor;
+use arrow_array::{Array, Float64Array, Int32Array, RecordBatch, UInt32Array};
+use arrow_ipc::reader::FileReader;
+use parquet::arrow::arrow_reader::ParquetRecordBatchReaderBuilder;
+use parquet::basic::Compression;
+use rand::SeedableRng;
+use rand_chacha::ChaCha12Rng;
+use stochastic_simulation::columnar::{ColumnarError, ColumnarFormat, ColumnarOptions, ColumnarWriter};
+use stochastic_simulation::reaction::Reaction;
+use stochastic_simulation::sampling::SamplingMonitor;
+use stochastic_simulation::species::species_builder;
+use stochastic_simulation::system::ChemicalSystem;
+
+fn species() -> Vec<String> {
+    vec!["A".to_string(), "B".to_string()]
+}
+
+fn samples(offset: i32) -> SamplingMonitor {
+    SamplingMonitor::from_samples(vec![0.0, 0.5, 1.0], species(), vec![vec![offset, 0], vec![offset - 1, 1], vec![offset - 2, 2]])
+}
+
+// The rows of the batches as (time, replicate, quantities)
+fn rows(batches: &[RecordBatch]) -> Vec<(f64, u32, Vec<i32>)> {
+    let mut rows = Vec::new();
+
+    for batch in batches {
+        let times = batch.column(0).as_any().downcast_ref::<Float64Array>().unwrap();
+        let replicates = batch.column(1).as_any().downcast_ref::<UInt32Array>().unwrap();
+        let quantities: Vec<&Int32Array> = batch.columns()[2..].iter()
+            .map(|column| column.as_any().downcast_ref::<Int32Array>().unwrap())
+            .collect();
+
+        for row in 0..batch.num_rows() {
+            rows.push((times.value(row), replicates.value(row), quantities.iter().map(|column| column.value(row)).collect()));
+        }
+    }
+
+    rows
+}
+
+fn expected_rows() -> Vec<(f64, u32, Vec<i32>)> {
+    [(0, samples(10)), (1, samples(20))].iter()
+        .flat_map(|(replicate, monitor)| {
+            monitor.times().iter().zip(monitor.samples()).map(|(&time, row)| (time, *replicate, row.clone())).collect::<Vec<_>>()
+        })
+        .collect()
+}
+
+fn write_samples<W: std::io::Write + Send>(writer: &mut ColumnarWriter<W>) {
+    writer.write_samples(0, &samples(10)).unwrap();
+    writer.write_samples(1, &samples(20)).unwrap();
+}
+
+#[test]
+fn parquet_files_round_trip_in_compressed_row_groups() {
+    let path = std::env::temp_dir().join(format!("stochastic_simulation_{}_samples.parquet", std::process::id()));
+    let options = ColumnarOptions { row_group_size: 4, ..ColumnarOptions::default() };
+    let mut writer = ColumnarWriter::create(&path, &species(), &options).unwrap();
+
+    write_samples(&mut writer);
+    writer.finish().unwrap();
+
+    let builder = ParquetRecordBatchReaderBuilder::try_new(File::open(&path).unwrap()).unwrap();
+    let metadata = builder.metadata().clone();
+    let batches: Vec<RecordBatch> = builder.build().unwrap().collect::<Result<_, _>>().unwrap();
+
+    fs::remove_file(&path).unwrap();
+
+    assert_eq!(options.compression, Compression::SNAPPY);
+    assert_eq!(metadata.num_row_groups(), 2);
+    assert!(metadata.row_groups().iter().flat_map(|group| group.columns()).all(|column| column.compression() == Compression::SNAPPY));
+    assert_eq!(batches[0].schema().fields().iter().map(|field| field.name().as_str()).collect::<Vec<_>>(), ["time", "replicate", "A", "B"]);
+    assert_eq!(rows(&batches), expected_rows());
+}
+
+#[test]
+fn arrow_ipc_files_round_trip() {
+    let options = ColumnarOptions { format: ColumnarFormat::ArrowIpc, row_group_size: 4, ..ColumnarOptions::default() };
+    let mut writer = ColumnarWriter::new(Vec::new(), &species(), &options).unwrap();
+
+    write_samples(&mut writer);
+
+    let reader = FileReader::try_new(Cursor::new(writer.finish().unwrap()), None).unwrap();
+    let batches: Vec<RecordBatch> = reader.collect::<Result<_, _>>().unwrap();
+
+    assert_eq!(batches.len(), 2);
+    assert_eq!(rows(&batches), expected_rows());
+}
+
+#[test]
+fn recorded_states_fill_the_species_columns() {
+    let a = species_builder("A", 30);
+    let b = species_builder("B", 0);
+    let c = species_builder("C", 0);
+    let mut system = ChemicalSystem::new(vec![Reaction::new(vec![a.clone()], vec![b.clone()], 1.0), Reaction::new(vec![b], vec![c], 1.0)]);
+    let options = ColumnarOptions { format: ColumnarFormat::ArrowIpc, ..ColumnarOptions::default() };
+    let mut writer = ColumnarWriter::new(Vec::new(), &["C".to_string(), "A".to_string()], &options).unwrap();
+
+    writer.set_replicate(7);
+    system.simulate(1.0, &mut ChaCha12Rng::seed_from_u64(3), &mut writer, &[]);
+
+    let reader = FileReader::try_new(Cursor::new(writer.finish().unwrap()), None).unwrap();
+    let rows = rows(&reader.collect::<Result<Vec<_>, _>>().unwrap());
+    let (_, replicate, last) = rows.last().unwrap();
+
+    assert!(rows.len() > 10);
+    assert_eq!(*replicate, 7);
+    assert_eq!(*last, [system.quantity("C").unwrap(), system.quantity("A").unwrap()]);
+
+    // A column for a species the system does not have is kept as an error for `finish`
+    let mut missing = ColumnarWriter::new(Vec::new(), &["D".to_string()], &options).unwrap();
+
+    system.simulate(2.0, &mut ChaCha12Rng::seed_from_u64(3), &mut missing, &[]);
+    assert!(matches!(missing.finish(), Err(ColumnarError::MissingSpecies(species)) if species == "D"));
+}
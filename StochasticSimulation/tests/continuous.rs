@@ -0,0 +1,93 @@
+use rand::SeedableRng;
+use rand_chacha::ChaCha12Rng;
+use stochastic_simulation::langevin::LangevinOptions;
+use stochastic_simulation::monitor::{DefaultMonitor, SnapshotData};
+use stochastic_simulation::ode::{OdeError, OdeMethod, OdeOptions};
+use stochastic_simulation::reaction::{Reaction, SpeciesRole};
+use stochastic_simulation::species::species_builder;
+use stochastic_simulation::system::ChemicalSystem;
+
+fn decay() -> ChemicalSystem {
+    let a = species_builder("A", 1000);
+    let b = species_builder("B", 0);
+
+    ChemicalSystem::new(vec![Reaction::new(vec![a], vec![b], 1.0)])
+}
+
+#[test]
+fn solve_ode_leaves_the_system_as_it_was() {
+    let system = decay();
+    let mut monitor = DefaultMonitor::new();
+
+    let trajectory = system.solve_ode(1.0, &OdeOptions::default(), &mut monitor, &[("A", SpeciesRole::Reactant)]).unwrap();
+
+    assert_eq!(system.quantity("A"), Some(1000));
+    assert_eq!(system.quantity("B"), Some(0));
+
+    // The trajectory is exact, the recorded states are rounded
+    let a = trajectory.species.iter().position(|species| species == "A").unwrap();
+    let last = trajectory.amounts.last().unwrap()[a];
+
+    assert!((last - 1000.0 * (-1.0f64).exp()).abs() < 1e-3, "A(1) = {}", last);
+    assert!(matches!(&monitor.history.last().unwrap().data,
+                     SnapshotData::SpeciesEvents(events) if events[0].new_quantity == last.round() as i32));
+}
+
+#[test]
+fn both_ode_methods_follow_the_exact_solution() {
+    // A <-> B with rates 1 and 3 relaxes to A = 750 as 750 + 250 e^(-4t)
+    let a = species_builder("A", 1000);
+    let b = species_builder("B", 0);
+    let system = ChemicalSystem::new(vec![Reaction::new(vec![a.clone()], vec![b.clone()], 1.0), Reaction::new(vec![b], vec![a], 3.0)]);
+
+    for method in [OdeMethod::DormandPrince, OdeMethod::Rosenbrock] {
+        let options = OdeOptions { method, ..OdeOptions::default() };
+        let trajectory = system.solve_ode(2.0, &options, &mut DefaultMonitor::new(), &[]).unwrap();
+
+        assert_eq!(*trajectory.times.last().unwrap(), 2.0);
+
+        for (time, amounts) in trajectory.times.iter().zip(&trajectory.amounts) {
+            let exact = 750.0 + 250.0 * (-4.0 * time).exp();
+
+            assert!((amounts[0] - exact).abs() < 1e-2, "{:?} gives A({}) = {} instead of {}", method, time, amounts[0], exact);
+        }
+    }
+}
+
+#[test]
+fn solutions_that_blow_up_end_with_an_error() {
+    // A' = A^2 / 2 goes to infinity at t = 2 / A(0)
+    let a = species_builder("A", 1000);
+    let system = ChemicalSystem::new(vec![Reaction::with_coefficients(vec![(a.clone(), 2)], vec![(a, 3)], 1.0)]);
+
+    for method in [OdeMethod::DormandPrince, OdeMethod::Rosenbrock] {
+        let options = OdeOptions { method, ..OdeOptions::default() };
+
+        match system.solve_ode(1.0, &options, &mut DefaultMonitor::new(), &[]) {
+            Err(OdeError::StepTooSmall { time, .. }) => assert!(time <= 0.002 + 1e-9, "{:?} stopped at {}", method, time),
+            Ok(trajectory) => panic!("{:?} reached {:?}", method, trajectory.times.last())
+        }
+
+        let options = OdeOptions { method, min_step: 1e-6, ..OdeOptions::default() };
+
+        assert!(matches!(system.solve_ode(1.0, &options, &mut DefaultMonitor::new(), &[]),
+                         Err(OdeError::StepTooSmall { step, .. }) if step < 1e-6), "{:?}", method);
+    }
+}
+
+#[test]
+fn solve_cle_leaves_the_system_as_it_was() {
+    let system = decay();
+    let mut monitor = DefaultMonitor::new();
+
+    let trajectory = system.solve_cle(1.0, &LangevinOptions::default(), &mut ChaCha12Rng::seed_from_u64(1), &mut monitor, &[("A", SpeciesRole::Reactant)]);
+
+    assert_eq!(system.quantity("A"), Some(1000));
+    assert_eq!(system.quantity("B"), Some(0));
+
+    let a = trajectory.species.iter().position(|species| species == "A").unwrap();
+    let last = trajectory.amounts.last().unwrap()[a];
+
+    assert!(matches!(&monitor.history.last().unwrap().data,
+                     SnapshotData::SpeciesEvents(events) if events[0].new_quantity == last.round() as i32));
+}
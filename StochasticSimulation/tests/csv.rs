@@ -0,0 +1,90 @@
+use rand::SeedableRng;
+use rand_chacha::ChaCha12Rng;
+use stochastic_simulation::csv::{parse_csv, CsvError, CsvLayout, CsvWriter};
+use stochastic_simulation::reaction::{Reaction, SpeciesRole};
+use stochastic_simulation::sampling::SamplingMonitor;
+use stochastic_simulation::species::species_builder;
+use stochastic_simulation::system::ChemicalSystem;
+
+// Species names that have to be quoted, one of them over two lines
+const NAMES: [&str; 3] = ["A", "complex \"AB\", bound", "two\nlines"];
+
+fn samples(offset: i32) -> SamplingMonitor {
+    SamplingMonitor::from_samples(vec![0.0, 0.5, 1.25],
+                                  NAMES.iter().map(|name| name.to_string()).collect(),
+                                  vec![vec![offset, 1, 2], vec![offset + 3, 4, 5], vec![offset + 6, 7, 8]])
+}
+
+fn write(layout: CsvLayout, replicates: &[SamplingMonitor]) -> String {
+    let mut writer = CsvWriter::new(Vec::new(), layout);
+
+    for (replicate, monitor) in replicates.iter().enumerate() {
+        writer.write_samples(replicate, monitor).unwrap();
+    }
+
+    String::from_utf8(writer.finish().unwrap()).unwrap()
+}
+
+#[test]
+fn samples_round_trip_in_both_layouts() {
+    let replicates = [samples(0), samples(100)];
+
+    for layout in [CsvLayout::Wide, CsvLayout::Long] {
+        let text = write(layout, &replicates);
+        let trajectories = parse_csv(&text).unwrap();
+
+        assert_eq!(trajectories.len(), 2, "{:?}", layout);
+
+        for (index, (trajectory, monitor)) in trajectories.iter().zip(&replicates).enumerate() {
+            assert_eq!(trajectory.replicate, index);
+            assert_eq!(trajectory.species, NAMES);
+            assert_eq!(trajectory.times, monitor.times());
+            assert_eq!(trajectory.quantities, monitor.samples(), "{:?}", layout);
+        }
+    }
+}
+
+#[test]
+fn simulated_states_round_trip() {
+    let a = species_builder("A", 50);
+    let b = species_builder("B", 0);
+    let mut system = ChemicalSystem::new(vec![Reaction::new(vec![a], vec![b], 1.0)]);
+    let mut writer = CsvWriter::new(Vec::new(), CsvLayout::Long);
+
+    writer.set_replicate(3);
+    system.simulate(1.0, &mut ChaCha12Rng::seed_from_u64(2), &mut writer, &[("B", SpeciesRole::Product)]);
+
+    let trajectories = parse_csv(&String::from_utf8(writer.finish().unwrap()).unwrap()).unwrap();
+    let trajectory = &trajectories[0];
+
+    assert_eq!(trajectories.len(), 1);
+    assert_eq!((trajectory.replicate, trajectory.species.as_slice()), (3, ["B".to_string()].as_slice()));
+    assert!(trajectory.times.len() > 10);
+    assert_eq!(trajectory.series("B").unwrap().last(), system.quantity("B").as_ref());
+}
+
+#[test]
+fn spacing_line_endings_and_missing_values_are_tolerated() {
+    let text = "time , replicate, A ,B\r\n\r\n0,0,1,2\r\n0.5, 0 ,,3\n\n1,0,4,\n";
+    let trajectory = &parse_csv(text).unwrap()[0];
+
+    assert_eq!(trajectory.species, ["A", "B"]);
+    assert_eq!(trajectory.times, [0.0, 0.5, 1.0]);
+    // Empty fields keep the previous quantity
+    assert_eq!(trajectory.quantities, [[1, 2], [1, 3], [4, 3]]);
+
+    let long = parse_csv("time,replicate,species,count\n0,1,A,5\n0,1,B,6\n1,1,B,7\n").unwrap();
+
+    assert_eq!(long[0].quantities, [[5, 6], [5, 7]]);
+}
+
+#[test]
+fn malformed_files_are_reported_at_their_line() {
+    assert!(matches!(parse_csv("\n\n"), Err(CsvError::MissingHeader)));
+    assert!(matches!(parse_csv("replicate,time,A\n"), Err(CsvError::InvalidHeader(header)) if header == "replicate,time,A"));
+    // The quoted name takes up lines 1 and 2
+    assert!(matches!(parse_csv("time,replicate,\"A\nB\"\n0,0\n"), Err(CsvError::WrongFieldCount { line: 3, expected: 3, found: 2 })));
+    assert!(matches!(parse_csv("time,replicate,A\n0,0,1\n1,0,\"1,5\"\n"), Err(CsvError::InvalidNumber { line: 3, value }) if value == "1,5"));
+    assert!(matches!(parse_csv("time,replicate,species,count\n0,0,A,1\n1,0,B,1\n"),
+                     Err(CsvError::MissingValue { replicate: 0, species }) if species == "B"));
+}
@@ -0,0 +1,82 @@
+use stochastic_simulation::ensemble::{Ensemble, EnsembleOptions};
+use stochastic_simulation::monitor::DefaultMonitor;
+use stochastic_simulation::reaction::Reaction;
+use stochastic_simulation::species::species_builder;
+use stochastic_simulation::system::ChemicalSystem;
+
+fn isomerisation() -> ChemicalSystem {
+    let a = species_builder("A", 100);
+    let b = species_builder("B", 0);
+
+    ChemicalSystem::new(vec![Reaction::new(vec![a.clone()], vec![b.clone()], 1.0), Reaction::new(vec![b], vec![a], 1.0)])
+}
+
+// Index, steps taken and final quantity of A of every replicate
+fn outcomes(options: EnsembleOptions) -> Vec<(usize, u64, i32)> {
+    Ensemble::new(isomerisation(), options).unwrap()
+        .run(2.0, |_| DefaultMonitor::new(), &[])
+        .into_iter()
+        .map(|replicate| (replicate.index, replicate.summary.steps, replicate.system.quantity("A").unwrap()))
+        .collect()
+}
+
+#[test]
+fn master_seed_reproduces_every_replicate() {
+    let options = EnsembleOptions { replicates: 16, seed: 42, threads: None };
+    let first = outcomes(options.clone());
+
+    assert_eq!(outcomes(options.clone()), first);
+    assert_ne!(outcomes(EnsembleOptions { seed: 43, ..options }), first);
+    assert_eq!(first.iter().map(|(index, _, _)| *index).collect::<Vec<_>>(), (0..16).collect::<Vec<_>>());
+}
+
+#[test]
+fn replicates_have_streams_of_their_own() {
+    let ensemble = Ensemble::new(isomerisation(), EnsembleOptions { replicates: 16, seed: 1, threads: None }).unwrap();
+    let mut seeds = ensemble.seeds();
+
+    seeds.sort();
+    seeds.dedup();
+    assert_eq!(seeds.len(), 16);
+
+    let mut steps: Vec<u64> = outcomes(EnsembleOptions { replicates: 16, seed: 1, threads: None }).iter().map(|(_, steps, _)| *steps).collect();
+
+    steps.sort();
+    steps.dedup();
+    assert!(steps.len() > 8, "replicates took {:?} steps", steps);
+}
+
+#[test]
+fn results_do_not_depend_on_the_thread_count() {
+    let options = EnsembleOptions { replicates: 24, seed: 7, threads: Some(1) };
+    let single = outcomes(options.clone());
+
+    assert_eq!(outcomes(EnsembleOptions { threads: Some(4), ..options.clone() }), single);
+    assert_eq!(outcomes(EnsembleOptions { threads: None, ..options }), single);
+}
+
+#[test]
+fn more_replicates_keep_the_earlier_ones() {
+    let few = outcomes(EnsembleOptions { replicates: 5, seed: 3, threads: None });
+    let many = outcomes(EnsembleOptions { replicates: 12, seed: 3, threads: None });
+
+    assert_eq!(many[..5], few[..]);
+}
+
+#[test]
+fn batches_come_back_in_replicate_order() {
+    let ensemble = Ensemble::new(isomerisation(), EnsembleOptions { replicates: 10, seed: 9, threads: Some(3) }).unwrap();
+    let mut batched = Vec::new();
+
+    ensemble.run_batched(4, |index, mut system, mut rng| {
+        system.simulate(2.0, &mut rng, &mut DefaultMonitor::new(), &[]);
+        (index, system.quantity("A").unwrap())
+    }, |result| batched.push(result));
+
+    let all = ensemble.run_with(|index, mut system, mut rng| {
+        system.simulate(2.0, &mut rng, &mut DefaultMonitor::new(), &[]);
+        (index, system.quantity("A").unwrap())
+    });
+
+    assert_eq!(batched, all);
+}
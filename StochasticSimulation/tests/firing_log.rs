@@ -0,0 +1,112 @@
+use rand::SeedableRng;
+use rand_chacha::ChaCha12Rng;
+use stochastic_simulation::firing_log::{Firing, FiringLog, FiringLogOptions};
+use stochastic_simulation::monitor::DefaultMonitor;
+use stochastic_simulation::reaction::{Reaction, SpeciesRole};
+use stochastic_simulation::simulation::Simulation;
+use stochastic_simulation::species::species_builder;
+use stochastic_simulation::system::{Algorithm, ChemicalSystem};
+
+// A -> B -> C, starting from `initial` A
+fn chain(initial: i32) -> ChemicalSystem {
+    let a = species_builder("A", initial);
+    let b = species_builder("B", 0);
+    let c = species_builder("C", 0);
+
+    ChemicalSystem::new(vec![Reaction::new(vec![a], vec![b.clone()], 1.0), Reaction::new(vec![b], vec![c], 0.5)])
+}
+
+#[test]
+fn every_event_is_logged_in_order() {
+    let mut logged = chain(200);
+    let mut log = FiringLog::new(FiringLogOptions::default());
+
+    logged.simulate(2.0, &mut ChaCha12Rng::seed_from_u64(5), &mut log, &[]);
+
+    // The same run stepped by hand
+    let mut stepped = chain(200);
+    let mut visitor = Algorithm::Direct.visitor();
+    let mut rng = ChaCha12Rng::seed_from_u64(5);
+    let expected: Vec<Firing> = Simulation::new(&mut stepped, visitor.as_mut(), &mut rng)
+        .take_while(|event| event.time <= 2.0)
+        .map(|event| Firing { time: event.time, reaction: event.reaction.unwrap(), count: 1 })
+        .collect();
+
+    assert!(expected.len() > 100);
+    assert_eq!(log.firings(), expected);
+    assert_eq!(log.total(), expected.len() as u64);
+}
+
+#[test]
+fn counts_match_the_change_in_quantities() {
+    for algorithm in [Algorithm::Direct, Algorithm::NextReaction, Algorithm::TauLeaping] {
+        // Enough molecules for leaps of many firings
+        let mut system = chain(20000);
+        let mut monitors = (DefaultMonitor::new(), FiringLog::new(FiringLogOptions::default()));
+
+        system.set_algorithm(algorithm);
+        system.simulate(3.0, &mut ChaCha12Rng::seed_from_u64(1), &mut monitors, &[("C", SpeciesRole::Product)]);
+
+        let (states, log) = monitors;
+
+        // Every A that is gone went through the first reaction, and every C through the second
+        assert_eq!(log.counts(), [20000 - system.quantity("A").unwrap() as u64, system.quantity("C").unwrap() as u64], "{:?}", algorithm);
+        assert_eq!(log.firings().iter().map(|firing| firing.count as u64).sum::<u64>(), log.total());
+        assert!(!states.history.is_empty());
+
+        if algorithm == Algorithm::TauLeaping {
+            assert!(log.firings().iter().any(|firing| firing.count > 1), "no leap fired a reaction more than once");
+        }
+    }
+}
+
+#[test]
+fn reactions_are_ranked_and_described() {
+    let mut system = chain(200);
+    let mut log = FiringLog::new(FiringLogOptions { keep_firings: false, window: None });
+
+    assert!(log.formula(0).is_none());
+    system.simulate(1.0, &mut ChaCha12Rng::seed_from_u64(2), &mut log, &[]);
+
+    let ranked = log.ranked();
+
+    assert!(log.firings().is_empty());
+    assert!(log.fluxes().is_none());
+    assert_eq!(ranked.iter().map(|&(reaction, _)| reaction).collect::<Vec<_>>(), [0, 1]);
+    assert!(ranked[0].1 > ranked[1].1);
+    assert_eq!(log.formula(1), Some("B -> C"));
+    assert_eq!(log.reaction_id(1), Some(system.model().reaction_id(1)));
+
+    log.clear();
+
+    assert_eq!(log.total(), 0);
+    assert!(log.ranked().is_empty() && log.formula(0).is_none());
+}
+
+#[test]
+fn fluxes_count_firings_per_window() {
+    let mut system = chain(200);
+    let mut log = FiringLog::new(FiringLogOptions { keep_firings: true, window: Some(0.25) });
+
+    system.simulate(2.0, &mut ChaCha12Rng::seed_from_u64(3), &mut log, &[]);
+
+    let fluxes = log.fluxes().unwrap();
+
+    assert_eq!(fluxes.counts.len(), 8);
+
+    for (window, counts) in fluxes.counts.iter().enumerate() {
+        let start = fluxes.window_start(window);
+
+        for (reaction, &count) in counts.iter().enumerate() {
+            let fired = log.firings().iter()
+                .filter(|firing| firing.reaction == reaction && firing.time >= start && firing.time < start + 0.25)
+                .count();
+
+            assert_eq!(count, fired as u64);
+            assert_eq!(fluxes.rates()[window][reaction], count as f64 * 4.0);
+        }
+    }
+
+    // A decays, so the first reaction slows down from window to window on the whole
+    assert!(fluxes.counts[0][0] > fluxes.counts[7][0]);
+}
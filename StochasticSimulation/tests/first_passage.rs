@@ -0,0 +1,123 @@
+use stochastic_simulation::ensemble::{Ensemble, EnsembleOptions};
+use stochastic_simulation::first_passage::{FirstPassageTimes, Passage, Threshold};
+use stochastic_simulation::reaction::Reaction;
+use stochastic_simulation::species::species_builder;
+use stochastic_simulation::system::ChemicalSystem;
+
+// A -> 0 at rate one per molecule
+fn decay(initial: i32, seed: u64) -> Ensemble {
+    let a = species_builder("A", initial);
+    let system = ChemicalSystem::new(vec![Reaction::new(vec![a], vec![], 1.0)]);
+
+    Ensemble::new(system, EnsembleOptions { replicates: 2000, seed, threads: None }).unwrap()
+}
+
+fn passages(passages: &[(f64, bool)]) -> FirstPassageTimes {
+    FirstPassageTimes {
+        species: "A".to_string(),
+        threshold: Threshold::AtMost(0),
+        end_time: 10.0,
+        passages: passages.iter().enumerate()
+            .map(|(replicate, &(time, reached))| Passage { replicate, time, reached })
+            .collect()
+    }
+}
+
+fn assert_close(actual: f64, expected: f64) {
+    assert!((actual - expected).abs() < 1e-12, "{} instead of {}", actual, expected);
+}
+
+#[test]
+fn extinction_times_have_the_exact_moments() {
+    // The sum of waiting times at rates 3, 2 and 1
+    let extinction = FirstPassageTimes::extinction(&decay(3, 1), "A", f64::INFINITY).unwrap();
+    let summary = extinction.summary(0.99);
+
+    assert_eq!((summary.replicates, summary.reached, summary.censored), (2000, 2000, 0));
+    assert_eq!(summary.fraction_reached, 1.0);
+
+    let (low, high) = summary.mean_interval.unwrap();
+    let (variance_low, variance_high) = summary.variance_interval.unwrap();
+
+    assert!(low < 11.0 / 6.0 && 11.0 / 6.0 < high, "mean {:?} not around 11/6", summary.mean);
+    assert!(variance_low < 49.0 / 36.0 && 49.0 / 36.0 < variance_high, "variance {:?} not around 49/36", summary.variance);
+    assert!(extinction.times().windows(2).all(|pair| pair[0] <= pair[1]));
+    assert_eq!(extinction.passages.iter().map(|passage| passage.replicate).collect::<Vec<_>>(), (0..2000).collect::<Vec<_>>());
+}
+
+#[test]
+fn replicates_that_do_not_reach_the_threshold_are_censored_at_the_end_time() {
+    // Extinction of a single molecule is exponential, so it happens by t = 1 with probability 1 - 1/e
+    let extinction = FirstPassageTimes::extinction(&decay(1, 2), "A", 1.0).unwrap();
+    let summary = extinction.summary(0.99);
+    let expected = 1.0 - (-1.0f64).exp();
+
+    assert!(summary.censored > 0);
+    assert_eq!(summary.reached + summary.censored, 2000);
+    assert!(extinction.passages.iter().all(|passage| passage.reached == (passage.time < 1.0)));
+    assert!(extinction.passages.iter().filter(|passage| !passage.reached).all(|passage| passage.time == 1.0));
+    assert!(summary.fraction_interval.0 < expected && expected < summary.fraction_interval.1);
+
+    // Without censoring before the end, the estimate is the empirical CDF
+    for time in [0.25, 0.5, 0.75] {
+        let empirical = extinction.times().iter().filter(|&&passage| passage <= time).count() as f64 / 2000.0;
+
+        assert_close(extinction.cdf_at(time), empirical);
+        assert!((empirical - (1.0 - (-time).exp())).abs() < 0.04);
+    }
+
+    assert_close(extinction.cdf_at(2.0), summary.fraction_reached);
+}
+
+#[test]
+fn censoring_before_the_end_is_taken_into_account() {
+    // Censored at t = 2, before the third passage; at t = 3 the one censored there is still at risk
+    let times = passages(&[(1.0, true), (2.0, false), (3.0, true), (3.0, false), (4.0, true)]);
+    let cdf = times.cdf();
+
+    assert_eq!(cdf.iter().map(|&(time, _)| time).collect::<Vec<_>>(), [1.0, 3.0, 4.0]);
+    assert_close(cdf[0].1, 0.2);
+    assert_close(cdf[1].1, 1.0 - 0.8 * 2.0 / 3.0);
+    assert_close(cdf[2].1, 1.0);
+    assert_eq!(times.cdf_at(0.5), 0.0);
+    assert_close(times.cdf_at(3.5), cdf[1].1);
+    assert_eq!((times.reached(), times.censored()), (3, 2));
+}
+
+#[test]
+fn summaries_of_known_passages() {
+    let summary = passages(&[(4.0, true), (1.0, true), (3.0, true), (2.0, true), (9.0, false)]).summary(0.95);
+    let half_width = 1.959964 * (5.0f64 / 3.0 / 4.0).sqrt();
+
+    assert_eq!((summary.mean, summary.variance), (Some(2.5), Some(5.0 / 3.0)));
+    assert!((summary.mean_interval.unwrap().0 - (2.5 - half_width)).abs() < 1e-5);
+    assert!((summary.mean_interval.unwrap().1 - (2.5 + half_width)).abs() < 1e-5);
+    // Wilson interval of 4 out of 5
+    assert!((summary.fraction_interval.0 - 0.3755).abs() < 1e-3 && (summary.fraction_interval.1 - 0.9638).abs() < 1e-3);
+
+    let single = passages(&[(1.0, true), (5.0, false)]).summary(0.95);
+
+    assert_eq!((single.mean, single.variance, single.mean_interval), (Some(1.0), None, None));
+    assert_eq!(passages(&[(5.0, false)]).summary(0.95).mean, None);
+}
+
+#[test]
+fn thresholds_from_above_and_below() {
+    let a = species_builder("A", 0);
+    let production = ChemicalSystem::new(vec![Reaction::new(vec![], vec![a], 10.0)]);
+    let ensemble = Ensemble::new(production, EnsembleOptions { replicates: 1000, seed: 3, threads: None }).unwrap();
+
+    // Five arrivals at rate ten take half a unit of time on average
+    let passages = FirstPassageTimes::simulate(&ensemble, "A", Threshold::AtLeast(5), f64::INFINITY).unwrap();
+    let (low, high) = passages.summary(0.99).mean_interval.unwrap();
+
+    assert!(low < 0.5 && 0.5 < high);
+    assert_eq!(FirstPassageTimes::simulate(&ensemble, "A", Threshold::AtLeast(5), f64::INFINITY).unwrap().passages, passages.passages);
+    assert!(FirstPassageTimes::simulate(&ensemble, "B", Threshold::AtLeast(5), 1.0).is_none());
+
+    // A threshold met at the start is reached at time zero
+    let started = FirstPassageTimes::simulate(&ensemble, "A", Threshold::AtMost(0), 1.0).unwrap();
+
+    assert!(started.passages.iter().all(|passage| passage.reached && passage.time == 0.0));
+    assert!(Threshold::AtMost(3).is_reached(3) && !Threshold::AtLeast(4).is_reached(3));
+}
@@ -0,0 +1,143 @@
+use rand::SeedableRng;
+use rand_chacha::ChaCha12Rng;
+use stochastic_simulation::checkpoint::CheckpointError;
+use stochastic_simulation::ensemble::EnsembleOptions;
+use stochastic_simulation::fork::{Fork, ForkError};
+use stochastic_simulation::monitor::DefaultMonitor;
+use stochastic_simulation::reaction::{Reaction, SpeciesRole};
+use stochastic_simulation::simulation::Simulation;
+use stochastic_simulation::species::species_builder;
+use stochastic_simulation::system::{Algorithm, ChemicalSystem};
+
+// A -> 0 at rate one per molecule, with the next reaction method so that firing times are
+// scheduled ahead at the fork
+fn decay() -> ChemicalSystem {
+    let a = species_builder("A", 100);
+    let mut system = ChemicalSystem::new(vec![Reaction::new(vec![a], vec![], 1.0)]);
+
+    system.set_algorithm(Algorithm::NextReaction);
+    system
+}
+
+fn options(replicates: usize, seed: u64) -> EnsembleOptions {
+    EnsembleOptions { replicates, seed, threads: None }
+}
+
+// Forks a run of `decay` after 20 events
+fn fork(options: EnsembleOptions) -> Fork {
+    let mut system = decay();
+    let mut visitor = Algorithm::NextReaction.visitor();
+    let mut rng = ChaCha12Rng::seed_from_u64(1);
+    let mut simulation = Simulation::new(&mut system, visitor.as_mut(), &mut rng);
+
+    for _ in 0..20 {
+        simulation.step();
+    }
+
+    Fork::new(&simulation, options).unwrap()
+}
+
+// Time of the first event of every branch
+fn first_events(fork: &Fork) -> Vec<f64> {
+    fork.run_with(|_, simulation| simulation.step().unwrap().time())
+}
+
+#[test]
+fn branches_start_from_the_forked_state() {
+    let mut system = decay();
+    let mut visitor = Algorithm::NextReaction.visitor();
+    let mut rng = ChaCha12Rng::seed_from_u64(1);
+    let mut simulation = Simulation::new(&mut system, visitor.as_mut(), &mut rng);
+
+    simulation.step_until(0.3);
+
+    let time = simulation.time();
+    let quantities = simulation.system().quantities().to_vec();
+    let fork = Fork::new(&simulation, options(8, 3)).unwrap();
+
+    assert_eq!((fork.time(), fork.system().quantities()), (time, quantities.as_slice()));
+    assert_eq!(fork.options().replicates, 8);
+
+    let starts = fork.run_with(|index, simulation| (index, simulation.time(), simulation.steps(), simulation.system().quantities().to_vec()));
+
+    assert_eq!(starts, (0..8).map(|index| (index, time, simulation.steps(), quantities.clone())).collect::<Vec<_>>());
+
+    // The forked run is left as it was
+    assert_eq!((simulation.time(), simulation.system().quantities()), (time, quantities.as_slice()));
+    assert!(simulation.step().is_some());
+
+    // Monitors get the fork state first, and branches go on from it
+    for replicate in fork.run(1.0, |_| DefaultMonitor::new(), &[("A", SpeciesRole::Reactant)]) {
+        let first = &replicate.monitor.history[0];
+
+        assert_eq!(first.time, time);
+        assert!(replicate.summary.time == 1.0 && replicate.summary.steps > 0);
+        assert!(replicate.system.quantity("A").unwrap() < quantities[0]);
+    }
+}
+
+#[test]
+fn branches_are_reproducible_from_the_master_seed() {
+    let seeds = fork(options(6, 11)).seeds();
+
+    assert_eq!(fork(options(6, 11)).seeds(), seeds);
+    assert_ne!(fork(options(6, 12)).seeds(), seeds);
+    // More branches keep the seeds of the first ones
+    assert_eq!(fork(options(9, 11)).seeds()[..6], seeds);
+
+    let run = |fork: Fork| {
+        fork.run(2.0, |_| DefaultMonitor::new(), &[])
+            .into_iter()
+            .map(|replicate| (replicate.summary.steps, replicate.system.quantities().to_vec()))
+            .collect::<Vec<_>>()
+    };
+    let single_thread = EnsembleOptions { threads: Some(1), ..options(6, 11) };
+
+    assert_eq!(run(fork(options(6, 11))), run(fork(single_thread)));
+}
+
+#[test]
+fn branches_are_independent() {
+    let fork = fork(options(1000, 5));
+    let seeds = fork.seeds();
+    let times = first_events(&fork);
+    let waits: Vec<f64> = times.iter().map(|time| time - fork.time()).collect();
+
+    assert!(seeds.iter().enumerate().all(|(index, seed)| !seeds[..index].contains(seed)));
+
+    // Scheduled firing times are drawn again, so the first waiting times are exponential at
+    // the total rate of the 80 molecules left
+    let count = waits.len() as f64;
+    let mean = waits.iter().sum::<f64>() / count;
+
+    assert_eq!(fork.system().quantity("A"), Some(80));
+    assert!(waits.iter().all(|&wait| wait > 0.0));
+    assert!((mean - 1.0 / 80.0).abs() < 4.0 * (1.0 / 80.0) / count.sqrt(), "mean waiting time {}", mean);
+
+    // Neighbouring branches are not correlated
+    let variance = waits.iter().map(|wait| (wait - mean).powi(2)).sum::<f64>() / count;
+    let covariance = waits.windows(2).map(|pair| (pair[0] - mean) * (pair[1] - mean)).sum::<f64>() / (count - 1.0);
+
+    assert!((covariance / variance).abs() < 0.1, "correlation {}", covariance / variance);
+}
+
+#[test]
+fn checkpoints_are_forked_only_for_their_network() {
+    let mut system = decay();
+    let mut visitor = Algorithm::NextReaction.visitor();
+    let mut rng = ChaCha12Rng::seed_from_u64(1);
+    let mut simulation = Simulation::new(&mut system, visitor.as_mut(), &mut rng);
+
+    simulation.step_until(0.2);
+
+    let checkpoint = simulation.checkpoint();
+    let forked = Fork::from_checkpoint(&decay(), &checkpoint, options(4, 7)).unwrap();
+    let direct = Fork::new(&simulation, options(4, 7)).unwrap();
+
+    assert_eq!((forked.time(), forked.system().quantities()), (direct.time(), direct.system().quantities()));
+    assert_eq!(first_events(&forked), first_events(&direct));
+
+    let other = ChemicalSystem::new(vec![Reaction::new(vec![species_builder("A", 100)], vec![], 2.0)]);
+
+    assert!(matches!(Fork::from_checkpoint(&other, &checkpoint, options(4, 7)), Err(ForkError::State(CheckpointError::WrongModel))));
+}
@@ -0,0 +1,84 @@
+use stochastic_simulation::parser::{parse_system, ParseError, ParseErrorKind};
+
+fn error(source: &str) -> ParseError {
+    match parse_system(source) {
+        Ok(_) => panic!("{:?} parsed", source),
+        Err(error) => error
+    }
+}
+
+fn assert_error(source: &str, line: usize, column: usize, kind: ParseErrorKind) {
+    assert_eq!(error(source), ParseError { line, column, kind }, "{:?}", source);
+}
+
+#[test]
+fn parses_declarations_comments_and_coefficients() {
+    let system = parse_system("\
+# Dimerisation with a catalyst
+species A = 100, E = 5
+species A2 = 0
+param k = 1e-3
+
+2A + E ->[k] A2 + E   # catalysed
+A2 ->[2.5E-1] 2A
+2E ->[0.5] 0
+").unwrap();
+
+    let model = system.model();
+
+    assert_eq!(model.species(), ["A", "E", "A2"]);
+    assert_eq!(system.quantities(), [100, 5, 0]);
+    assert_eq!(model.reaction_count(), 3);
+    assert_eq!(model.formula(0), "2A + E -> A2 + E");
+    assert_eq!(model.formula(1), "A2 -> 2A");
+    assert_eq!(model.formula(2), "2E -> ");
+    // k * C(100, 2) * 5
+    assert!((model.propensity(0, system.quantities()) - 1e-3 * 4950.0 * 5.0).abs() < 1e-9);
+    assert_eq!(model.propensity(1, system.quantities()), 0.0);
+}
+
+#[test]
+fn unknown_names_are_reported_where_they_are_used() {
+    assert_error("species A = 1\nA + B ->[1] 0", 2, 5, ParseErrorKind::UnknownSpecies("B".to_string()));
+    assert_error("species A = 1\n\nA ->[rate] 0", 3, 6, ParseErrorKind::UnknownParameter("rate".to_string()));
+}
+
+#[test]
+fn duplicate_names_are_reported_at_the_second_declaration() {
+    assert_error("species A = 1, B = 2\nspecies B = 3", 2, 9, ParseErrorKind::DuplicateName("B".to_string()));
+    assert_error("species A = 1\nparam A = 0.5", 2, 7, ParseErrorKind::DuplicateName("A".to_string()));
+}
+
+#[test]
+fn malformed_rates_are_reported_at_the_rate() {
+    for (rate, found) in [("1e", "1e"), ("1e-", "1e-"), ("2.5E+", "2.5E+"), ("1.2.3", "1.2.3"), ("-1", "-1"), ("1e999", "1e999")] {
+        let source = format!("species A = 1\nA ->[{}] 0", rate);
+
+        assert_error(&source, 2, 6, ParseErrorKind::MalformedRate(found.to_string()));
+    }
+
+    assert_error("param k = 1e", 1, 11, ParseErrorKind::MalformedRate("1e".to_string()));
+}
+
+#[test]
+fn malformed_quantities_and_coefficients_are_reported() {
+    assert_error("species A = -3", 1, 13, ParseErrorKind::MalformedQuantity("-3".to_string()));
+    assert_error("species A = 1.5", 1, 13, ParseErrorKind::MalformedQuantity("1.5".to_string()));
+    assert_error("species A = 1\n0A ->[1] 0", 2, 1, ParseErrorKind::MalformedCoefficient("0".to_string()));
+    assert_error("species A = 1\nA ->[1] 1.5A", 2, 9, ParseErrorKind::MalformedCoefficient("1.5".to_string()));
+}
+
+#[test]
+fn unexpected_tokens_are_reported() {
+    assert_error("species A = 1\nA -> 0", 2, 6,
+                 ParseErrorKind::Unexpected { expected: "`[` and a rate after `->`", found: "`0`".to_string() });
+    assert_error("species A = 1\nA ->[1 0", 2, 8, ParseErrorKind::Unexpected { expected: "`]`", found: "`0`".to_string() });
+    assert_error("species A = 1\nA ->[1] 0 + A", 2, 11, ParseErrorKind::Unexpected { expected: "end of line", found: "`+`".to_string() });
+    assert_error("species A = 1\nA ->[1] 0;", 2, 10, ParseErrorKind::Unexpected { expected: "a token", found: "`;`".to_string() });
+    assert_error("species A =", 1, 12, ParseErrorKind::Unexpected { expected: "an initial quantity", found: "end of line".to_string() });
+}
+
+#[test]
+fn unused_species_are_rejected_at_their_declaration() {
+    assert_error("species A = 1, Idle = 5\nA ->[1] 0", 1, 16, ParseErrorKind::UnusedSpecies("Idle".to_string()));
+}
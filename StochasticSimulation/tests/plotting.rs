@@ -0,0 +1,180 @@
+use std::fs;
+use std::path::PathBuf;
+use plotters::style::RGBColor;
+use rand::SeedableRng;
+use rand_chacha::ChaCha12Rng;
+use stochastic_simulation::monitor::DefaultMonitor;
+use stochastic_simulation::ode::OdeOptions;
+use stochastic_simulation::plotter::{plot, plot_ensemble_to_svg, plot_heatmap_to_svg, plot_histograms_to_svg, plot_to_svg, AxisRange, EnsemblePlot, PlotError, PlotOptions};
+use stochastic_simulation::reaction::{Reaction, SpeciesRole};
+use stochastic_simulation::sampling::SamplingMonitor;
+use stochastic_simulation::species::species_builder;
+use stochastic_simulation::statistics::{DistributionHeatmap, EnsembleStatistics, Histogram};
+use stochastic_simulation::system::ChemicalSystem;
+
+const SPECIES: [(&str, SpeciesRole); 2] = [("A", SpeciesRole::Reactant), ("B", SpeciesRole::Product)];
+
+fn isomerisation() -> ChemicalSystem {
+    let a = species_builder("A", 20);
+    let b = species_builder("B", 0);
+
+    ChemicalSystem::new(vec![Reaction::new(vec![a], vec![b], 1.0)])
+}
+
+// Histories of two runs of A -> B, merged like replicates of an ensemble
+fn merged_runs() -> DefaultMonitor {
+    let mut merged = DefaultMonitor::new();
+
+    for seed in 0..2 {
+        let mut monitor = DefaultMonitor::new();
+
+        isomerisation().simulate(5.0, &mut ChaCha12Rng::seed_from_u64(seed), &mut monitor, &SPECIES);
+        merged.merge(monitor, &SPECIES);
+    }
+
+    merged
+}
+
+// A file in the temporary directory that is not shared with other tests
+fn output(name: &str) -> PathBuf {
+    std::env::temp_dir().join(format!("stochastic_simulation_{}_{}", std::process::id(), name))
+}
+
+#[test]
+fn options_are_drawn_into_the_chart() {
+    let options = PlotOptions {
+        title: "Isomerisation".to_string(),
+        x_label: "Seconds".to_string(),
+        y_label: "Molecules".to_string(),
+        palette: vec![RGBColor(1, 2, 3), RGBColor(4, 5, 6)],
+        ..PlotOptions::default()
+    };
+    let svg = plot_to_svg(&merged_runs().history, &SPECIES, &options).unwrap();
+
+    assert!(svg.starts_with("<svg"));
+    assert!(svg.contains(r#"width="600" height="400""#));
+
+    for text in ["Isomerisation", "Seconds", "Molecules", "A", "B"] {
+        assert!(svg.contains(&format!(">\n{}\n</text>", text)), "no {} in the chart", text);
+    }
+
+    // Each species is drawn in its colour once for each of the two runs and once in the legend
+    for color in ["#010203", "#040506"] {
+        assert_eq!(svg.matches(&format!(r#"stroke="{}""#, color)).count(), 3);
+    }
+}
+
+#[test]
+fn monitor_history_is_written_to_the_file() {
+    let path = output("history.svg");
+    let options = PlotOptions { path: path.clone(), log_scale: true, ..PlotOptions::default() };
+
+    merged_runs().visualize_data_with_options(&SPECIES, &options).unwrap();
+
+    let written = fs::read_to_string(&path).unwrap();
+
+    fs::remove_file(&path).unwrap();
+    assert!(written.starts_with("<svg"));
+}
+
+#[test]
+fn plots_that_cannot_be_drawn_are_errors() {
+    let monitor = merged_runs();
+    let svg = |options: &PlotOptions| plot_to_svg(&monitor.history, &SPECIES, options);
+
+    assert!(matches!(monitor.visualize_data(&[("C", SpeciesRole::Product)]), Err(PlotError::NoData)));
+    assert!(matches!(DefaultMonitor::new().visualize_data(&SPECIES), Err(PlotError::NoData)));
+    assert!(matches!(svg(&PlotOptions { y_range: AxisRange::Fixed(10.0, 10.0), ..PlotOptions::default() }),
+                     Err(PlotError::InvalidRange(..))));
+    assert!(matches!(svg(&PlotOptions { y_range: AxisRange::Fixed(0.0, 10.0), log_scale: true, ..PlotOptions::default() }),
+                     Err(PlotError::InvalidRange(..))));
+    assert!(svg(&PlotOptions { y_range: AxisRange::Fixed(0.0, 10.0), ..PlotOptions::default() }).is_ok());
+}
+
+#[test]
+fn backend_is_chosen_by_the_extension() {
+    let history = merged_runs().history;
+    let plot_to = |name: &str| {
+        let path = output(name);
+        let result = plot(&history, &SPECIES, &PlotOptions { path: path.clone(), ..PlotOptions::default() });
+
+        result.map(|()| {
+            let written = fs::read(&path).unwrap();
+
+            fs::remove_file(&path).unwrap();
+            written
+        })
+    };
+
+    assert!(plot_to("chart.png").unwrap().starts_with(b"\x89PNG"));
+    assert!(plot_to("chart.PNG").unwrap().starts_with(b"\x89PNG"));
+    // Files hold the same document as the string
+    assert_eq!(plot_to("chart.svg").unwrap(), plot_to_svg(&history, &SPECIES, &PlotOptions::default()).unwrap().into_bytes());
+
+    for name in ["chart.jpg", "chart"] {
+        assert!(matches!(plot_to(name), Err(PlotError::UnsupportedFormat(path)) if path == output(name)));
+        assert!(!output(name).exists());
+    }
+}
+
+#[test]
+fn ensemble_bands_are_drawn_with_their_layers() {
+    let samples: Vec<SamplingMonitor> = (0..10)
+        .map(|seed| {
+            let mut monitor = SamplingMonitor::with_interval(0.5, 3.0);
+
+            isomerisation().simulate(3.0, &mut ChaCha12Rng::seed_from_u64(seed), &mut monitor, &SPECIES);
+            monitor
+        })
+        .collect();
+    let statistics = EnsembleStatistics::from_samples(&samples, &[0.05, 0.25, 0.75, 0.95]).unwrap();
+    let ode = isomerisation().solve_ode(3.0, &OdeOptions::default(), &mut DefaultMonitor::new(), &[]).unwrap();
+
+    let plot = EnsemblePlot { band: (0.25, 0.75), samples: samples.iter().take(3).collect(), ode: Some(&ode), ..EnsemblePlot::new(&statistics) };
+    let options = PlotOptions { palette: vec![RGBColor(1, 2, 3), RGBColor(4, 5, 6)], ..PlotOptions::default() };
+    let svg = plot_ensemble_to_svg(&plot, &SPECIES, &options).unwrap();
+
+    for text in ["A 25-75%", "A mean", "A ODE", "B 25-75%", "B mean", "B ODE"] {
+        assert!(svg.contains(&format!(">\n{}\n</text>", text)), "no {} in the legend", text);
+    }
+
+    // A band for each species, with its mean drawn thicker than the three samples
+    assert_eq!(svg.matches("<polygon").count(), 2);
+    assert_eq!(svg.matches(r##"stroke="#010203" stroke-width="2""##).count(), 2);
+    assert_eq!(svg.matches(r##"stroke="#010203" stroke-width="1""##).count(), 3);
+
+    let without_extras = plot_ensemble_to_svg(&EnsemblePlot::new(&statistics), &SPECIES, &options).unwrap();
+
+    let black_lines = |svg: &str| svg.matches(r##"<polyline fill="none" opacity="1" stroke="#000000""##).count();
+
+    assert!(!without_extras.contains("ODE"));
+    // The ODE solutions are dashed, so each of them is drawn as many short lines
+    assert!(black_lines(&svg) > black_lines(&without_extras) + 20);
+    assert!(matches!(plot_ensemble_to_svg(&EnsemblePlot { band: (0.1, 0.9), ..plot.clone() }, &SPECIES, &options),
+                     Err(PlotError::MissingQuantile(probability)) if probability == 0.1));
+    assert!(matches!(plot_ensemble_to_svg(&plot, &[("C", SpeciesRole::Product)], &options), Err(PlotError::NoData)));
+}
+
+#[test]
+fn distributions_are_drawn_as_bars_and_cells() {
+    let samples: Vec<SamplingMonitor> = [[4, 2, 0], [4, 3, 1], [4, 3, 3]].iter()
+        .map(|series| SamplingMonitor::from_samples(vec![0.0, 1.0, 2.0], vec!["A".to_string()], series.iter().map(|&a| vec![a]).collect()))
+        .collect();
+    let options = PlotOptions { palette: vec![RGBColor(1, 2, 3), RGBColor(4, 5, 6)], ..PlotOptions::default() };
+    let histograms: Vec<Histogram> = [1.0, 2.0].iter()
+        .map(|&time| Histogram::from_samples(&samples, "A", time, 1).unwrap())
+        .collect();
+
+    let bars = plot_histograms_to_svg(&histograms, true, &options).unwrap();
+
+    assert!(bars.contains(">\nA t=1\n</text>") && bars.contains(">\nA t=2\n</text>"));
+    // Only bins with replicates in them are drawn, besides a box in the legend
+    assert_eq!(bars.matches(r##"fill="#010203""##).count(), 2 + 1);
+    assert_eq!(bars.matches(r##"fill="#040506""##).count(), 3 + 1);
+
+    // A cell for every bin with replicates in it at each grid point, and a box in the legend
+    let heatmap = DistributionHeatmap::from_samples(&samples, "A", 1).unwrap();
+
+    assert_eq!(plot_heatmap_to_svg(&heatmap, &options).unwrap().matches(r##"fill="#010203""##).count(), 1 + 2 + 3 + 1);
+    assert!(matches!(plot_histograms_to_svg(&[], false, &options), Err(PlotError::NoData)));
+}
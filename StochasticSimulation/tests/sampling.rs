@@ -0,0 +1,76 @@
+use rand::SeedableRng;
+use rand_chacha::ChaCha12Rng;
+use stochastic_simulation::ensemble::{Ensemble, EnsembleOptions};
+use stochastic_simulation::monitor::{DefaultMonitor, SnapshotData};
+use stochastic_simulation::reaction::{Reaction, SpeciesRole};
+use stochastic_simulation::sampling::SamplingMonitor;
+use stochastic_simulation::species::species_builder;
+use stochastic_simulation::stopping::StopConditions;
+use stochastic_simulation::system::ChemicalSystem;
+
+fn isomerisation() -> ChemicalSystem {
+    let a = species_builder("A", 30);
+    let b = species_builder("B", 0);
+
+    ChemicalSystem::new(vec![Reaction::new(vec![a.clone()], vec![b.clone()], 1.0), Reaction::new(vec![b], vec![a], 0.5)])
+}
+
+#[test]
+fn grid_points_get_the_state_of_the_last_event_before_them() {
+    let mut system = isomerisation();
+    let mut monitors = (SamplingMonitor::with_interval(0.25, 4.0), DefaultMonitor::new());
+    let species = [("A", SpeciesRole::Reactant)];
+
+    system.simulate(4.0, &mut ChaCha12Rng::seed_from_u64(8), &mut monitors, &species);
+
+    let (sampling, events) = monitors;
+
+    // A(t) as a step function of the recorded events
+    let changes: Vec<(f64, i32)> = events.history.iter()
+        .map(|snapshot| match &snapshot.data {
+            SnapshotData::SpeciesEvents(events) => (snapshot.time, events[0].new_quantity),
+            SnapshotData::Quantities(..) => unreachable!()
+        })
+        .collect();
+    let a_at = |time: f64| changes.iter().rev().find(|(changed, _)| *changed <= time).unwrap().1;
+
+    assert!(changes.len() > 20);
+    assert!(sampling.is_complete());
+    assert_eq!(sampling.species(), ["A"]);
+
+    for (time, sample) in sampling.times().iter().zip(sampling.series("A").unwrap()) {
+        assert_eq!(sample, a_at(*time), "at {}", time);
+    }
+
+    assert_eq!(*sampling.samples().last().unwrap(), [system.quantity("A").unwrap()]);
+}
+
+#[test]
+fn ensemble_replicates_are_sampled_up_to_the_end_time() {
+    let ensemble = Ensemble::new(isomerisation(), EnsembleOptions { replicates: 8, seed: 5, threads: None }).unwrap();
+
+    // The end time is not a multiple of the interval in floating point
+    let replicates = ensemble.run(0.3, |_| SamplingMonitor::with_interval(0.1, 0.3), &[("A", SpeciesRole::Reactant), ("B", SpeciesRole::Product)]);
+
+    for replicate in replicates {
+        let monitor = &replicate.monitor;
+
+        assert_eq!(monitor.times().len(), 4);
+        assert!(monitor.is_complete(), "replicate {} has {} samples", replicate.index, monitor.samples().len());
+        assert_eq!(*monitor.samples().last().unwrap(), replicate.system.quantities());
+    }
+}
+
+#[test]
+fn finish_carries_the_state_of_a_stopped_run_forward() {
+    let mut system = isomerisation();
+    let mut monitor = SamplingMonitor::with_times(vec![0.0, 50.0, 100.0]);
+
+    system.simulate_until(&StopConditions::at(100.0).max_steps(5), &mut ChaCha12Rng::seed_from_u64(1), &mut monitor, &[("A", SpeciesRole::Reactant)]);
+
+    assert!(!monitor.is_complete());
+
+    monitor.finish();
+
+    assert_eq!(monitor.series("A").unwrap()[1..], [system.quantity("A").unwrap(); 2]);
+}
@@ -0,0 +1,234 @@
+use std::collections::HashSet;
+use stochastic_simulation::reaction::Reaction;
+use stochastic_simulation::sbml::{export_sbml, import_sbml, ImportAction, SbmlError};
+use stochastic_simulation::species::species_builder;
+use stochastic_simulation::system::ChemicalSystem;
+
+#[test]
+fn round_trip_keeps_species_named_like_exporter_ids() {
+    let k = species_builder("k", 10);
+    let default = species_builder("default", 20);
+    let r1 = species_builder("R1", 30);
+    let model = species_builder("model", 0);
+    let system = ChemicalSystem::new(vec![
+        Reaction::new(vec![k.clone(), default.clone()], vec![r1.clone()], 0.5),
+        Reaction::new(vec![r1.clone()], vec![model.clone()], 2.0)
+    ]);
+
+    let xml = export_sbml(&system);
+    let document = roxmltree::Document::parse(&xml).unwrap();
+
+    // Local parameters are scoped to their kinetic law, every other id has to be unique
+    let ids: Vec<&str> = document.descendants()
+        .filter(|node| node.tag_name().name() != "localParameter")
+        .filter_map(|node| node.attribute("id"))
+        .collect();
+    let distinct: HashSet<&str> = ids.iter().copied().collect();
+
+    assert_eq!(distinct.len(), ids.len(), "duplicate ids in {:?}", ids);
+
+    let import = import_sbml(&xml).unwrap();
+
+    assert!(import.unsupported.is_empty(), "{:?}", import.unsupported.iter().map(|construct| construct.to_string()).collect::<Vec<_>>());
+
+    let mut expected: Vec<(String, i32)> = system.model().species().iter().cloned().zip(system.quantities().iter().copied()).collect();
+    let mut imported: Vec<(String, i32)> = import.system.model().species().iter().cloned().zip(import.system.quantities().iter().copied()).collect();
+    expected.sort();
+    imported.sort();
+
+    assert_eq!(imported, expected);
+    assert_eq!(import.system.model().reaction_count(), 2);
+
+    let mut formulas: Vec<&str> = (0..2).map(|reaction| system.model().formula(reaction)).collect();
+    let mut imported_formulas: Vec<&str> = (0..2).map(|reaction| import.system.model().formula(reaction)).collect();
+    formulas.sort();
+    imported_formulas.sort();
+
+    assert_eq!(imported_formulas, formulas);
+}
+
+#[test]
+fn species_outside_reactions_are_listed_but_not_simulated() {
+    let xml = r#"<?xml version="1.0" encoding="UTF-8"?>
+<sbml xmlns="http://www.sbml.org/sbml/level3/version1/core" level="3" version="1">
+  <model id="model">
+    <listOfCompartments>
+      <compartment id="cell" spatialDimensions="3" size="1" constant="true"/>
+    </listOfCompartments>
+    <listOfSpecies>
+      <species id="A" compartment="cell" initialAmount="10" hasOnlySubstanceUnits="true" boundaryCondition="false" constant="false"/>
+      <species id="B" compartment="cell" initialAmount="0" hasOnlySubstanceUnits="true" boundaryCondition="false" constant="false"/>
+      <species id="Idle" compartment="cell" initialAmount="5" hasOnlySubstanceUnits="true" boundaryCondition="false" constant="false"/>
+    </listOfSpecies>
+    <listOfReactions>
+      <reaction id="R1" reversible="false">
+        <listOfReactants><speciesReference species="A" stoichiometry="1" constant="true"/></listOfReactants>
+        <listOfProducts><speciesReference species="B" stoichiometry="1" constant="true"/></listOfProducts>
+        <kineticLaw>
+          <math xmlns="http://www.w3.org/1998/Math/MathML"><apply><times/><ci> k </ci><ci> A </ci></apply></math>
+          <listOfLocalParameters><localParameter id="k" value="1"/></listOfLocalParameters>
+        </kineticLaw>
+      </reaction>
+    </listOfReactions>
+  </model>
+</sbml>"#;
+
+    let import = import_sbml(xml).unwrap();
+
+    assert_eq!(import.species, vec![("A".to_string(), 10), ("B".to_string(), 0), ("Idle".to_string(), 5)]);
+    assert_eq!(import.system.quantity("Idle"), None);
+    assert_eq!(import.system.quantity("A"), Some(10));
+}
+
+#[test]
+fn round_trip_keeps_names_that_are_not_identifiers() {
+    let a_p = species_builder("A-P", 40);
+    let x = species_builder("2X", 3);
+    let system = ChemicalSystem::new(vec![
+        Reaction::with_coefficients(vec![(a_p.clone(), 2)], vec![(x.clone(), 1)], 0.25),
+        Reaction::new(vec![x.clone()], vec![], 1.5)
+    ]);
+
+    let import = import_sbml(&export_sbml(&system)).unwrap();
+
+    assert!(import.unsupported.is_empty());
+    assert_eq!(import.species, vec![("A-P".to_string(), 40), ("2X".to_string(), 3)]);
+    assert_eq!(import.system.quantity("A-P"), Some(40));
+    assert_eq!(import.system.quantity("2X"), Some(3));
+
+    let model = import.system.model();
+    let formulas: Vec<&str> = (0..model.reaction_count()).map(|reaction| model.formula(reaction)).collect();
+
+    assert_eq!(formulas, vec!["2A-P -> 2X", "2X -> "]);
+
+    for (reaction, formula) in formulas.iter().enumerate() {
+        let rate = model.propensity(reaction, import.system.quantities());
+        let expected = system.model().propensity(reaction, system.quantities());
+
+        assert!((rate - expected).abs() <= 1e-12 * expected, "{} has propensity {} instead of {}", formula, rate, expected);
+    }
+}
+
+fn sbml(species: &str, reactions: &str, extra: &str) -> String {
+    format!(r#"<?xml version="1.0" encoding="UTF-8"?>
+<sbml xmlns="http://www.sbml.org/sbml/level3/version1/core" level="3" version="2">
+  <model id="model">
+    <listOfCompartments>
+      <compartment id="cell" spatialDimensions="3" size="2" constant="true"/>
+    </listOfCompartments>
+    <listOfParameters>
+      <parameter id="kf" value="0.5" constant="true"/>
+    </listOfParameters>
+    <listOfSpecies>{}</listOfSpecies>
+    <listOfReactions>{}</listOfReactions>
+    {}
+  </model>
+</sbml>"#, species, reactions, extra)
+}
+
+const SPECIES: &str = r#"
+      <species id="A" compartment="cell" initialConcentration="50" hasOnlySubstanceUnits="false" boundaryCondition="false" constant="false"/>
+      <species id="B" compartment="cell" initialAmount="7.4" hasOnlySubstanceUnits="true" boundaryCondition="false" constant="false"/>
+      <species id="S" compartment="cell" initialAmount="30" hasOnlySubstanceUnits="true" boundaryCondition="true" constant="false"/>"#;
+
+#[test]
+fn imports_mass_action_reactions_in_molecule_counts() {
+    let reactions = r#"
+      <reaction id="binding" reversible="true">
+        <listOfReactants><speciesReference species="A" stoichiometry="2" constant="true"/></listOfReactants>
+        <listOfProducts><speciesReference species="B" stoichiometry="1" constant="true"/></listOfProducts>
+        <kineticLaw>
+          <math xmlns="http://www.w3.org/1998/Math/MathML">
+            <apply><minus/>
+              <apply><times/><ci> kf </ci><ci> cell </ci><apply><power/><ci> A </ci><cn type="integer"> 2 </cn></apply></apply>
+              <apply><times/><ci> kr </ci><ci> cell </ci><ci> B </ci></apply>
+            </apply>
+          </math>
+          <listOfLocalParameters><localParameter id="kr" value="2"/></listOfLocalParameters>
+        </kineticLaw>
+      </reaction>
+      <reaction id="supply" reversible="false">
+        <listOfReactants><speciesReference species="S" stoichiometry="1" constant="true"/></listOfReactants>
+        <listOfProducts><speciesReference species="B" stoichiometry="1" constant="true"/></listOfProducts>
+        <kineticLaw>
+          <math xmlns="http://www.w3.org/1998/Math/MathML">
+            <apply><times/><cn type="e-notation"> 1 <sep/> -3 </cn><ci> S </ci></apply>
+          </math>
+        </kineticLaw>
+      </reaction>"#;
+
+    let import = import_sbml(&sbml(SPECIES, reactions, "")).unwrap();
+    let system = &import.system;
+    let model = system.model();
+
+    // A concentration of 50 in a compartment of size 2, and 7.4 rounded to 7
+    assert_eq!(import.species, vec![("A".to_string(), 100), ("B".to_string(), 7), ("S".to_string(), 30)]);
+    assert_eq!(import.unsupported.len(), 1);
+    assert_eq!(import.unsupported[0].action, ImportAction::Approximated);
+
+    // Reversible laws become a reaction in each direction
+    let formulas: Vec<&str> = (0..model.reaction_count()).map(|reaction| model.formula(reaction)).collect();
+
+    assert_eq!(formulas, ["2A -> B", "B -> 2A", "S -> B + S"]);
+
+    // kf * cell * [A]^2 with [A] = A / cell is kf / cell * 2! * C(A, 2)
+    let propensity = model.propensity(0, system.quantities());
+
+    assert!((propensity - 0.5 / 2.0 * 2.0 * 4950.0).abs() < 1e-9, "{}", propensity);
+    // B is in amounts already, so kr * cell * B is the propensity
+    assert!((model.propensity(1, system.quantities()) - 2.0 * 2.0 * 7.0).abs() < 1e-9);
+    assert!((model.propensity(2, system.quantities()) - 1e-3 * 30.0).abs() < 1e-12);
+}
+
+#[test]
+fn unsupported_constructs_are_reported() {
+    let reactions = r#"
+      <reaction id="saturating" reversible="false">
+        <listOfReactants><speciesReference species="A" stoichiometry="1" constant="true"/></listOfReactants>
+        <kineticLaw>
+          <math xmlns="http://www.w3.org/1998/Math/MathML">
+            <apply><divide/><ci> A </ci><apply><plus/><cn> 1 </cn><ci> A </ci></apply></apply>
+          </math>
+        </kineticLaw>
+      </reaction>
+      <reaction id="decay" reversible="false">
+        <listOfReactants><speciesReference species="B" stoichiometry="1" constant="true"/></listOfReactants>
+        <kineticLaw>
+          <math xmlns="http://www.w3.org/1998/Math/MathML"><apply><times/><ci> kf </ci><ci> B </ci></apply></math>
+        </kineticLaw>
+      </reaction>"#;
+    let extra = r#"
+    <listOfRules><assignmentRule variable="kf"><math xmlns="http://www.w3.org/1998/Math/MathML"><cn> 1 </cn></math></assignmentRule></listOfRules>
+    <listOfEvents><event id="pulse"/></listOfEvents>"#;
+
+    let import = import_sbml(&sbml(SPECIES, reactions, extra)).unwrap();
+    let reported: Vec<(&str, Option<&str>, ImportAction)> = import.unsupported.iter()
+        .map(|construct| (construct.construct.as_str(), construct.id.as_deref(), construct.action))
+        .collect();
+
+    assert_eq!(reported, [
+        ("species", Some("B"), ImportAction::Approximated),
+        ("assignmentRule", Some("kf"), ImportAction::Skipped),
+        ("event", Some("pulse"), ImportAction::Skipped),
+        ("reaction", Some("saturating"), ImportAction::Rejected)
+    ]);
+    assert_eq!(import.system.model().reaction_count(), 1);
+    assert_eq!(import.system.model().formula(0), "B -> ");
+}
+
+#[test]
+fn invalid_documents_are_errors() {
+    let level_2 = r#"<sbml xmlns="http://www.sbml.org/sbml/level2/version4" level="2" version="4"><model/></sbml>"#;
+
+    assert!(matches!(import_sbml(level_2), Err(SbmlError::UnsupportedLevel { .. })));
+    assert!(matches!(import_sbml("<notsbml/>"), Err(SbmlError::NotSbml)));
+    assert!(matches!(import_sbml("<sbml"), Err(SbmlError::Xml(_))));
+
+    let reactions = r#"
+      <reaction id="lost" reversible="false">
+        <listOfReactants><speciesReference species="Z" constant="true"/></listOfReactants>
+      </reaction>"#;
+
+    assert!(matches!(import_sbml(&sbml(SPECIES, reactions, "")),
+                     Err(SbmlError::UnknownSpecies { reaction, species }) if reaction == "lost" && species == "Z"));
+}
@@ -0,0 +1,154 @@
+use rand::SeedableRng;
+use rand_chacha::ChaCha12Rng;
+use stochastic_simulation::monitor::DefaultMonitor;
+use stochastic_simulation::reaction::Reaction;
+use stochastic_simulation::simulation::Simulation;
+use stochastic_simulation::species::species_builder;
+use stochastic_simulation::stopping::StopReason;
+use stochastic_simulation::system::{Algorithm, ChemicalSystem};
+
+// Quantity of `species` at `end_time` in runs with the seeds in `seeds`
+fn final_quantities(system: &ChemicalSystem, algorithm: Algorithm, species: &str, seeds: std::ops::Range<u64>, end_time: f64) -> Vec<f64> {
+    seeds.map(|seed| {
+        let mut replicate = system.clone();
+        let mut rng = ChaCha12Rng::seed_from_u64(seed);
+
+        replicate.set_algorithm(algorithm);
+        replicate.simulate(end_time, &mut rng, &mut DefaultMonitor::new(), &[]);
+        replicate.quantity(species).unwrap() as f64
+    }).collect()
+}
+
+fn mean_and_variance(samples: &[f64]) -> (f64, f64) {
+    let mean = samples.iter().sum::<f64>() / samples.len() as f64;
+    let variance = samples.iter().map(|x| (x - mean) * (x - mean)).sum::<f64>() / (samples.len() - 1) as f64;
+
+    (mean, variance)
+}
+
+#[test]
+fn reused_visitor_starts_every_run_afresh() {
+    let a = species_builder("A", 100);
+    let b = species_builder("B", 0);
+    let system = ChemicalSystem::new(vec![Reaction::new(vec![a], vec![b], 1.0)]);
+
+    for algorithm in [Algorithm::FirstReaction, Algorithm::Direct, Algorithm::NextReaction, Algorithm::TauLeaping] {
+        let mut visitor = algorithm.visitor();
+        let mut rng = ChaCha12Rng::seed_from_u64(1);
+
+        for run in 0..2 {
+            let mut replicate = system.clone();
+            let summary = replicate.simulation(f64::INFINITY, visitor.as_mut(), &mut rng, &mut DefaultMonitor::new(), &[]);
+
+            assert_eq!(summary.reason, StopReason::Absorbed, "{:?} run {}", algorithm, run);
+            assert_eq!(replicate.quantity("A"), Some(0), "{:?} run {}", algorithm, run);
+            assert!(summary.steps > 0, "{:?} run {}", algorithm, run);
+        }
+    }
+}
+
+#[test]
+fn direct_method_is_reproducible_from_the_callers_generator() {
+    let a = species_builder("A", 50);
+    let b = species_builder("B", 0);
+    let system = ChemicalSystem::new(vec![Reaction::new(vec![a.clone()], vec![b.clone()], 1.0), Reaction::new(vec![b], vec![a], 0.5)]);
+
+    let trajectory = |seed: u64| {
+        let mut replicate = system.clone();
+        let mut visitor = Algorithm::Direct.visitor();
+        let mut rng = ChaCha12Rng::seed_from_u64(seed);
+
+        Simulation::new(&mut replicate, visitor.as_mut(), &mut rng).take(100).collect::<Vec<_>>()
+    };
+
+    assert_eq!(trajectory(7), trajectory(7));
+    assert_ne!(trajectory(7), trajectory(8));
+}
+
+#[test]
+fn direct_method_matches_exponential_decay() {
+    let a = species_builder("A", 1000);
+    let system = ChemicalSystem::new(vec![Reaction::new(vec![a], vec![], 0.5)]);
+
+    // Every molecule survives to t = 1 with probability p = e^-0.5, so A(1) is binomial
+    let p = (-0.5f64).exp();
+    let (mean, variance) = mean_and_variance(&final_quantities(&system, Algorithm::Direct, "A", 0..400, 1.0));
+
+    // The standard error of the mean is about 0.8
+    assert!((mean - 1000.0 * p).abs() < 3.5, "mean {} instead of {}", mean, 1000.0 * p);
+    assert!((variance / (1000.0 * p * (1.0 - p)) - 1.0).abs() < 0.25, "variance {}", variance);
+}
+
+#[test]
+fn direct_method_chooses_reactions_in_proportion_to_their_propensity() {
+    let a = species_builder("A", 4000);
+    let b = species_builder("B", 0);
+    let c = species_builder("C", 0);
+    let mut system = ChemicalSystem::new(vec![
+        Reaction::new(vec![a.clone()], vec![b], 1.0),
+        Reaction::new(vec![a], vec![c], 3.0)
+    ]);
+
+    let summary = system.simulate(f64::INFINITY, &mut ChaCha12Rng::seed_from_u64(2), &mut DefaultMonitor::new(), &[]);
+
+    // B is binomial with n = 4000 and p = 1/4, a standard deviation of about 27
+    assert_eq!(summary.reason, StopReason::Absorbed);
+    assert_eq!(summary.steps, 4000);
+    assert!((system.quantity("B").unwrap() - 1000).abs() < 110, "B = {:?}", system.quantity("B"));
+}
+
+// Binding, unbinding and conversion of the bound complex
+fn binding() -> ChemicalSystem {
+    let a = species_builder("A", 100);
+    let b = species_builder("B", 80);
+    let c = species_builder("C", 0);
+    let d = species_builder("D", 0);
+
+    ChemicalSystem::new(vec![
+        Reaction::new(vec![a.clone(), b.clone()], vec![c.clone()], 0.01),
+        Reaction::new(vec![c.clone()], vec![a, b], 1.0),
+        Reaction::new(vec![c], vec![d], 0.1)
+    ])
+}
+
+#[test]
+fn next_reaction_method_updates_only_dependent_reactions() {
+    let system = binding();
+    let model = system.model();
+    let dependents = |reaction: usize| {
+        let mut dependents = model.dependents(reaction).to_vec();
+        dependents.sort();
+        dependents
+    };
+
+    assert_eq!(dependents(0), [0, 1, 2]);
+    assert_eq!(dependents(1), [0, 1, 2]);
+    // D is no reactant, so only the reactions consuming C are affected
+    assert_eq!(dependents(2), [1, 2]);
+
+    let a = species_builder("A", 10);
+    let b = species_builder("B", 0);
+    let c = species_builder("C", 0);
+    let chain = ChemicalSystem::new(vec![
+        Reaction::new(vec![a], vec![b.clone()], 1.0),
+        Reaction::new(vec![b], vec![c.clone()], 1.0),
+        Reaction::new(vec![c], vec![], 1.0)
+    ]);
+
+    assert_eq!(chain.model().dependents(0), [0, 1]);
+    assert_eq!(chain.model().dependents(2), [2]);
+}
+
+#[test]
+fn next_reaction_method_matches_the_direct_method() {
+    let system = binding();
+
+    for species in ["C", "D"] {
+        let (direct_mean, direct_variance) = mean_and_variance(&final_quantities(&system, Algorithm::Direct, species, 0..400, 3.0));
+        let (mean, variance) = mean_and_variance(&final_quantities(&system, Algorithm::NextReaction, species, 1000..1400, 3.0));
+        let standard_error = (2.0 * direct_variance / 400.0).sqrt();
+
+        assert!((mean - direct_mean).abs() < 4.0 * standard_error, "{} mean {} against {}", species, mean, direct_mean);
+        assert!((variance / direct_variance - 1.0).abs() < 0.3, "{} variance {} against {}", species, variance, direct_variance);
+    }
+}
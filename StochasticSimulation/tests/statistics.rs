@@ -0,0 +1,146 @@
+use rand::{Rng, SeedableRng};
+use rand_chacha::ChaCha12Rng;
+use stochastic_simulation::sampling::SamplingMonitor;
+use stochastic_simulation::statistics::{DistributionHeatmap, EnsembleStatistics, Histogram, StreamingStatistics};
+
+// A replicate sampled at t = 0 and 1, with species A and B
+fn replicate(a: [i32; 2], b: [i32; 2]) -> SamplingMonitor {
+    SamplingMonitor::from_samples(vec![0.0, 1.0], vec!["A".to_string(), "B".to_string()], vec![vec![a[0], b[0]], vec![a[1], b[1]]])
+}
+
+fn assert_close(actual: &[f64], expected: &[f64], tolerance: f64) {
+    assert_eq!(actual.len(), expected.len());
+
+    for (actual, expected) in actual.iter().zip(expected) {
+        assert!((actual - expected).abs() <= tolerance, "{:?} instead of {:?}", actual, expected);
+    }
+}
+
+#[test]
+fn exact_statistics_of_every_grid_point() {
+    let replicates = [replicate([0, 10], [5, 5]), replicate([0, 20], [5, 7]), replicate([0, 30], [5, 9]), replicate([0, 40], [5, 11])];
+    let statistics = EnsembleStatistics::from_samples(&replicates, &[0.0, 0.5, 0.9]).unwrap();
+
+    assert_eq!(statistics.replicates, 4);
+    assert_eq!(statistics.times, [0.0, 1.0]);
+    assert_close(statistics.mean_of("A").unwrap(), &[0.0, 25.0], 1e-12);
+    assert_close(statistics.mean_of("B").unwrap(), &[5.0, 8.0], 1e-12);
+    // Sample standard deviations, with n - 1
+    assert_close(statistics.std_dev_of("A").unwrap(), &[0.0, (500.0f64 / 3.0).sqrt()], 1e-12);
+    assert_close(statistics.std_dev_of("B").unwrap(), &[0.0, (20.0f64 / 3.0).sqrt()], 1e-12);
+    // Interpolated between ranks, so the median of 10, 20, 30, 40 is 25
+    assert_close(statistics.quantile_of("A", 0.0).unwrap(), &[0.0, 10.0], 1e-12);
+    assert_close(statistics.quantile_of("A", 0.5).unwrap(), &[0.0, 25.0], 1e-12);
+    assert_close(statistics.quantile_of("A", 0.9).unwrap(), &[0.0, 37.0], 1e-12);
+    assert!(statistics.quantile_of("A", 0.25).is_none());
+    assert!(statistics.mean_of("C").is_none());
+}
+
+#[test]
+fn no_replicates_have_no_statistics() {
+    assert!(EnsembleStatistics::from_samples(&[], &[0.5]).is_none());
+    assert!(StreamingStatistics::new(&[0.5]).statistics().is_none());
+}
+
+#[test]
+fn streaming_statistics_agree_with_the_exact_ones() {
+    let mut rng = ChaCha12Rng::seed_from_u64(6);
+    let replicates: Vec<SamplingMonitor> = (0..2000)
+        .map(|_| replicate([rng.gen_range(0..100), rng.gen_range(0..1000)], [rng.gen_range(50..60), rng.gen_range(0..10)]))
+        .collect();
+    let probabilities = [0.05, 0.5, 0.95];
+
+    let exact = EnsembleStatistics::from_samples(&replicates, &probabilities).unwrap();
+    let mut streaming = StreamingStatistics::new(&probabilities);
+
+    replicates.iter().for_each(|monitor| streaming.add(monitor));
+
+    let streamed = streaming.statistics().unwrap();
+
+    assert_eq!(streaming.replicates(), 2000);
+
+    for species in ["A", "B"] {
+        assert_close(streamed.mean_of(species).unwrap(), exact.mean_of(species).unwrap(), 1e-9);
+        assert_close(streamed.std_dev_of(species).unwrap(), exact.std_dev_of(species).unwrap(), 1e-9);
+    }
+
+    // P² estimates of uniformly distributed values are within a few percent of the range
+    for probability in probabilities {
+        assert_close(streamed.quantile_of("A", probability).unwrap(), exact.quantile_of("A", probability).unwrap(), 30.0);
+    }
+}
+
+#[test]
+fn streaming_quantiles_of_few_replicates_are_exact() {
+    let replicates = [replicate([1, 4], [0, 0]), replicate([3, 2], [0, 0]), replicate([2, 9], [0, 0])];
+    let mut streaming = StreamingStatistics::new(&[0.5]);
+
+    replicates.iter().for_each(|monitor| streaming.add(monitor));
+
+    assert_close(streaming.statistics().unwrap().quantile_of("A", 0.5).unwrap(), &[2.0, 4.0], 0.0);
+}
+
+#[test]
+fn histogram_bins_are_aligned_to_their_width() {
+    let replicates = [replicate([0, 7], [0, 0]), replicate([0, 12], [0, 0]), replicate([0, 13], [0, 0]), replicate([0, 24], [0, 0])];
+    let histogram = Histogram::from_samples(&replicates, "A", 1.0, 5).unwrap();
+
+    // Bins start at multiples of five, from the one holding the smallest quantity
+    assert_eq!(histogram.low, 5);
+    assert_eq!(histogram.counts, [1, 2, 0, 1]);
+    assert_eq!((histogram.bin_start(0), histogram.bin_start(3)), (5, 20));
+    assert_eq!(histogram.total(), 4);
+    assert_eq!(histogram.probabilities(), [0.25, 0.5, 0.0, 0.25]);
+
+    let single_quantities = Histogram::from_samples(&replicates, "A", 1.0, 0).unwrap();
+
+    assert_eq!((single_quantities.low, single_quantities.width, single_quantities.counts.len()), (7, 1, 18));
+    assert_eq!(Histogram::from_samples(&replicates, "A", 0.0, 5).unwrap().counts, [4]);
+}
+
+#[test]
+fn histograms_are_only_taken_at_sampled_points() {
+    let replicates: Vec<SamplingMonitor> = (0..3)
+        .map(|replicate| {
+            let times: Vec<f64> = (0..4).map(|point| point as f64 * 0.1).collect();
+
+            SamplingMonitor::from_samples(times, vec!["A".to_string()], (0..4).map(|point| vec![replicate * point]).collect())
+        })
+        .collect();
+
+    // 0.1 + 0.2 is not 0.3 in floating point, but is the same grid point
+    assert_eq!(Histogram::from_samples(&replicates, "A", 0.1 + 0.2, 1).unwrap().counts, [1, 0, 0, 1, 0, 0, 1]);
+    assert!(Histogram::from_samples(&replicates, "A", 0.15, 1).is_none());
+    assert!(Histogram::from_samples(&replicates, "B", 0.1, 1).is_none());
+    assert!(Histogram::from_samples(&[], "A", 0.1, 1).is_none());
+}
+
+#[test]
+fn heatmap_columns_share_their_bins() {
+    let replicates = [replicate([0, 10], [5, 5]), replicate([0, 20], [5, 7]), replicate([3, 30], [5, 9]), replicate([0, 40], [5, 11])];
+    let heatmap = DistributionHeatmap::from_samples(&replicates, "A", 10).unwrap();
+
+    assert_eq!(heatmap.times, [0.0, 1.0]);
+    assert_eq!((heatmap.low, heatmap.width), (0, 10));
+    assert_eq!(heatmap.counts, [vec![4, 0, 0, 0, 0], vec![0, 1, 1, 1, 1]]);
+    assert_eq!(heatmap.probabilities()[1], [0.0, 0.25, 0.25, 0.25, 0.25]);
+    assert_eq!(heatmap.bin_start(4), 40);
+
+    // Every column is the histogram at its grid point
+    for (point, &time) in heatmap.times.iter().enumerate() {
+        let histogram = Histogram::from_samples(&replicates, "A", time, 10).unwrap();
+        let bins = (histogram.low - heatmap.low) as usize / 10;
+
+        assert_eq!(heatmap.counts[point][bins..bins + histogram.counts.len()], histogram.counts[..]);
+    }
+
+    assert!(DistributionHeatmap::from_samples(&replicates, "C", 10).is_none());
+}
+
+#[test]
+#[should_panic(expected = "different grid")]
+fn replicates_have_to_share_their_grid() {
+    let other = SamplingMonitor::from_samples(vec![0.0, 2.0], vec!["A".to_string(), "B".to_string()], vec![vec![0, 0], vec![0, 0]]);
+
+    Histogram::from_samples(&[replicate([0, 0], [0, 0]), other], "A", 0.0, 1);
+}
@@ -0,0 +1,123 @@
+use std::time::Duration;
+use rand::SeedableRng;
+use rand_chacha::ChaCha12Rng;
+use stochastic_simulation::monitor::{DefaultMonitor, FilterableMonitor, Monitor};
+use stochastic_simulation::reaction::{Reaction, SpeciesRole};
+use stochastic_simulation::simulation::{Event, Simulation};
+use stochastic_simulation::species::species_builder;
+use stochastic_simulation::stopping::{StopConditions, StopReason};
+use stochastic_simulation::system::{Algorithm, ChemicalSystem};
+
+// A -> B until A runs out
+fn conversion() -> ChemicalSystem {
+    let a = species_builder("A", 50);
+    let b = species_builder("B", 0);
+
+    ChemicalSystem::new(vec![Reaction::new(vec![a], vec![b], 1.0)])
+}
+
+// Every event of a run from `seed`, stepped by hand
+fn events(seed: u64) -> Vec<Event> {
+    let mut system = conversion();
+    let mut visitor = Algorithm::Direct.visitor();
+    let mut rng = ChaCha12Rng::seed_from_u64(seed);
+
+    Simulation::new(&mut system, visitor.as_mut(), &mut rng).collect()
+}
+
+// Every recorded state, whether it changed or not
+#[derive(Default)]
+struct States(Vec<(f64, Vec<i32>)>);
+
+impl Monitor<ChemicalSystem> for States {
+    fn record_state(&mut self, time: f64, system: &ChemicalSystem) {
+        self.0.push((time, system.quantities().to_vec()));
+    }
+}
+
+impl FilterableMonitor<ChemicalSystem> for States {
+    fn record_state_with_filter(&mut self, time: f64, system: &ChemicalSystem, _species_to_record: &[(&str, SpeciesRole)]) {
+        self.record_state(time, system);
+    }
+}
+
+#[test]
+fn max_steps_stops_after_that_many_events() {
+    let events = events(4);
+    let mut system = conversion();
+    let mut monitor = States::default();
+    let summary = system.simulate_until(&StopConditions::at(f64::INFINITY).max_steps(20), &mut ChaCha12Rng::seed_from_u64(4), &mut monitor, &[]);
+
+    assert_eq!((summary.reason, summary.steps), (StopReason::MaxSteps, 20));
+    assert_eq!(summary.time, events[19].time);
+    assert_eq!(system.quantities(), events[19].quantities);
+    // The initial state and one state per event
+    assert_eq!(monitor.0.len(), 21);
+}
+
+#[test]
+fn predicates_stop_at_the_first_event_they_hold_after() {
+    let events = events(6);
+    let first = events.iter().position(|event| event.quantities[1] >= 10).unwrap();
+    let mut system = conversion();
+    let conditions = StopConditions::at(f64::INFINITY)
+        .stop_when(|system| system.quantity("A") == Some(0))
+        .stop_when(|system| system.quantity("B").unwrap() >= 10)
+        .max_steps(10);
+    let summary = system.simulate_until(&conditions, &mut ChaCha12Rng::seed_from_u64(6), &mut DefaultMonitor::new(), &[]);
+
+    // The predicate is checked before the step limit, which the same step reaches
+    assert_eq!(summary.reason, StopReason::Condition(1));
+    assert_eq!((summary.steps, summary.time), (first as u64 + 1, events[first].time));
+    assert_eq!(system.quantity("B"), Some(10));
+
+    // Conditions that hold from the start stop the run before any event, the first one winning
+    let summary = conversion().simulate_until(&StopConditions::at(1.0).stop_when(|_| true).stop_when(|_| true),
+                                              &mut ChaCha12Rng::seed_from_u64(6), &mut DefaultMonitor::new(), &[]);
+
+    assert_eq!((summary.reason, summary.steps, summary.time), (StopReason::Condition(0), 0, 0.0));
+}
+
+#[test]
+fn runs_that_run_out_of_reactions_are_absorbed() {
+    let events = events(8);
+    let mut system = conversion();
+    let summary = system.simulate(f64::INFINITY, &mut ChaCha12Rng::seed_from_u64(8), &mut DefaultMonitor::new(), &[]);
+
+    assert_eq!((summary.reason, summary.steps), (StopReason::Absorbed, 50));
+    assert_eq!(summary.time, events.last().unwrap().time);
+    assert_eq!(system.quantities(), [0, 50]);
+
+    // With a finite end time the final state holds until then, and is recorded there
+    let mut monitor = States::default();
+    let summary = conversion().simulate(1000.0, &mut ChaCha12Rng::seed_from_u64(8), &mut monitor, &[]);
+
+    assert_eq!((summary.reason, summary.time), (StopReason::Absorbed, 1000.0));
+    assert_eq!(monitor.0.last(), Some(&(1000.0, vec![0, 50])));
+}
+
+#[test]
+fn end_time_keeps_the_state_of_the_last_event_before_it() {
+    let events = events(2);
+    let end_time = events[25].time + (events[26].time - events[25].time) / 2.0;
+    let mut system = conversion();
+    let mut monitor = States::default();
+    let summary = system.simulate(end_time, &mut ChaCha12Rng::seed_from_u64(2), &mut monitor, &[]);
+
+    assert_eq!((summary.reason, summary.steps, summary.time), (StopReason::EndTime, 26, end_time));
+    assert_eq!(monitor.0.last(), Some(&(end_time, events[25].quantities.clone())));
+    assert_eq!(monitor.0.len(), 1 + 26 + 1);
+}
+
+#[test]
+fn wall_clock_budget_stops_the_run() {
+    let summary = conversion().simulate_until(&StopConditions::at(f64::INFINITY).wall_clock(Duration::ZERO),
+                                              &mut ChaCha12Rng::seed_from_u64(1), &mut DefaultMonitor::new(), &[]);
+
+    assert_eq!((summary.reason, summary.steps), (StopReason::WallClock, 0));
+
+    let summary = conversion().simulate_until(&StopConditions::at(f64::INFINITY).wall_clock(Duration::from_secs(60)),
+                                              &mut ChaCha12Rng::seed_from_u64(1), &mut DefaultMonitor::new(), &[]);
+
+    assert_eq!(summary.reason, StopReason::Absorbed);
+}
@@ -0,0 +1,127 @@
+use rand::SeedableRng;
+use rand_chacha::ChaCha12Rng;
+use stochastic_simulation::monitor::DefaultMonitor;
+use stochastic_simulation::reaction::Reaction;
+use stochastic_simulation::simulation::Simulation;
+use stochastic_simulation::species::species_builder;
+use stochastic_simulation::system::{Algorithm, ChemicalSystem};
+use stochastic_simulation::tau_leaping::{TauLeapingOptions, TauLeapingVisitor};
+use stochastic_simulation::visitor::Visitor;
+
+// Production at rate 100 and degradation at rate 1 per molecule, around 100 molecules of A
+fn birth_death(initial: i32) -> ChemicalSystem {
+    let a = species_builder("A", initial);
+
+    ChemicalSystem::new(vec![
+        Reaction::new(vec![], vec![a.clone()], 100.0),
+        Reaction::new(vec![a], vec![], 1.0)
+    ])
+}
+
+fn final_quantities(system: &ChemicalSystem, visitor: &mut dyn Visitor, seeds: std::ops::Range<u64>, end_time: f64) -> Vec<f64> {
+    seeds.map(|seed| {
+        let mut replicate = system.clone();
+        let mut rng = ChaCha12Rng::seed_from_u64(seed);
+
+        replicate.simulation(end_time, visitor, &mut rng, &mut DefaultMonitor::new(), &[]);
+        replicate.quantity("A").unwrap() as f64
+    }).collect()
+}
+
+fn mean_and_variance(samples: &[f64]) -> (f64, f64) {
+    let mean = samples.iter().sum::<f64>() / samples.len() as f64;
+    let variance = samples.iter().map(|x| (x - mean) * (x - mean)).sum::<f64>() / (samples.len() - 1) as f64;
+
+    (mean, variance)
+}
+
+#[test]
+fn large_system_leaps_over_many_firings() {
+    let a = species_builder("A", 1_000_000);
+    let b = species_builder("B", 0);
+    let mut system = ChemicalSystem::new(vec![Reaction::new(vec![a], vec![b], 1.0)]);
+    let mut visitor = TauLeapingVisitor::new(TauLeapingOptions::default());
+    let mut rng = ChaCha12Rng::seed_from_u64(3);
+    let mut simulation = Simulation::new(&mut system, &mut visitor, &mut rng);
+
+    simulation.step_until(0.5);
+
+    let steps = simulation.steps();
+    let firings = 1_000_000 - simulation.system().quantity("A").unwrap() as u64;
+
+    assert!(firings > 300_000, "only {} firings", firings);
+    assert!(steps * 100 < firings, "{} steps for {} firings", steps, firings);
+}
+
+#[test]
+fn fixed_tau_is_leaped_even_when_short() {
+    // A total propensity of 10 makes a tau of 0.01 a tenth of an expected event, far below
+    // the threshold at which adaptive leaping falls back to exact steps
+    let a = species_builder("A", 1000);
+    let b = species_builder("B", 0);
+    let mut system = ChemicalSystem::new(vec![Reaction::new(vec![a], vec![b], 0.01)]);
+    let mut visitor = TauLeapingVisitor::new(TauLeapingOptions { fixed_tau: Some(0.01), ..TauLeapingOptions::default() });
+    let mut rng = ChaCha12Rng::seed_from_u64(5);
+    let mut simulation = Simulation::new(&mut system, &mut visitor, &mut rng);
+
+    let mut previous_time = 0.0;
+
+    for _ in 0..100 {
+        let step = simulation.step().unwrap();
+
+        assert!((step.time() - previous_time - 0.01).abs() < 1e-12, "step from {} to {}", previous_time, step.time());
+        previous_time = step.time();
+    }
+}
+
+#[test]
+fn adaptive_and_fixed_tau_agree_with_the_direct_method() {
+    let system = birth_death(0);
+    let end_time = 5.0;
+
+    let direct = final_quantities(&system, Algorithm::Direct.visitor().as_mut(), 0..400, end_time);
+    let adaptive = final_quantities(&system, &mut TauLeapingVisitor::new(TauLeapingOptions::default()), 1000..1400, end_time);
+    let fixed = final_quantities(&system,
+                                 &mut TauLeapingVisitor::new(TauLeapingOptions { fixed_tau: Some(0.05), ..TauLeapingOptions::default() }),
+                                 2000..2400,
+                                 end_time);
+
+    // The exact distribution at t = 5 is Poisson with mean 100 (1 - e^-5), about 99.3
+    let (direct_mean, direct_variance) = mean_and_variance(&direct);
+
+    for (name, samples) in [("adaptive", &adaptive), ("fixed", &fixed)] {
+        let (mean, variance) = mean_and_variance(samples);
+
+        // The standard error of the difference of the means is about 0.7
+        assert!((mean - direct_mean).abs() < 3.0, "{} mean {} against {}", name, mean, direct_mean);
+        assert!((variance / direct_variance - 1.0).abs() < 0.3, "{} variance {} against {}", name, variance, direct_variance);
+    }
+}
+
+#[test]
+fn critical_reactions_never_drive_species_negative() {
+    // Few molecules and a leap long enough for many firings of every reaction
+    let a = species_builder("A", 12);
+    let b = species_builder("B", 8);
+    let c = species_builder("C", 0);
+    let system = ChemicalSystem::new(vec![
+        Reaction::new(vec![a.clone(), b.clone()], vec![c.clone()], 5.0),
+        Reaction::with_coefficients(vec![(a.clone(), 2)], vec![], 3.0),
+        Reaction::new(vec![c.clone()], vec![a, b], 0.5)
+    ]);
+
+    for fixed_tau in [None, Some(1.0)] {
+        let mut visitor = TauLeapingVisitor::new(TauLeapingOptions { fixed_tau, ..TauLeapingOptions::default() });
+
+        for seed in 0..50 {
+            let mut replicate = system.clone();
+            let mut rng = ChaCha12Rng::seed_from_u64(seed);
+            let simulation = Simulation::new(&mut replicate, &mut visitor, &mut rng);
+
+            for event in simulation.take(200) {
+                assert!(event.quantities.iter().all(|&quantity| quantity >= 0),
+                        "{:?} at {} with tau {:?} and seed {}", event.quantities, event.time, fixed_tau, seed);
+            }
+        }
+    }
+}